@@ -2,7 +2,9 @@ use crate::{
     component::{base_route::BaseRoute, logout::Logout, nav::NavBar},
     error_template::{AppError, ErrorTemplate},
     page::{
+        account_recovery::AccountRecovery,
         account_transfer::AccountTransfer,
+        devices::Devices,
         err::ServerErrorPage,
         leaderboard::Leaderboard,
         menu::{AuthorizedUserToSeedContent, Menu},
@@ -15,7 +17,12 @@ use crate::{
         upload::UploadPostPage,
         wallet::{transactions::Transactions, Wallet},
     },
-    state::{canisters::Canisters, content_seed_client::ContentSeedClient, history::HistoryCtx},
+    state::{
+        canisters::Canisters,
+        content_seed_client::ContentSeedClient,
+        history::HistoryCtx,
+        network::{network, NetworkConfig, NETWORK_META},
+    },
     utils::event_streaming::EventHistory,
 };
 use leptos::*;
@@ -30,12 +37,11 @@ fn NotFound() -> impl IntoView {
 }
 
 #[component(transparent)]
-fn GoogleAuthRedirectHandlerRoute() -> impl IntoView {
-    let path = "/auth/google_redirect";
+fn OidcAuthRedirectHandlerRoute(#[prop(into)] path: String) -> impl IntoView {
     #[cfg(any(feature = "oauth-ssr", feature = "oauth-hydrate"))]
     {
-        use crate::page::google_redirect::GoogleRedirectHandler;
-        view! { <Route path view=GoogleRedirectHandler/> }
+        use crate::page::oidc_redirect::OidcRedirectHandler;
+        view! { <Route path view=OidcRedirectHandler/> }
     }
     #[cfg(not(any(feature = "oauth-ssr", feature = "oauth-hydrate")))]
     {
@@ -44,12 +50,12 @@ fn GoogleAuthRedirectHandlerRoute() -> impl IntoView {
 }
 
 #[component(transparent)]
-fn GoogleAuthRedirectorRoute() -> impl IntoView {
-    let path = "/auth/perform_google_redirect";
+fn OidcAuthRedirectorRoute() -> impl IntoView {
+    let path = "/auth/:provider/perform_redirect";
     #[cfg(any(feature = "oauth-ssr", feature = "oauth-hydrate"))]
     {
-        use crate::page::google_redirect::GoogleRedirector;
-        view! { <Route path view=GoogleRedirector/> }
+        use crate::page::oidc_redirect::OidcRedirector;
+        view! { <Route path view=OidcRedirector/> }
     }
     #[cfg(not(any(feature = "oauth-ssr", feature = "oauth-hydrate")))]
     {
@@ -57,11 +63,42 @@ fn GoogleAuthRedirectorRoute() -> impl IntoView {
     }
 }
 
+#[component(transparent)]
+fn LoginMethodsRoute() -> impl IntoView {
+    let path = "/login-methods";
+    #[cfg(any(feature = "oauth-ssr", feature = "oauth-hydrate"))]
+    {
+        use crate::page::login_methods::LoginMethods;
+        view! { <Route path view=LoginMethods/> }
+    }
+    #[cfg(not(any(feature = "oauth-ssr", feature = "oauth-hydrate")))]
+    {
+        view! { <Route path view=NotFound/> }
+    }
+}
+
+#[component(transparent)]
+fn EmailLoginVerifyRoute() -> impl IntoView {
+    let path = "/auth/email/verify";
+    #[cfg(any(feature = "email-ssr", feature = "email-hydrate"))]
+    {
+        use crate::page::email_login::EmailLoginVerify;
+        view! { <Route path view=EmailLoginVerify/> }
+    }
+    #[cfg(not(any(feature = "email-ssr", feature = "email-hydrate")))]
+    {
+        view! { <Route path view=NotFound/> }
+    }
+}
+
 #[component]
 pub fn App() -> impl IntoView {
     // Provides context that manages stylesheets, titles, meta tags, etc.
     provide_meta_context();
     provide_context(Canisters::default());
+    // the server's network, read by the client before hydrating
+    let server_network = use_context::<NetworkConfig>().unwrap_or_else(|| network().clone());
+    let network_json = serde_json::to_string(&server_network).unwrap();
     provide_context(ContentSeedClient::default());
     provide_context(PostViewCtx::default());
     provide_context(ProfilePostsContext::default());
@@ -91,6 +128,7 @@ pub fn App() -> impl IntoView {
         <Title text="Yral"/>
 
         <Link rel="manifest" href="/app.webmanifest"/>
+        <Meta name=NETWORK_META content=network_json/>
 
         // GA4 Global Site Tag (gtag.js) - Google Analytics
         // G-6W5Q2MRX0E to test locally | G-PLNNETMSLM
@@ -114,8 +152,10 @@ pub fn App() -> impl IntoView {
             <main>
                 <Routes>
                     // auth redirect routes exist outside main context
-                    <GoogleAuthRedirectHandlerRoute/>
-                    <GoogleAuthRedirectorRoute/>
+                    <OidcAuthRedirectHandlerRoute path="/auth/:provider/redirect"/>
+                    // legacy Google redirect url, still registered with Google
+                    <OidcAuthRedirectHandlerRoute path="/auth/google_redirect"/>
+                    <OidcAuthRedirectorRoute/>
                     <Route path="" view=BaseRoute>
                         <Route path="/hot-or-not/:canister_id/:post_id" view=PostView/>
                         <Route path="/profile/:canister_id/:post_id" view=ProfilePost/>
@@ -132,6 +172,10 @@ pub fn App() -> impl IntoView {
                         <Route path="/transactions" view=Transactions/>
                         <Route path="/leaderboard" view=Leaderboard/>
                         <Route path="/account-transfer" view=AccountTransfer/>
+                        <LoginMethodsRoute/>
+                        <Route path="/devices" view=Devices/>
+                        <Route path="/account-recovery" view=AccountRecovery/>
+                        <EmailLoginVerifyRoute/>
                         <Route path="/logout" view=Logout/>
                         <Route path="" view=RootPage/>
                     </Route>
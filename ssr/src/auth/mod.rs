@@ -1,5 +1,7 @@
 #[cfg(feature = "ssr")]
 pub mod server_impl;
+#[cfg(test)]
+mod tests;
 
 use candid::Principal;
 use ic_agent::{
@@ -10,12 +12,35 @@ use k256::elliptic_curve::JwkEcKey;
 use leptos::{server, ServerFnError};
 use rand_chacha::rand_core::OsRng;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use web_time::Duration;
 
 use crate::utils::current_epoch;
 
+/// Version of [DelegatedIdentityWire]'s serialized form
+const WIRE_VERSION: u32 = 1;
+/// Longest delegation chain accepted over the wire
+const MAX_DELEGATION_CHAIN_LEN: usize = 4;
+
+#[derive(Debug, Error)]
+pub enum DelegationError {
+    #[error("unsupported delegated identity version {0}")]
+    UnsupportedVersion(u32),
+    #[error("delegation chain is empty")]
+    EmptyChain,
+    #[error("delegation chain is too long ({0} delegations)")]
+    ChainTooLong(usize),
+    #[error("delegation has expired")]
+    Expired,
+    #[error("invalid delegated secret key: {0}")]
+    InvalidSecret(#[from] k256::elliptic_curve::Error),
+    #[error("delegated public key does not match the secret key")]
+    KeyMismatch,
+}
+
 /// Delegated identity that can be serialized over the wire
 #[derive(Serialize, Deserialize, Clone)]
+#[serde(try_from = "WireEnvelope", into = "WireEnvelope")]
 pub struct DelegatedIdentityWire {
     /// raw bytes of delegated identity's public key
     from_key: Vec<u8>,
@@ -27,6 +52,66 @@ pub struct DelegatedIdentityWire {
     delegation_chain: Vec<SignedDelegation>,
 }
 
+/// Layout of version 1
+#[derive(Serialize, Deserialize)]
+struct WireV1 {
+    from_key: Vec<u8>,
+    to_secret: JwkEcKey,
+    delegation_chain: Vec<SignedDelegation>,
+}
+
+/// Serialized form of [DelegatedIdentityWire]
+/// the fields are flattened so consumers of the unversioned layout keep working
+#[derive(Serialize, Deserialize)]
+struct WireEnvelope {
+    /// missing in payloads from before versioning, which use the version 1 layout
+    #[serde(default = "legacy_wire_version")]
+    version: u32,
+    #[serde(flatten)]
+    identity: WireV1,
+}
+
+fn legacy_wire_version() -> u32 {
+    1
+}
+
+impl From<DelegatedIdentityWire> for WireEnvelope {
+    fn from(wire: DelegatedIdentityWire) -> Self {
+        Self {
+            version: WIRE_VERSION,
+            identity: WireV1 {
+                from_key: wire.from_key,
+                to_secret: wire.to_secret,
+                delegation_chain: wire.delegation_chain,
+            },
+        }
+    }
+}
+
+impl TryFrom<WireEnvelope> for DelegatedIdentityWire {
+    type Error = DelegationError;
+
+    fn try_from(envelope: WireEnvelope) -> Result<Self, Self::Error> {
+        if envelope.version != WIRE_VERSION {
+            return Err(DelegationError::UnsupportedVersion(envelope.version));
+        }
+        let WireV1 {
+            from_key,
+            to_secret,
+            delegation_chain,
+        } = envelope.identity;
+        let wire = Self {
+            from_key,
+            to_secret,
+            delegation_chain,
+        };
+        // expiry is checked by `validate` so callers get `DelegationError::Expired`
+        wire.validate_chain()?;
+
+        Ok(wire)
+    }
+}
+
 impl DelegatedIdentityWire {
     pub fn delegate_short_lived_identity(from: &impl Identity) -> Self {
         let to_secret = k256::SecretKey::random(&mut OsRng);
@@ -55,22 +140,100 @@ impl DelegatedIdentityWire {
     }
 }
 
+impl DelegatedIdentityWire {
+    /// Expiry of the chain, the earliest expiry of its delegations
+    pub fn expiry_ns(&self) -> u64 {
+        self.delegation_chain
+            .iter()
+            .map(|signed| signed.delegation.expiration)
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Check that the chain is well formed and ends at `to_secret`, regardless of expiry
+    fn validate_chain(&self) -> Result<(), DelegationError> {
+        let Some(last) = self.delegation_chain.last() else {
+            return Err(DelegationError::EmptyChain);
+        };
+        if self.delegation_chain.len() > MAX_DELEGATION_CHAIN_LEN {
+            return Err(DelegationError::ChainTooLong(self.delegation_chain.len()));
+        }
+
+        let to_secret = k256::SecretKey::from_jwk(&self.to_secret)?;
+        let to_public = Secp256k1Identity::from_private_key(to_secret).public_key();
+        if to_public.as_ref() != Some(&last.delegation.pubkey) {
+            return Err(DelegationError::KeyMismatch);
+        }
+
+        Ok(())
+    }
+
+    /// Check that the chain is usable and ends at `to_secret`
+    pub fn validate(&self) -> Result<(), DelegationError> {
+        self.validate_chain()?;
+        if self.expiry_ns() <= current_epoch().as_nanos() as u64 {
+            return Err(DelegationError::Expired);
+        }
+        Ok(())
+    }
+}
+
 impl std::fmt::Debug for DelegatedIdentityWire {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("DelegatedIdentityWire").finish()
     }
 }
 
-#[derive(Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub struct RefreshToken {
     principal: Principal,
     expiry_epoch_ms: u128,
+    /// Server side session, missing in tokens issued before sessions were tracked
+    #[serde(default)]
+    session_id: Option<String>,
+}
+
+/// A device logged into the current principal
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SessionInfo {
+    pub id: String,
+    /// Short description of the device, e.g `Chrome on Android`
+    pub device: String,
+    pub created_at_ms: u64,
+    pub last_used_ms: u64,
+    /// Session of the device making the request
+    pub current: bool,
+}
+
+/// Activity of a guest account that would be lost by logging into another account
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct GuestActivity {
+    /// COYN balance
+    pub balance: u64,
+    pub has_bets: bool,
+    pub has_posts: bool,
+}
+
+impl GuestActivity {
+    pub fn is_empty(&self) -> bool {
+        self.balance == 0 && !self.has_bets && !self.has_posts
+    }
+}
+
+/// Result of logging in with an external provider
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum LoginOutcome {
+    LoggedIn(DelegatedIdentityWire),
+    /// The provider is linked to another account and the current guest account has activity
+    /// the login is deferred until the user decides what to do with the guest account
+    GuestHasActivity(GuestActivity),
 }
 
 impl TryFrom<DelegatedIdentityWire> for DelegatedIdentity {
-    type Error = k256::elliptic_curve::Error;
+    type Error = DelegationError;
 
     fn try_from(identity: DelegatedIdentityWire) -> Result<Self, Self::Error> {
+        identity.validate()?;
         let to_secret = k256::SecretKey::from_jwk(&identity.to_secret)?;
         let to_identity = Secp256k1Identity::from_private_key(to_secret);
         Ok(Self::new(
@@ -86,7 +249,40 @@ pub async fn extract_or_generate_identity() -> Result<DelegatedIdentityWire, Ser
     server_impl::extract_or_generate_identity_impl().await
 }
 
+/// Fresh delegation for the current refresh token
+/// `None` if it has expired or its session was revoked, no identity is generated then
+#[server]
+pub async fn renew_identity() -> Result<Option<DelegatedIdentityWire>, ServerFnError> {
+    server_impl::renew_identity_impl().await
+}
+
 #[server]
 pub async fn logout_identity() -> Result<DelegatedIdentityWire, ServerFnError> {
     server_impl::logout_identity_impl().await
 }
+
+/// Recovery phrase of the current base identity
+#[server]
+pub async fn recovery_phrase() -> Result<String, ServerFnError> {
+    server_impl::recovery_phrase_impl().await
+}
+
+#[server]
+pub async fn recover_identity(phrase: String) -> Result<DelegatedIdentityWire, ServerFnError> {
+    server_impl::recover_identity_impl(phrase).await
+}
+
+#[server]
+pub async fn active_sessions() -> Result<Vec<SessionInfo>, ServerFnError> {
+    server_impl::active_sessions_impl().await
+}
+
+#[server]
+pub async fn revoke_session(session_id: String) -> Result<(), ServerFnError> {
+    server_impl::revoke_session_impl(session_id).await
+}
+
+#[server]
+pub async fn revoke_other_sessions() -> Result<(), ServerFnError> {
+    server_impl::revoke_other_sessions_impl().await
+}
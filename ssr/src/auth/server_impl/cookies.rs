@@ -0,0 +1,173 @@
+//! Cookie jars with support for key rotation
+//!
+//! Cookies are signed or encrypted with the primary key.
+//! Cookies using a retired key are still accepted, and re-issued with the primary key on the same response
+
+#[cfg(test)]
+mod tests;
+
+use std::{collections::HashSet, iter, sync::Arc};
+
+use axum::response::IntoResponse;
+use axum_extra::extract::{
+    cookie::{Cookie, Key, SameSite},
+    PrivateCookieJar, SignedCookieJar,
+};
+use candid::Principal;
+use http::{header, HeaderMap, HeaderValue};
+use leptos::{expect_context, ServerFnError};
+use leptos_axum::{extract, ResponseOptions};
+
+use crate::{
+    auth::RefreshToken,
+    consts::auth::{FLOW_COOKIE_MAX_AGE, REFRESH_TOKEN_COOKIE},
+};
+
+use super::{refresh_cookie, store::KVStoreImpl, verify_refresh_token};
+
+#[derive(Clone)]
+pub struct CookieKeys {
+    primary: Key,
+    retired: Arc<[Key]>,
+}
+
+impl CookieKeys {
+    /// `primary` signs new cookies, `retired` keys are only used to verify
+    pub fn new(primary: Key, retired: Vec<Key>) -> Self {
+        Self {
+            primary,
+            retired: retired.into(),
+        }
+    }
+
+    /// Principal of the request's refresh token, if it's neither expired nor revoked
+    /// for use outside server functions, e.g in middleware
+    pub async fn refresh_token_principal(
+        &self,
+        headers: &HeaderMap,
+        kv: &KVStoreImpl,
+    ) -> Result<Option<Principal>, ServerFnError> {
+        let jar = iter::once(&self.primary)
+            .chain(self.retired.iter())
+            .map(|key| SignedCookieJar::from_headers(headers, key.clone()))
+            .find(|jar| jar.get(REFRESH_TOKEN_COOKIE).is_some());
+        let Some(jar) = jar else {
+            return Ok(None);
+        };
+        Ok(verify_refresh_token(&jar, kv)
+            .await?
+            .map(|token| token.principal))
+    }
+}
+
+trait KeyedJar: IntoResponse + Sized {
+    fn from_headers(headers: &HeaderMap, key: Key) -> Self;
+
+    fn cookies(&self) -> Vec<Cookie<'static>>;
+
+    fn add(self, cookie: Cookie<'static>) -> Self;
+}
+
+macro_rules! impl_keyed_jar {
+    ($jar:ty) => {
+        impl KeyedJar for $jar {
+            fn from_headers(headers: &HeaderMap, key: Key) -> Self {
+                <$jar>::from_headers(headers, key)
+            }
+
+            fn cookies(&self) -> Vec<Cookie<'static>> {
+                self.iter().collect()
+            }
+
+            fn add(self, cookie: Cookie<'static>) -> Self {
+                <$jar>::add(self, cookie)
+            }
+        }
+    };
+}
+
+impl_keyed_jar!(SignedCookieJar);
+impl_keyed_jar!(PrivateCookieJar);
+
+/// Cookie kept during a login flow, every cookie but the refresh token is one
+pub fn flow_cookie(name: impl Into<String>, value: impl Into<String>) -> Cookie<'static> {
+    Cookie::build((name.into(), value.into()))
+        .same_site(SameSite::None)
+        .path("/")
+        .max_age(FLOW_COOKIE_MAX_AGE.try_into().unwrap())
+        .build()
+}
+
+/// `cookie` to be signed with the primary key
+/// browsers don't send attributes back, the refresh token's follow from its value
+/// and flow cookies are given a full [FLOW_COOKIE_MAX_AGE] again
+fn reissued(cookie: &Cookie<'static>) -> Cookie<'static> {
+    if cookie.name() == REFRESH_TOKEN_COOKIE {
+        if let Ok(token) = serde_json::from_str::<RefreshToken>(cookie.value()) {
+            return refresh_cookie(&token);
+        }
+    }
+    flow_cookie(cookie.name(), cookie.value())
+}
+
+async fn extract_jar<J: KeyedJar>() -> Result<J, ServerFnError> {
+    let keys: CookieKeys = expect_context();
+    let mut headers: HeaderMap = extract().await?;
+    let jar = J::from_headers(&headers, keys.primary.clone());
+
+    let mut verified: HashSet<_> = jar
+        .cookies()
+        .into_iter()
+        .map(|cookie| cookie.name().to_owned())
+        .collect();
+    let mut readback = J::from_headers(&HeaderMap::new(), keys.primary.clone());
+    let mut rotated = J::from_headers(&HeaderMap::new(), keys.primary.clone());
+    let mut has_retired = false;
+    for key in keys.retired.iter() {
+        for cookie in J::from_headers(&headers, key.clone()).cookies() {
+            if !verified.insert(cookie.name().to_owned()) {
+                continue;
+            }
+            rotated = rotated.add(reissued(&cookie));
+            readback = readback.add(Cookie::new(
+                cookie.name().to_owned(),
+                cookie.value().to_owned(),
+            ));
+            has_retired = true;
+        }
+    }
+    if !has_retired {
+        return Ok(jar);
+    }
+
+    let resp: ResponseOptions = expect_context();
+    for set_cookie in rotated
+        .into_response()
+        .headers()
+        .get_all(header::SET_COOKIE)
+    {
+        resp.append_header(header::SET_COOKIE, set_cookie.clone());
+    }
+    // Read the cookies back as if the request carried them signed with the primary key
+    // cookies added later take precedence over the old values
+    for set_cookie in readback
+        .into_response()
+        .headers()
+        .get_all(header::SET_COOKIE)
+    {
+        let name_value = set_cookie.to_str()?.split(';').next().unwrap_or_default();
+        headers.append(header::COOKIE, HeaderValue::from_str(name_value)?);
+    }
+
+    Ok(J::from_headers(&headers, keys.primary))
+}
+
+/// Signed cookies of the current request
+pub async fn signed_jar() -> Result<SignedCookieJar, ServerFnError> {
+    extract_jar().await
+}
+
+/// Encrypted cookies of the current request
+pub async fn private_jar() -> Result<PrivateCookieJar, ServerFnError> {
+    extract_jar().await
+}
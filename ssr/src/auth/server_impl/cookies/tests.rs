@@ -0,0 +1,132 @@
+use std::future::Future;
+
+use axum::response::IntoResponse;
+use axum_extra::extract::{
+    cookie::{Cookie, Key},
+    PrivateCookieJar, SignedCookieJar,
+};
+use http::{header, HeaderMap, Request};
+use leptos::{create_runtime, provide_context};
+use leptos_axum::ResponseOptions;
+
+use crate::consts::auth::FLOW_COOKIE_MAX_AGE;
+
+use super::{private_jar, signed_jar, CookieKeys, KeyedJar};
+
+fn key(byte: u8) -> Key {
+    Key::from(&[byte; 64])
+}
+
+/// `Cookie` header with `cookies` as `J` would send them, signed or encrypted with `key`
+fn cookie_header<J: KeyedJar>(key: &Key, cookies: &[(&'static str, &'static str)]) -> String {
+    let mut jar = J::from_headers(&HeaderMap::new(), key.clone());
+    for (name, value) in cookies {
+        jar = jar.add(Cookie::new(*name, *value));
+    }
+    set_cookies(jar.into_response().headers())
+        .iter()
+        .map(|cookie| format!("{}={}", cookie.name(), cookie.value()))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn set_cookies(headers: &HeaderMap) -> Vec<Cookie<'static>> {
+    headers
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .map(|cookie| Cookie::parse(cookie.to_str().unwrap().to_string()).unwrap())
+        .collect()
+}
+
+/// Run `extract` in a request carrying `cookie`, returns its result and the cookies set
+async fn with_request<T, Fut>(
+    keys: &CookieKeys,
+    cookie: &str,
+    extract: impl FnOnce() -> Fut,
+) -> (T, Vec<Cookie<'static>>)
+where
+    Fut: Future<Output = T>,
+{
+    let runtime = create_runtime();
+    let parts = Request::builder()
+        .header(header::COOKIE, cookie)
+        .body(())
+        .unwrap()
+        .into_parts()
+        .0;
+    let resp = ResponseOptions::default();
+    provide_context(parts);
+    provide_context(resp.clone());
+    provide_context(keys.clone());
+
+    let res = extract().await;
+    runtime.dispose();
+    let cookies = set_cookies(&resp.0.read().headers);
+    (res, cookies)
+}
+
+fn rotated_keys() -> CookieKeys {
+    CookieKeys::new(key(2), vec![key(1)])
+}
+
+#[tokio::test]
+async fn signed_cookie_with_retired_key_is_reissued() {
+    let keys = rotated_keys();
+    let cookie = cookie_header::<SignedCookieJar>(&key(1), &[("pending", "a")]);
+
+    let (jar, reissued) = with_request(&keys, &cookie, signed_jar).await;
+    assert_eq!(jar.unwrap().get("pending").unwrap().value(), "a");
+    assert_eq!(reissued.len(), 1);
+    assert_eq!(reissued[0].path(), Some("/"));
+    assert_eq!(
+        reissued[0].max_age(),
+        Some(FLOW_COOKIE_MAX_AGE.try_into().unwrap())
+    );
+
+    // the re-issued cookie verifies without the retired key
+    let primary_only = CookieKeys::new(key(2), vec![]);
+    let cookie = format!("{}={}", reissued[0].name(), reissued[0].value());
+    let (jar, reissued) = with_request(&primary_only, &cookie, signed_jar).await;
+    assert_eq!(jar.unwrap().get("pending").unwrap().value(), "a");
+    assert!(reissued.is_empty());
+}
+
+#[tokio::test]
+async fn private_cookie_with_retired_key_is_reissued() {
+    let keys = rotated_keys();
+    let cookie = cookie_header::<PrivateCookieJar>(&key(1), &[("pkce", "verifier")]);
+
+    let (jar, reissued) = with_request(&keys, &cookie, private_jar).await;
+    assert_eq!(jar.unwrap().get("pkce").unwrap().value(), "verifier");
+    assert_eq!(reissued.len(), 1);
+
+    let primary_only = CookieKeys::new(key(2), vec![]);
+    let cookie = format!("{}={}", reissued[0].name(), reissued[0].value());
+    let (jar, _) = with_request(&primary_only, &cookie, private_jar).await;
+    assert_eq!(jar.unwrap().get("pkce").unwrap().value(), "verifier");
+}
+
+#[tokio::test]
+async fn cookie_with_primary_key_is_kept() {
+    let keys = rotated_keys();
+    let cookie = cookie_header::<SignedCookieJar>(&key(2), &[("pending", "a")]);
+
+    let (jar, reissued) = with_request(&keys, &cookie, signed_jar).await;
+    assert_eq!(jar.unwrap().get("pending").unwrap().value(), "a");
+    assert!(reissued.is_empty());
+}
+
+#[tokio::test]
+async fn cookie_with_unknown_key_is_rejected() {
+    let keys = rotated_keys();
+
+    let cookie = cookie_header::<SignedCookieJar>(&key(3), &[("pending", "a")]);
+    let (jar, reissued) = with_request(&keys, &cookie, signed_jar).await;
+    assert!(jar.unwrap().get("pending").is_none());
+    assert!(reissued.is_empty());
+
+    let cookie = cookie_header::<PrivateCookieJar>(&key(3), &[("pkce", "verifier")]);
+    let (jar, reissued) = with_request(&keys, &cookie, private_jar).await;
+    assert!(jar.unwrap().get("pkce").is_none());
+    assert!(reissued.is_empty());
+}
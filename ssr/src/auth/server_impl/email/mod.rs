@@ -0,0 +1,234 @@
+//! Email magic link login
+//!
+//! `email-login-token-{sha256(token)}` holds pending single use tokens,
+//! only the hash is stored so the KV store never holds a usable link.
+//! `email-login-{email}` maps an email address to a principal,
+//! `emails-{principal}` lists the addresses linked to a principal
+
+pub mod mailer;
+
+use candid::Principal;
+use ic_agent::{identity::Secp256k1Identity, Identity};
+use leptos::{expect_context, ServerFnError};
+use leptos_axum::ResponseOptions;
+use lettre::Address;
+use rand_chacha::rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use web_time::Duration;
+
+use crate::{auth::DelegatedIdentityWire, utils::current_epoch};
+
+use self::mailer::{Mailer, MailerImpl};
+
+use super::{
+    cookies, extract_refresh_token, fetch_identity_from_kv, generate_and_save_identity, guest_gc,
+    login_user_identity,
+    store::{KVStore, KVStoreImpl, KvKey, RecordKind},
+    try_extract_identity,
+};
+
+/// Login links expire after 15 minutes
+const TOKEN_MAX_AGE: Duration = Duration::from_secs(60 * 15);
+
+#[derive(Clone)]
+pub struct EmailLogin {
+    pub mailer: MailerImpl,
+    /// Base url of the site, login links point to `{link_base_url}/auth/email/verify`
+    pub link_base_url: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PendingToken {
+    email: String,
+    expiry_epoch_ms: u128,
+    /// Session that requested the link, only that session's identity is linked to a new address
+    #[serde(default)]
+    session_id: Option<String>,
+}
+
+fn token_key(token: &str) -> KvKey {
+    KvKey::new(
+        RecordKind::EmailLogin,
+        format!(
+            "email-login-token-{}",
+            hex::encode(Sha256::digest(token.as_bytes()))
+        ),
+    )
+}
+
+fn principal_lookup_key(email: &str) -> KvKey {
+    KvKey::new(RecordKind::EmailLogin, format!("email-login-{email}"))
+}
+
+fn principal_emails_key(principal: Principal) -> KvKey {
+    KvKey::new(
+        RecordKind::EmailLogin,
+        format!("emails-{}", principal.to_text()),
+    )
+}
+
+async fn read_linked_emails(
+    kv: &KVStoreImpl,
+    principal: Principal,
+) -> Result<Vec<String>, ServerFnError> {
+    let Some(raw) = kv.read(principal_emails_key(principal)).await? else {
+        return Ok(vec![]);
+    };
+    Ok(serde_json::from_str(&raw)?)
+}
+
+/// Link `email` to `principal`
+async fn link_email(
+    kv: &KVStoreImpl,
+    principal: Principal,
+    email: &str,
+) -> Result<(), ServerFnError> {
+    // reverse index first, so a linked address is always listed
+    let mut emails = read_linked_emails(kv, principal).await?;
+    if !emails.iter().any(|linked| linked == email) {
+        emails.push(email.to_string());
+        kv.write(
+            principal_emails_key(principal),
+            serde_json::to_string(&emails)?,
+        )
+        .await?;
+    }
+    kv.write(principal_lookup_key(email), principal.to_text())
+        .await?;
+    guest_gc::mark_linked(kv, principal).await
+}
+
+/// Number of email addresses linked to `principal`
+/// listed addresses that now point to another principal aren't counted
+pub async fn linked_email_count(
+    kv: &KVStoreImpl,
+    principal: Principal,
+) -> Result<usize, ServerFnError> {
+    let emails = read_linked_emails(kv, principal).await?;
+    if emails.is_empty() {
+        return Ok(0);
+    }
+    let principal_text = principal.to_text();
+    let linked = kv
+        .read_many(
+            emails
+                .iter()
+                .map(|email| principal_lookup_key(email))
+                .collect(),
+        )
+        .await?;
+    Ok(linked
+        .into_iter()
+        .filter(|linked| linked.as_deref() == Some(principal_text.as_str()))
+        .count())
+}
+
+fn normalize_email(email: &str) -> Result<String, ServerFnError> {
+    let email = email.trim().to_lowercase();
+    email
+        .parse::<Address>()
+        .map_err(|_| ServerFnError::new("Invalid email address"))?;
+    Ok(email)
+}
+
+/// Email a single use login link to `email`
+pub async fn send_login_link_impl(email: String) -> Result<(), ServerFnError> {
+    let email_login: EmailLogin = expect_context();
+    let kv: KVStoreImpl = expect_context();
+    let email = normalize_email(&email)?;
+    let jar = cookies::signed_jar().await?;
+    let session_id = extract_refresh_token(&jar, &kv)
+        .await?
+        .and_then(|token| token.session_id);
+
+    let mut raw = [0u8; 32];
+    OsRng.fill_bytes(&mut raw);
+    let token = hex::encode(raw);
+    let pending = PendingToken {
+        email: email.clone(),
+        expiry_epoch_ms: (current_epoch() + TOKEN_MAX_AGE).as_millis(),
+        session_id,
+    };
+    kv.write_with_ttl(
+        token_key(&token),
+        serde_json::to_string(&pending)?,
+        TOKEN_MAX_AGE,
+    )
+    .await?;
+
+    let link = format!(
+        "{}/auth/email/verify?token={token}",
+        email_login.link_base_url.trim_end_matches('/')
+    );
+    let body = format!(
+        "Use the link below to log in to Yral. It expires in 15 minutes and can only be used once.\n\n{link}\n\nIf you didn't request this email, you can ignore it."
+    );
+    email_login
+        .mailer
+        .send(&email, "Your Yral login link", &body)
+        .await?;
+    Ok(())
+}
+
+/// Consume `token`, returning the pending login it was issued for
+async fn take_token(kv: &KVStoreImpl, token: &str) -> Result<PendingToken, ServerFnError> {
+    let key = token_key(token);
+    let Some(raw) = kv.read(key.clone()).await? else {
+        return Err(ServerFnError::new("Login link is invalid or already used"));
+    };
+    // only the request that removes the token may use it
+    if !kv.delete(key).await? {
+        return Err(ServerFnError::new("Login link is invalid or already used"));
+    }
+    let pending: PendingToken = serde_json::from_str(&raw)?;
+    if current_epoch().as_millis() > pending.expiry_epoch_ms {
+        return Err(ServerFnError::new("Login link expired"));
+    }
+    Ok(pending)
+}
+
+async fn try_extract_identity_from_email(
+    kv: &KVStoreImpl,
+    email: &str,
+) -> Result<Option<Secp256k1Identity>, ServerFnError> {
+    let Some(principal_text) = kv.read(principal_lookup_key(email)).await? else {
+        return Ok(None);
+    };
+    let principal = Principal::from_text(principal_text)?;
+    Ok(fetch_identity_from_kv(kv, principal)
+        .await?
+        .map(Secp256k1Identity::from_private_key))
+}
+
+/// Exchange a login link's token for the identity linked to its email address
+/// the first login links the address to the identity of the session that requested the link,
+/// links opened in any other session get a fresh identity,
+/// so a link sent to someone else's address can't be attached to the current account
+pub async fn perform_email_auth_impl(
+    token: String,
+) -> Result<DelegatedIdentityWire, ServerFnError> {
+    let kv: KVStoreImpl = expect_context();
+    let pending = take_token(&kv, &token).await?;
+    let jar = cookies::signed_jar().await?;
+
+    let identity = if let Some(identity) =
+        try_extract_identity_from_email(&kv, &pending.email).await?
+    {
+        identity
+    } else {
+        let current_session = extract_refresh_token(&jar, &kv)
+            .await?
+            .and_then(|token| token.session_id);
+        let requested_here = pending.session_id.is_some() && current_session == pending.session_id;
+        let identity = match try_extract_identity(&jar, &kv).await? {
+            Some(secret) if requested_here => Secp256k1Identity::from_private_key(secret),
+            _ => generate_and_save_identity(&kv).await?,
+        };
+        link_email(&kv, identity.sender().unwrap(), &pending.email).await?;
+        identity
+    };
+
+    let resp: ResponseOptions = expect_context();
+    login_user_identity(&resp, jar, identity).await
+}
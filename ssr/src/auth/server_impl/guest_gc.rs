@@ -0,0 +1,285 @@
+//! Garbage collection of guest identities
+//!
+//! `identity-meta-{principal}` holds the [IdentityMeta] of an identity.
+//! Identities created before metadata was recorded get it the next time they're seen,
+//! or when a sweep first finds them, so they're aged from then on.
+//! A guest identity is removed once it was created and last seen longer than the configured age ago,
+//! if it was never linked to a login method and never registered a user canister.
+//! `guest-gc-lease` is held by the instance currently sweeping, so instances don't sweep concurrently
+
+use candid::Principal;
+use leptos::ServerFnError;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use web_time::Duration;
+
+use crate::{state::canisters::Canisters, utils::current_epoch};
+
+use super::{
+    identity_key,
+    login_methods::has_login_method,
+    session,
+    store::{KVStore, KVStoreImpl, KvKey, RecordKind},
+};
+
+/// `last_seen_ms` is only updated if it's older than this, avoids a write on every request
+const TOUCH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Slightly shorter than [SWEEP_INTERVAL], so one instance sweeps per interval
+const LEASE_TTL: Duration = Duration::from_secs(55 * 60);
+const LEASE_KEY: &str = "guest-gc-lease";
+const META_PREFIX: &str = "identity-meta-";
+/// Keys listed per batch while sweeping
+const SWEEP_BATCH: usize = 100;
+
+#[derive(Serialize, Deserialize)]
+struct IdentityMeta {
+    created_at_ms: u64,
+    last_seen_ms: u64,
+    /// Set once the identity is known to be kept, skips further checks
+    #[serde(default)]
+    linked: bool,
+}
+
+fn meta_key(principal: Principal) -> KvKey {
+    KvKey::new(
+        RecordKind::Identity,
+        format!("{META_PREFIX}{}", principal.to_text()),
+    )
+}
+
+fn now_ms() -> u64 {
+    current_epoch().as_millis() as u64
+}
+
+async fn read_meta(
+    kv: &KVStoreImpl,
+    principal: Principal,
+) -> Result<Option<IdentityMeta>, ServerFnError> {
+    let Some(raw) = kv.read(meta_key(principal)).await? else {
+        return Ok(None);
+    };
+    Ok(Some(serde_json::from_str(&raw)?))
+}
+
+async fn write_meta(
+    kv: &KVStoreImpl,
+    principal: Principal,
+    meta: &IdentityMeta,
+) -> Result<(), ServerFnError> {
+    kv.write(meta_key(principal), serde_json::to_string(meta)?)
+        .await?;
+    Ok(())
+}
+
+/// Record the creation of an identity
+pub async fn record_created(kv: &KVStoreImpl, principal: Principal) -> Result<(), ServerFnError> {
+    let now = now_ms();
+    let meta = IdentityMeta {
+        created_at_ms: now,
+        last_seen_ms: now,
+        linked: false,
+    };
+    write_meta(kv, principal, &meta).await
+}
+
+/// Record the use of an identity
+pub async fn touch_identity(kv: &KVStoreImpl, principal: Principal) -> Result<(), ServerFnError> {
+    let Some(mut meta) = read_meta(kv, principal).await? else {
+        return record_created(kv, principal).await;
+    };
+    let now = now_ms();
+    if now.saturating_sub(meta.last_seen_ms) > TOUCH_INTERVAL.as_millis() as u64 {
+        meta.last_seen_ms = now;
+        write_meta(kv, principal, &meta).await?;
+    }
+    Ok(())
+}
+
+/// Exempt an identity from garbage collection
+/// for login methods that aren't otherwise visible to the collector
+pub async fn mark_linked(kv: &KVStoreImpl, principal: Principal) -> Result<(), ServerFnError> {
+    let now = now_ms();
+    let mut meta = read_meta(kv, principal).await?.unwrap_or(IdentityMeta {
+        created_at_ms: now,
+        last_seen_ms: now,
+        linked: false,
+    });
+    if !meta.linked {
+        meta.linked = true;
+        write_meta(kv, principal, &meta).await?;
+    }
+    Ok(())
+}
+
+/// Whether `principal` is linked to a login method or has a user canister
+async fn is_kept(
+    kv: &KVStoreImpl,
+    canisters: &Canisters<false>,
+    principal: Principal,
+) -> Result<bool, ServerFnError> {
+    if has_login_method(kv, principal).await? {
+        return Ok(true);
+    }
+    // tokens are only held by user canisters
+    Ok(canisters
+        .get_individual_canister_by_user_principal(principal)
+        .await?
+        .is_some())
+}
+
+async fn remove_identity(kv: &KVStoreImpl, principal: Principal) -> Result<(), ServerFnError> {
+    for (id, _) in session::list_sessions(kv, principal).await? {
+        session::revoke_session(kv, &id).await?;
+    }
+    kv.delete(identity_key(principal)).await?;
+    kv.delete(meta_key(principal)).await?;
+    Ok(())
+}
+
+/// Principal of an identity record
+/// other keys listed along with identities, e.g their metadata, are skipped
+fn identity_principal(key: &str) -> Option<Principal> {
+    if key.starts_with(META_PREFIX) {
+        return None;
+    }
+    Principal::from_text(key).ok()
+}
+
+/// Remove the guests among `principals` last seen before `cutoff`, returns the number removed
+/// failing to check or remove an identity is logged and skips it
+async fn sweep_batch(
+    kv: &KVStoreImpl,
+    canisters: &Canisters<false>,
+    principals: &[Principal],
+    now: u64,
+    cutoff: u64,
+) -> Result<usize, ServerFnError> {
+    if principals.is_empty() {
+        return Ok(0);
+    }
+    let metas = kv
+        .read_many(
+            principals
+                .iter()
+                .map(|principal| meta_key(*principal))
+                .collect(),
+        )
+        .await?;
+    let mut removed = 0;
+    for (&principal, raw) in principals.iter().zip(metas) {
+        let Some(raw) = raw else {
+            // created before metadata was recorded, unless it was just written
+            let meta = IdentityMeta {
+                created_at_ms: now,
+                last_seen_ms: now,
+                linked: false,
+            };
+            kv.compare_and_set(
+                meta_key(principal),
+                None,
+                serde_json::to_string(&meta)?,
+                None,
+            )
+            .await?;
+            continue;
+        };
+        let mut meta: IdentityMeta = match serde_json::from_str(&raw) {
+            Ok(meta) => meta,
+            Err(e) => {
+                log::warn!("skipping malformed identity metadata of {principal} {e}");
+                continue;
+            }
+        };
+        if meta.linked || meta.created_at_ms > cutoff || meta.last_seen_ms > cutoff {
+            continue;
+        }
+        match is_kept(kv, canisters, principal).await {
+            Ok(true) => {
+                meta.linked = true;
+                write_meta(kv, principal, &meta).await?;
+                continue;
+            }
+            Ok(false) => (),
+            Err(e) => {
+                log::warn!("skipping guest identity {principal}, failed to check its links {e}");
+                continue;
+            }
+        }
+        if let Err(e) = remove_identity(kv, principal).await {
+            log::warn!("failed to remove guest identity {principal} {e}");
+            continue;
+        }
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+/// Remove guest identities older than `max_age`, returns the number of identities removed
+pub async fn sweep_guest_identities(
+    kv: &KVStoreImpl,
+    canisters: &Canisters<false>,
+    max_age: Duration,
+) -> Result<usize, ServerFnError> {
+    let now = now_ms();
+    let cutoff = now.saturating_sub(max_age.as_millis() as u64);
+    let mut removed = 0;
+    let mut cursor = None;
+    loop {
+        let page = kv
+            .list_keys_page(RecordKind::Identity, String::new(), cursor, SWEEP_BATCH)
+            .await?;
+        let principals: Vec<_> = page
+            .keys
+            .iter()
+            .filter_map(|key| identity_principal(key))
+            .collect();
+        removed += sweep_batch(kv, canisters, &principals, now, cutoff).await?;
+        cursor = page.cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    Ok(removed)
+}
+
+/// Take the sweep lease for [LEASE_TTL], false if another instance holds it
+/// the lease isn't released after sweeping, it expires on its own
+async fn take_lease(kv: &KVStoreImpl) -> Result<bool, ServerFnError> {
+    Ok(kv
+        .compare_and_set(
+            KvKey::new(RecordKind::Other, LEASE_KEY),
+            None,
+            now_ms().to_string(),
+            Some(LEASE_TTL),
+        )
+        .await?)
+}
+
+/// Periodically remove guest identities older than `max_age` in the background
+/// only one instance sweeps per interval
+pub fn spawn_guest_identity_gc(
+    kv: KVStoreImpl,
+    canisters: Canisters<false>,
+    max_age: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            ticker.tick().await;
+            match take_lease(&kv).await {
+                Ok(true) => (),
+                Ok(false) => continue,
+                Err(e) => {
+                    log::warn!("failed to take the guest identity sweep lease {e}");
+                    continue;
+                }
+            }
+            match sweep_guest_identities(&kv, &canisters, max_age).await {
+                Ok(0) => (),
+                Ok(removed) => log::info!("removed {removed} guest identities"),
+                Err(e) => log::warn!("failed to sweep guest identities {e}"),
+            }
+        }
+    })
+}
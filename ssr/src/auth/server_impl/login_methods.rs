@@ -0,0 +1,42 @@
+//! Login methods of a principal, across every provider
+//!
+//! Each provider keeps its own records, this only counts them
+
+use candid::Principal;
+use leptos::ServerFnError;
+
+use super::store::KVStoreImpl;
+
+/// Number of login methods linked to `principal`
+/// OpenID Connect accounts, passkeys and email addresses
+#[cfg_attr(
+    not(any(feature = "oauth-ssr", feature = "passkey-ssr", feature = "email-ssr")),
+    allow(unused_variables, unused_mut)
+)]
+pub async fn count_login_methods(
+    kv: &KVStoreImpl,
+    principal: Principal,
+) -> Result<usize, ServerFnError> {
+    let mut count = 0;
+    #[cfg(feature = "oauth-ssr")]
+    {
+        count += super::oidc::links::linked_sub_count(kv, principal).await?;
+    }
+    #[cfg(feature = "passkey-ssr")]
+    {
+        count += super::passkey::credential_count(kv, principal).await?;
+    }
+    #[cfg(feature = "email-ssr")]
+    {
+        count += super::email::linked_email_count(kv, principal).await?;
+    }
+    Ok(count)
+}
+
+/// Whether `principal` can log in with anything but its cookie
+pub async fn has_login_method(
+    kv: &KVStoreImpl,
+    principal: Principal,
+) -> Result<bool, ServerFnError> {
+    Ok(count_login_methods(kv, principal).await? > 0)
+}
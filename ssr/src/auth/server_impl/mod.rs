@@ -1,33 +1,61 @@
+pub mod cookies;
+#[cfg(feature = "email-ssr")]
+pub mod email;
+pub mod guest_gc;
+pub mod login_methods;
 #[cfg(feature = "oauth-ssr")]
-pub mod google;
+pub mod oidc;
+#[cfg(feature = "passkey-ssr")]
+pub mod passkey;
+mod recovery;
+pub mod secrets;
+pub mod session;
 pub mod store;
+#[cfg(test)]
+mod tests;
 
 use axum::response::IntoResponse;
 use axum_extra::extract::{
-    cookie::{Cookie, Key, SameSite},
+    cookie::{Cookie, SameSite},
     SignedCookieJar,
 };
 use candid::Principal;
-use http::header;
+use http::{header, HeaderMap};
 use ic_agent::{
     identity::{Delegation, Secp256k1Identity, SignedDelegation},
     Identity,
 };
 use leptos::{expect_context, ServerFnError};
-use leptos_axum::{extract_with_state, ResponseOptions};
+use leptos_axum::{extract, ResponseOptions};
 use rand_chacha::rand_core::OsRng;
+use web_time::Duration;
 
 use crate::{
     consts::auth::{DELEGATION_MAX_AGE, REFRESH_MAX_AGE, REFRESH_TOKEN_COOKIE},
     utils::current_epoch,
 };
 
-use self::store::{KVStore, KVStoreImpl};
+use self::{
+    secrets::IdentityKeyring,
+    store::{KVStore, KVStoreImpl, KvKey, RecordKind},
+};
 
-use super::{DelegatedIdentityWire, RefreshToken};
+use super::{DelegatedIdentityWire, RefreshToken, SessionInfo};
 
 impl DelegatedIdentityWire {
+    /// Delegation usable with any canister
+    /// user sessions call other users' canisters (likes, views, referrals),
+    /// which can't be listed upfront
     pub fn delegate(from: &impl Identity) -> Self {
+        Self::delegate_with_targets(from, None)
+    }
+
+    /// Delegation only usable with `targets`, for server side flows acting on known canisters
+    pub fn delegate_scoped(from: &impl Identity, targets: Vec<Principal>) -> Self {
+        Self::delegate_with_targets(from, Some(targets))
+    }
+
+    fn delegate_with_targets(from: &impl Identity, targets: Option<Vec<Principal>>) -> Self {
         let to_secret = k256::SecretKey::random(&mut OsRng);
         let to_identity = Secp256k1Identity::from_private_key(to_secret.clone());
         let expiry = current_epoch() + DELEGATION_MAX_AGE;
@@ -35,7 +63,7 @@ impl DelegatedIdentityWire {
         let delegation = Delegation {
             pubkey: to_identity.public_key().unwrap(),
             expiration: expiry_ns,
-            targets: None,
+            targets,
         };
         let sig = from.sign_delegation(&delegation).unwrap();
         let signed_delegation = SignedDelegation {
@@ -63,9 +91,30 @@ fn set_cookies(resp: &ResponseOptions, jar: impl IntoResponse) {
     }
 }
 
-async fn extract_principal_from_cookie(
+fn refresh_cookie(token: &RefreshToken) -> Cookie<'static> {
+    let max_age_ms = token
+        .expiry_epoch_ms
+        .saturating_sub(current_epoch().as_millis());
+    let refresh_token_enc =
+        serde_json::to_string(token).expect("refresh token should be serializable");
+
+    Cookie::build((REFRESH_TOKEN_COOKIE, refresh_token_enc))
+        .http_only(true)
+        .secure(true)
+        .path("/")
+        .same_site(SameSite::None)
+        .partitioned(true)
+        .max_age(Duration::from_millis(max_age_ms as u64).try_into().unwrap())
+        .build()
+}
+
+/// Refresh token in the cookie, if it's neither expired nor revoked
+/// tokens issued before sessions were tracked are returned as is until migrated,
+/// the caller must migrate them with [migrate_legacy_token]
+async fn verify_refresh_token(
     jar: &SignedCookieJar,
-) -> Result<Option<Principal>, ServerFnError> {
+    kv: &KVStoreImpl,
+) -> Result<Option<RefreshToken>, ServerFnError> {
     let Some(cookie) = jar.get(REFRESH_TOKEN_COOKIE) else {
         return Ok(None);
     };
@@ -73,73 +122,182 @@ async fn extract_principal_from_cookie(
     if current_epoch().as_millis() > token.expiry_epoch_ms {
         return Ok(None);
     }
-    Ok(Some(token.principal))
+    let valid = match &token.session_id {
+        Some(session_id) => session::touch_session(kv, session_id, token.principal).await?,
+        None => !session::legacy_token_claimed(kv, token.principal).await?,
+    };
+    Ok(valid.then_some(token))
+}
+
+/// Refresh token in the cookie, if it's neither expired nor revoked
+async fn extract_refresh_token(
+    jar: &SignedCookieJar,
+    kv: &KVStoreImpl,
+) -> Result<Option<RefreshToken>, ServerFnError> {
+    match verify_refresh_token(jar, kv).await? {
+        Some(token) if token.session_id.is_none() => migrate_legacy_token(jar, kv, token).await,
+        token => Ok(token),
+    }
+}
+
+/// Move a token issued before sessions were tracked into a new session, and re-issue it
+/// only the first request presenting such a token for a principal is migrated,
+/// copies of the cookie are rejected afterwards
+async fn migrate_legacy_token(
+    jar: &SignedCookieJar,
+    kv: &KVStoreImpl,
+    token: RefreshToken,
+) -> Result<Option<RefreshToken>, ServerFnError> {
+    if !session::claim_legacy_token(kv, token.principal).await? {
+        return Ok(None);
+    }
+    let headers: HeaderMap = extract().await?;
+    let session_id =
+        session::create_session(kv, token.principal, session::device_label(&headers)).await?;
+    let token = RefreshToken {
+        session_id: Some(session_id),
+        ..token
+    };
+
+    let resp: ResponseOptions = expect_context();
+    set_cookies(&resp, jar.clone().add(refresh_cookie(&token)));
+    Ok(Some(token))
+}
+
+async fn extract_principal_from_cookie(
+    jar: &SignedCookieJar,
+    kv: &KVStoreImpl,
+) -> Result<Option<Principal>, ServerFnError> {
+    Ok(extract_refresh_token(jar, kv)
+        .await?
+        .map(|token| token.principal))
+}
+
+/// KV key of `principal`'s sealed identity
+pub fn identity_key(principal: Principal) -> KvKey {
+    KvKey::new(RecordKind::Identity, principal.to_text())
 }
 
 async fn fetch_identity_from_kv(
     kv: &KVStoreImpl,
     principal: Principal,
 ) -> Result<Option<k256::SecretKey>, ServerFnError> {
-    let Some(identity_jwk) = kv.read(principal.to_text()).await? else {
+    let key = identity_key(principal);
+    let Some(record) = kv.read(key.clone()).await? else {
         return Ok(None);
     };
 
-    Ok(Some(k256::SecretKey::from_jwk_str(&identity_jwk)?))
+    let keyring: IdentityKeyring = expect_context();
+    let opened = keyring.open(key.key.as_bytes(), &record)?;
+    let identity_jwk = std::str::from_utf8(&opened.plaintext)?;
+    let identity = k256::SecretKey::from_jwk_str(identity_jwk)?;
+    // re-encrypt legacy plaintext records and records using a retired key
+    if opened.stale {
+        let sealed = keyring.seal(key.key.as_bytes(), identity_jwk.as_bytes())?;
+        kv.write(key, sealed).await?;
+    }
+
+    Ok(Some(identity))
 }
 
 pub async fn try_extract_identity(
     jar: &SignedCookieJar,
     kv: &KVStoreImpl,
 ) -> Result<Option<k256::SecretKey>, ServerFnError> {
-    let Some(principal) = extract_principal_from_cookie(jar).await? else {
+    let Some(principal) = extract_principal_from_cookie(jar, kv).await? else {
         return Ok(None);
     };
     fetch_identity_from_kv(kv, principal).await
 }
 
-async fn generate_and_save_identity(kv: &KVStoreImpl) -> Result<Secp256k1Identity, ServerFnError> {
-    let base_identity_key = k256::SecretKey::random(&mut OsRng);
+async fn save_identity(
+    kv: &KVStoreImpl,
+    base_identity_key: k256::SecretKey,
+) -> Result<Secp256k1Identity, ServerFnError> {
     let base_identity = Secp256k1Identity::from_private_key(base_identity_key.clone());
     let principal = base_identity.sender().unwrap();
 
+    let keyring: IdentityKeyring = expect_context();
+    let key = identity_key(principal);
     let base_jwk = base_identity_key.to_jwk_string();
-    kv.write(principal.to_text(), base_jwk.to_string()).await?;
+    let sealed = keyring.seal(key.key.as_bytes(), base_jwk.as_bytes())?;
+    kv.write(key, sealed).await?;
+    guest_gc::record_created(kv, principal).await?;
     Ok(base_identity)
 }
 
+async fn generate_and_save_identity(kv: &KVStoreImpl) -> Result<Secp256k1Identity, ServerFnError> {
+    save_identity(kv, k256::SecretKey::random(&mut OsRng)).await
+}
+
+/// Issue a refresh token and delegation for `identity`
+/// used when the session carries on without the user proving who they are
 pub async fn update_user_identity(
+    response_opts: &ResponseOptions,
+    jar: SignedCookieJar,
+    identity: impl Identity,
+) -> Result<DelegatedIdentityWire, ServerFnError> {
+    issue_identity(response_opts, jar, identity, false).await
+}
+
+/// [update_user_identity] after an explicit login, e.g with a provider, passkey or email link
+/// the session counts as freshly authenticated for sensitive actions
+pub async fn login_user_identity(
+    response_opts: &ResponseOptions,
+    jar: SignedCookieJar,
+    identity: impl Identity,
+) -> Result<DelegatedIdentityWire, ServerFnError> {
+    issue_identity(response_opts, jar, identity, true).await
+}
+
+async fn issue_identity(
     response_opts: &ResponseOptions,
     mut jar: SignedCookieJar,
     identity: impl Identity,
+    authenticated: bool,
 ) -> Result<DelegatedIdentityWire, ServerFnError> {
-    let refresh_max_age = REFRESH_MAX_AGE;
-    let refresh_token = RefreshToken {
-        principal: identity.sender().unwrap(),
-        expiry_epoch_ms: (current_epoch() + refresh_max_age).as_millis(),
+    let principal = identity.sender().unwrap();
+    let kv: KVStoreImpl = expect_context();
+    // keep the current session if it belongs to the same principal
+    let session_id = match extract_refresh_token(&jar, &kv).await? {
+        Some(RefreshToken {
+            principal: current,
+            session_id: Some(session_id),
+            ..
+        }) if current == principal => session_id,
+        current => {
+            if let Some(old_session) = current.and_then(|token| token.session_id) {
+                session::revoke_session(&kv, &old_session).await?;
+            }
+            let headers: HeaderMap = extract().await?;
+            session::create_session(&kv, principal, session::device_label(&headers)).await?
+        }
     };
-    let refresh_token_enc = serde_json::to_string(&refresh_token)?;
 
-    let refresh_cookie = Cookie::build((REFRESH_TOKEN_COOKIE, refresh_token_enc))
-        .http_only(true)
-        .secure(true)
-        .path("/")
-        .same_site(SameSite::None)
-        .partitioned(true)
-        .max_age(refresh_max_age.try_into().unwrap());
+    if authenticated {
+        session::mark_authenticated(&kv, &session_id).await?;
+    }
+
+    let refresh_token = RefreshToken {
+        principal,
+        expiry_epoch_ms: (current_epoch() + REFRESH_MAX_AGE).as_millis(),
+        session_id: Some(session_id),
+    };
 
-    jar = jar.add(refresh_cookie);
+    jar = jar.add(refresh_cookie(&refresh_token));
     set_cookies(response_opts, jar);
 
     Ok(DelegatedIdentityWire::delegate(&identity))
 }
 
 pub async fn extract_or_generate_identity_impl() -> Result<DelegatedIdentityWire, ServerFnError> {
-    let key: Key = expect_context();
-    let jar: SignedCookieJar = extract_with_state(&key).await?;
+    let jar = cookies::signed_jar().await?;
     let kv: KVStoreImpl = expect_context();
 
     let base_identity = if let Some(identity) = try_extract_identity(&jar, &kv).await? {
-        Secp256k1Identity::from_private_key(identity)
+        let identity = Secp256k1Identity::from_private_key(identity);
+        guest_gc::touch_identity(&kv, identity.sender().unwrap()).await?;
+        identity
     } else {
         generate_and_save_identity(&kv).await?
     };
@@ -150,13 +308,96 @@ pub async fn extract_or_generate_identity_impl() -> Result<DelegatedIdentityWire
     Ok(delegated)
 }
 
+pub async fn renew_identity_impl() -> Result<Option<DelegatedIdentityWire>, ServerFnError> {
+    let jar = cookies::signed_jar().await?;
+    let kv: KVStoreImpl = expect_context();
+
+    let Some(identity) = try_extract_identity(&jar, &kv).await? else {
+        return Ok(None);
+    };
+    let base_identity = Secp256k1Identity::from_private_key(identity);
+    guest_gc::touch_identity(&kv, base_identity.sender().unwrap()).await?;
+
+    let resp: ResponseOptions = expect_context();
+    let delegated = update_user_identity(&resp, jar, base_identity).await?;
+    Ok(Some(delegated))
+}
+
 pub async fn logout_identity_impl() -> Result<DelegatedIdentityWire, ServerFnError> {
-    let key: Key = expect_context();
     let kv: KVStoreImpl = expect_context();
-    let jar: SignedCookieJar = extract_with_state(&key).await?;
+    let jar = cookies::signed_jar().await?;
+    if let Some(session_id) = extract_refresh_token(&jar, &kv)
+        .await?
+        .and_then(|token| token.session_id)
+    {
+        session::revoke_session(&kv, &session_id).await?;
+    }
     let base_identity = generate_and_save_identity(&kv).await?;
 
     let resp: ResponseOptions = expect_context();
     let delegated = update_user_identity(&resp, jar, base_identity).await?;
     Ok(delegated)
 }
+
+pub use recovery::{recover_identity_impl, recovery_phrase_impl};
+
+/// Sessions of the principal currently logged in
+pub async fn active_sessions_impl() -> Result<Vec<SessionInfo>, ServerFnError> {
+    let jar = cookies::signed_jar().await?;
+    let kv: KVStoreImpl = expect_context();
+    let Some(token) = extract_refresh_token(&jar, &kv).await? else {
+        return Ok(vec![]);
+    };
+
+    let mut sessions: Vec<_> = session::list_sessions(&kv, token.principal)
+        .await?
+        .into_iter()
+        .map(|(id, session)| SessionInfo {
+            current: token.session_id.as_ref() == Some(&id),
+            id,
+            device: session.device,
+            created_at_ms: session.created_at_ms,
+            last_used_ms: session.last_used_ms,
+        })
+        .collect();
+    sessions.sort_by(|a, b| {
+        b.current
+            .cmp(&a.current)
+            .then(b.last_used_ms.cmp(&a.last_used_ms))
+    });
+    Ok(sessions)
+}
+
+/// Revoke a session of the principal currently logged in
+pub async fn revoke_session_impl(session_id: String) -> Result<(), ServerFnError> {
+    let jar = cookies::signed_jar().await?;
+    let kv: KVStoreImpl = expect_context();
+    let principal = extract_principal_from_cookie(&jar, &kv)
+        .await?
+        .ok_or_else(|| ServerFnError::new("Not logged in"))?;
+
+    let owned = session::list_sessions(&kv, principal)
+        .await?
+        .into_iter()
+        .any(|(id, _)| id == session_id);
+    if !owned {
+        return Err(ServerFnError::new("Session not found"));
+    }
+    session::revoke_session(&kv, &session_id).await
+}
+
+/// Revoke every session of the principal currently logged in, except the current one
+pub async fn revoke_other_sessions_impl() -> Result<(), ServerFnError> {
+    let jar = cookies::signed_jar().await?;
+    let kv: KVStoreImpl = expect_context();
+    let token = extract_refresh_token(&jar, &kv)
+        .await?
+        .ok_or_else(|| ServerFnError::new("Not logged in"))?;
+
+    for (id, _) in session::list_sessions(&kv, token.principal).await? {
+        if token.session_id.as_ref() != Some(&id) {
+            session::revoke_session(&kv, &id).await?;
+        }
+    }
+    Ok(())
+}
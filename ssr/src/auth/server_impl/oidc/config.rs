@@ -0,0 +1,254 @@
+use std::{collections::HashMap, env, fs};
+
+use openidconnect::{
+    core::{CoreClient, CoreProviderMetadata},
+    reqwest::async_http_client,
+    AuthUrl, ClientId, ClientSecret, IssuerUrl, JsonWebKeySetUrl, RedirectUrl, Scope, TokenUrl,
+};
+use serde::Deserialize;
+
+use crate::{component::auth_providers::ProviderKind, consts::oidc as oidc_consts};
+
+use super::{JwksCache, OidcProvider, OidcProviders};
+
+/// Endpoints of providers that don't need discovery
+#[derive(Clone, Copy)]
+struct WellKnownEndpoints {
+    issuer_url: &'static str,
+    auth_url: &'static str,
+    token_url: &'static str,
+    jwks_url: &'static str,
+}
+
+fn well_known_endpoints(provider: ProviderKind) -> Option<WellKnownEndpoints> {
+    match provider {
+        ProviderKind::Google => Some(WellKnownEndpoints {
+            issuer_url: oidc_consts::GOOGLE_ISSUER_URL,
+            auth_url: oidc_consts::GOOGLE_AUTH_URL,
+            token_url: oidc_consts::GOOGLE_TOKEN_URL,
+            jwks_url: oidc_consts::GOOGLE_JWKS_URL,
+        }),
+        ProviderKind::Apple => Some(WellKnownEndpoints {
+            issuer_url: oidc_consts::APPLE_ISSUER_URL,
+            auth_url: oidc_consts::APPLE_AUTH_URL,
+            token_url: oidc_consts::APPLE_TOKEN_URL,
+            jwks_url: oidc_consts::APPLE_JWKS_URL,
+        }),
+        _ => None,
+    }
+}
+
+/// Configuration of a single OpenID Connect provider
+///
+/// In the TOML file each provider is a table named after it, e.g `[google]`.
+/// Every field can be overridden with `<PROVIDER>_<FIELD>` env vars, e.g `GOOGLE_CLIENT_ID`
+#[derive(Deserialize, Default, Clone, Debug)]
+pub struct OidcProviderConfig {
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub redirect_url: Option<String>,
+    /// Defaults to the well known issuer of the provider, if any
+    pub issuer_url: Option<String>,
+    /// Discovered from the issuer if not set and not well known
+    pub auth_url: Option<String>,
+    /// Discovered from the issuer if not set and not well known
+    pub token_url: Option<String>,
+    /// Keys used to verify ID tokens
+    /// Discovered from the issuer if not set and not well known
+    pub jwks_url: Option<String>,
+    /// Defaults to `openid`
+    pub scopes: Option<Vec<String>>,
+}
+
+impl OidcProviderConfig {
+    fn override_from_env(&mut self, env_prefix: &str) {
+        let var = |field: &str| {
+            env::var(format!("{env_prefix}_{field}"))
+                .ok()
+                .filter(|v| !v.is_empty())
+        };
+
+        self.client_id = var("CLIENT_ID").or(self.client_id.take());
+        self.client_secret = var("CLIENT_SECRET").or(self.client_secret.take());
+        self.redirect_url = var("REDIRECT_URL").or(self.redirect_url.take());
+        self.issuer_url = var("ISSUER_URL").or(self.issuer_url.take());
+        self.auth_url = var("AUTH_URL").or(self.auth_url.take());
+        self.token_url = var("TOKEN_URL").or(self.token_url.take());
+        self.jwks_url = var("JWKS_URL").or(self.jwks_url.take());
+        if let Some(scopes) = var("SCOPES") {
+            self.scopes = Some(scopes.split_whitespace().map(str::to_string).collect());
+        }
+    }
+
+    fn scopes(&self) -> Vec<Scope> {
+        self.scopes
+            .clone()
+            .unwrap_or_else(|| {
+                oidc_consts::DEFAULT_SCOPES
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect()
+            })
+            .into_iter()
+            .map(Scope::new)
+            .collect()
+    }
+
+    /// Enabled once the client id is set
+    fn enabled(&self) -> bool {
+        self.client_id.is_some()
+    }
+
+    fn issuer_url(&self, provider: ProviderKind) -> Option<String> {
+        self.issuer_url
+            .clone()
+            .or_else(|| well_known_endpoints(provider).map(|w| w.issuer_url.to_string()))
+    }
+
+    /// Problems that would prevent building the provider
+    fn validate(&self, provider: ProviderKind) -> Vec<String> {
+        let name = provider.as_str();
+        let mut problems = vec![];
+        match &self.redirect_url {
+            Some(url) => {
+                if let Err(e) = RedirectUrl::new(url.clone()) {
+                    problems.push(format!("invalid redirect url for `{name}`: {e}"));
+                }
+            }
+            None => problems.push(format!("`redirect_url` is required for `{name}`")),
+        }
+        match self.issuer_url(provider) {
+            Some(url) => {
+                if let Err(e) = IssuerUrl::new(url) {
+                    problems.push(format!("invalid issuer url for `{name}`: {e}"));
+                }
+            }
+            None => problems.push(format!("`issuer_url` is required for `{name}`")),
+        }
+        let endpoints = [
+            ("auth", &self.auth_url),
+            ("token", &self.token_url),
+            ("JWKS", &self.jwks_url),
+        ];
+        for (kind, url) in endpoints {
+            if let Some(Err(e)) = url.as_deref().map(reqwest::Url::parse) {
+                problems.push(format!("invalid {kind} url for `{name}`: {e}"));
+            }
+        }
+        problems
+    }
+
+    /// Only fails on discovery, the configuration is validated when loaded
+    async fn build(self, provider: ProviderKind) -> OidcProvider {
+        let name = provider.as_str();
+        let client_id = ClientId::new(self.client_id.clone().unwrap());
+        let client_secret = self.client_secret.clone().map(ClientSecret::new);
+        let redirect_url = RedirectUrl::new(self.redirect_url.clone().unwrap()).unwrap();
+
+        let well_known = well_known_endpoints(provider);
+        let issuer_url = IssuerUrl::new(self.issuer_url(provider).unwrap()).unwrap();
+
+        let auth_url = self
+            .auth_url
+            .clone()
+            .or_else(|| well_known.map(|w| w.auth_url.to_string()));
+        let token_url = self
+            .token_url
+            .clone()
+            .or_else(|| well_known.map(|w| w.token_url.to_string()));
+        let jwks_url = self
+            .jwks_url
+            .clone()
+            .or_else(|| well_known.map(|w| w.jwks_url.to_string()));
+
+        let (client, jwks_url) = if let (Some(auth_url), Some(token_url), Some(jwks_url)) =
+            (auth_url, token_url, jwks_url)
+        {
+            let client = CoreClient::new(
+                client_id.clone(),
+                client_secret,
+                issuer_url.clone(),
+                AuthUrl::new(auth_url).unwrap(),
+                Some(TokenUrl::new(token_url).unwrap()),
+                None,
+                // ID tokens are verified against the cached JWKS instead
+                Default::default(),
+            );
+            let jwks_url = JsonWebKeySetUrl::new(jwks_url).unwrap();
+            (client, jwks_url)
+        } else {
+            let metadata =
+                CoreProviderMetadata::discover_async(issuer_url.clone(), async_http_client)
+                    .await
+                    .unwrap_or_else(|e| panic!("Failed to discover `{name}` OIDC metadata: {e}"));
+            let jwks_url = metadata.jwks_uri().clone();
+            let client =
+                CoreClient::from_provider_metadata(metadata, client_id.clone(), client_secret);
+            (client, jwks_url)
+        };
+
+        OidcProvider::new(
+            client.set_redirect_uri(redirect_url),
+            client_id,
+            issuer_url,
+            JwksCache::new(jwks_url),
+            self.scopes(),
+        )
+    }
+}
+
+/// Configuration of all OpenID Connect providers, keyed by [ProviderKind::as_str]
+#[derive(Deserialize, Default, Clone, Debug)]
+pub struct OidcConfig(HashMap<String, OidcProviderConfig>);
+
+impl OidcConfig {
+    /// Load the TOML file at `path` (if any), apply env overrides and validate enabled providers
+    /// loaded by [AppConfig](crate::init::config::AppConfig), every problem is returned
+    pub fn load(path: Option<&str>) -> Result<Self, Vec<String>> {
+        let mut config = if let Some(path) = path {
+            let raw = fs::read_to_string(path)
+                .map_err(|e| vec![format!("failed to read `{path}`: {e}")])?;
+            toml::from_str(&raw).map_err(|e| vec![format!("invalid OIDC config `{path}`: {e}")])?
+        } else {
+            Self::default()
+        };
+
+        let mut problems = vec![];
+        for provider in ProviderKind::oidc_providers() {
+            let key = provider.as_str().to_string();
+            let prov_config = config.0.entry(key).or_default();
+            prov_config.override_from_env(&provider.as_str().to_uppercase());
+            if prov_config.enabled() {
+                problems.extend(prov_config.validate(provider));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Replace the configuration of `provider`
+    pub fn insert(&mut self, provider: ProviderKind, config: OidcProviderConfig) {
+        self.0.insert(provider.as_str().to_string(), config);
+    }
+
+    /// Build the registry from every provider that has a client id
+    pub async fn build(mut self) -> OidcProviders {
+        let mut providers = HashMap::new();
+        for provider in ProviderKind::oidc_providers() {
+            let Some(config) = self.0.remove(provider.as_str()) else {
+                continue;
+            };
+            if !config.enabled() {
+                continue;
+            }
+            log::info!("enabling {} login", provider.as_str());
+            providers.insert(provider, config.build(provider).await);
+        }
+
+        OidcProviders::new(providers)
+    }
+}
@@ -0,0 +1,143 @@
+//! Logging into an existing account from a guest session
+//!
+//! If the guest has activity, the login is deferred and the user picks between
+//! keeping the guest account or transferring its assets into the existing account
+
+use axum_extra::extract::SignedCookieJar;
+use candid::Principal;
+use ic_agent::identity::{DelegatedIdentity, Secp256k1Identity};
+use leptos::{expect_context, ServerFnError};
+use leptos_axum::ResponseOptions;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::{
+        server_impl::{
+            cookies, extract_principal_from_cookie, fetch_identity_from_kv, login_user_identity,
+            set_cookies, store::KVStoreImpl,
+        },
+        DelegatedIdentityWire, GuestActivity,
+    },
+    canister::individual_user_template::{Result5, Result8},
+    state::canisters::{unauth_canisters, Canisters},
+};
+
+const PENDING_LOGIN_COOKIE: &str = "oidc-pending-login";
+
+#[derive(Serialize, Deserialize)]
+struct PendingLogin {
+    guest: Principal,
+    account: Principal,
+}
+
+/// Activity of `guest`'s canister, empty if it has none
+pub async fn guest_activity(guest: Principal) -> Result<GuestActivity, ServerFnError> {
+    let canisters = unauth_canisters();
+    let Some(user_canister) = canisters
+        .get_individual_canister_by_user_principal(guest)
+        .await?
+    else {
+        return Ok(GuestActivity::default());
+    };
+    let user = canisters.individual_user(user_canister).await?;
+
+    let balance = user.get_utility_token_balance().await?;
+    let has_bets = !user
+        .get_hot_or_not_bets_placed_by_this_profile_with_pagination(0)
+        .await?
+        .is_empty();
+    let has_posts = matches!(
+        user.get_posts_of_this_user_profile_with_pagination_cursor(0, 1).await?,
+        Result5::Ok(posts) if !posts.is_empty()
+    );
+
+    Ok(GuestActivity {
+        balance,
+        has_bets,
+        has_posts,
+    })
+}
+
+/// Remember that `guest` is logging into `account`
+/// the cookies still point to `guest` until the login is completed
+pub async fn defer_login(guest: Principal, account: Principal) -> Result<(), ServerFnError> {
+    let mut jar = cookies::private_jar().await?;
+    let pending = serde_json::to_string(&PendingLogin { guest, account })?;
+    jar = jar.add(cookies::flow_cookie(PENDING_LOGIN_COOKIE, pending));
+
+    let resp: ResponseOptions = expect_context();
+    set_cookies(&resp, jar);
+    Ok(())
+}
+
+/// Take the pending login of the current guest, if any
+async fn take_pending_login(
+    signed_jar: &SignedCookieJar,
+    kv: &KVStoreImpl,
+) -> Result<PendingLogin, ServerFnError> {
+    let mut jar = cookies::private_jar().await?;
+    let pending: PendingLogin = jar
+        .get(PENDING_LOGIN_COOKIE)
+        .map(|cookie| serde_json::from_str(cookie.value()))
+        .transpose()?
+        .ok_or_else(|| ServerFnError::new("No pending login"))?;
+    jar = jar.remove(PENDING_LOGIN_COOKIE);
+    let resp: ResponseOptions = expect_context();
+    set_cookies(&resp, jar);
+
+    if extract_principal_from_cookie(signed_jar, kv).await? != Some(pending.guest) {
+        return Err(ServerFnError::new(
+            "Pending login belongs to another session",
+        ));
+    }
+    Ok(pending)
+}
+
+/// Transfer the guest's tokens and posts into the account, then log into it
+pub async fn transfer_guest_and_login_impl() -> Result<DelegatedIdentityWire, ServerFnError> {
+    let jar = cookies::signed_jar().await?;
+    let kv: KVStoreImpl = expect_context();
+    let pending = take_pending_login(&jar, &kv).await?;
+
+    let guest_secret = fetch_identity_from_kv(&kv, pending.guest)
+        .await?
+        .ok_or_else(|| ServerFnError::new("Guest identity not found"))?;
+    let account_secret = fetch_identity_from_kv(&kv, pending.account)
+        .await?
+        .ok_or_else(|| ServerFnError::new("Account identity not found"))?;
+
+    let unauth = unauth_canisters();
+    let account_canister = unauth
+        .get_individual_canister_by_user_principal(pending.account)
+        .await?
+        .ok_or_else(|| ServerFnError::new("Account canister not found"))?;
+    if let Some(guest_canister) = unauth
+        .get_individual_canister_by_user_principal(pending.guest)
+        .await?
+    {
+        let guest_identity = Secp256k1Identity::from_private_key(guest_secret);
+        let guest_id: DelegatedIdentity =
+            DelegatedIdentityWire::delegate_scoped(&guest_identity, vec![guest_canister])
+                .try_into()?;
+        let guest_cans = Canisters::<true>::authenticated(guest_id);
+        let guest_user = guest_cans.individual_user(guest_canister).await?;
+        if let Result8::Err(_) = guest_user
+            .transfer_tokens_and_posts(pending.account, account_canister)
+            .await?
+        {
+            return Err(ServerFnError::new("failed to transfer guest account"));
+        }
+    }
+
+    let resp: ResponseOptions = expect_context();
+    let account_identity = Secp256k1Identity::from_private_key(account_secret);
+    login_user_identity(&resp, jar, account_identity).await
+}
+
+/// Stay on the guest account, dropping the pending login
+pub async fn keep_guest_account_impl() -> Result<(), ServerFnError> {
+    let jar = cookies::signed_jar().await?;
+    let kv: KVStoreImpl = expect_context();
+    take_pending_login(&jar, &kv).await?;
+    Ok(())
+}
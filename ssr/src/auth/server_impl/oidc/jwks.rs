@@ -0,0 +1,68 @@
+use std::{
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use openidconnect::{core::CoreJsonWebKeySet, reqwest::async_http_client, JsonWebKeySetUrl};
+
+/// Cached keys are refetched after this long, even if they still verify tokens
+const JWKS_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Minimum interval between refreshes forced by an unknown key
+/// the key id comes from the token, anyone can send one that doesn't exist
+const JWKS_MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, thiserror::Error)]
+#[error("failed to fetch JWKS: {0}")]
+pub struct JwksError(String);
+
+/// JSON Web Key Set of an issuer, fetched lazily and cached
+#[derive(Clone)]
+pub struct JwksCache {
+    url: JsonWebKeySetUrl,
+    cached: Arc<RwLock<Option<(CoreJsonWebKeySet, Instant)>>>,
+    last_forced_refresh: Arc<Mutex<Option<Instant>>>,
+}
+
+impl JwksCache {
+    pub fn new(url: JsonWebKeySetUrl) -> Self {
+        Self {
+            url,
+            cached: Arc::default(),
+            last_forced_refresh: Arc::default(),
+        }
+    }
+
+    /// Cached keys, fetching them if missing or stale
+    pub async fn get(&self) -> Result<CoreJsonWebKeySet, JwksError> {
+        if let Some((jwks, fetched_at)) = self.cached.read().unwrap().as_ref() {
+            if fetched_at.elapsed() < JWKS_MAX_AGE {
+                return Ok(jwks.clone());
+            }
+        }
+        self.fetch().await
+    }
+
+    /// Refetch the keys when a token is signed with an unknown key, the issuer may have rotated them
+    /// `None` if the keys were already refreshed within [JWKS_MIN_REFRESH_INTERVAL]
+    pub async fn refresh(&self) -> Result<Option<CoreJsonWebKeySet>, JwksError> {
+        {
+            let mut last = self.last_forced_refresh.lock().unwrap();
+            if last.is_some_and(|at| at.elapsed() < JWKS_MIN_REFRESH_INTERVAL) {
+                return Ok(None);
+            }
+            *last = Some(Instant::now());
+        }
+        self.fetch().await.map(Some)
+    }
+
+    /// Fetch the keys from the issuer, replacing the cached ones
+    async fn fetch(&self) -> Result<CoreJsonWebKeySet, JwksError> {
+        let jwks = CoreJsonWebKeySet::fetch_async(&self.url, async_http_client)
+            .await
+            .map_err(|e| JwksError(e.to_string()))?;
+        *self.cached.write().unwrap() = Some((jwks.clone(), Instant::now()));
+
+        Ok(jwks)
+    }
+}
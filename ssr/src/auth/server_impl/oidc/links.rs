@@ -0,0 +1,167 @@
+//! Login methods linked to a principal
+//!
+//! `{provider}-login-{sub}` maps a provider's subject to a principal,
+//! `login-methods-{principal}` is the reverse index listing every subject linked to it
+
+use candid::Principal;
+use leptos::ServerFnError;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::server_impl::{
+        login_methods::count_login_methods,
+        store::{KVStore, KVStoreImpl, KvKey, RecordKind},
+    },
+    component::auth_providers::ProviderKind,
+};
+
+use super::principal_lookup_key;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+struct LinkedLoginMethod {
+    provider: ProviderKind,
+    sub: String,
+}
+
+fn linked_methods_key(principal: Principal) -> KvKey {
+    KvKey::new(
+        RecordKind::OAuth,
+        format!("login-methods-{}", principal.to_text()),
+    )
+}
+
+/// Principal a provider's subject is linked to
+pub async fn principal_for_sub(
+    kv: &KVStoreImpl,
+    provider: ProviderKind,
+    sub: &str,
+) -> Result<Option<Principal>, ServerFnError> {
+    let Some(principal_text) = kv.read(principal_lookup_key(provider, sub)).await? else {
+        return Ok(None);
+    };
+    Ok(Some(Principal::from_text(principal_text)?))
+}
+
+async fn read_linked_methods(
+    kv: &KVStoreImpl,
+    principal: Principal,
+) -> Result<Vec<LinkedLoginMethod>, ServerFnError> {
+    let Some(raw) = kv.read(linked_methods_key(principal)).await? else {
+        return Ok(vec![]);
+    };
+    Ok(serde_json::from_str(&raw)?)
+}
+
+async fn write_linked_methods(
+    kv: &KVStoreImpl,
+    principal: Principal,
+    methods: &[LinkedLoginMethod],
+) -> Result<(), ServerFnError> {
+    let key = linked_methods_key(principal);
+    if methods.is_empty() {
+        kv.delete(key).await?;
+    } else {
+        kv.write(key, serde_json::to_string(methods)?).await?;
+    }
+    Ok(())
+}
+
+/// Subjects linked to `principal`
+/// entries whose forward record no longer points to `principal` are dropped
+async fn valid_linked_methods(
+    kv: &KVStoreImpl,
+    principal: Principal,
+) -> Result<Vec<LinkedLoginMethod>, ServerFnError> {
+    let methods = read_linked_methods(kv, principal).await?;
+    let mut valid = Vec::with_capacity(methods.len());
+    for method in &methods {
+        if principal_for_sub(kv, method.provider, &method.sub).await? == Some(principal) {
+            valid.push(method.clone());
+        }
+    }
+    if valid.len() != methods.len() {
+        write_linked_methods(kv, principal, &valid).await?;
+    }
+    Ok(valid)
+}
+
+/// Number of provider subjects linked to `principal`
+pub async fn linked_sub_count(
+    kv: &KVStoreImpl,
+    principal: Principal,
+) -> Result<usize, ServerFnError> {
+    Ok(valid_linked_methods(kv, principal).await?.len())
+}
+
+/// Providers linked to `principal`
+pub async fn linked_providers(
+    kv: &KVStoreImpl,
+    principal: Principal,
+) -> Result<Vec<ProviderKind>, ServerFnError> {
+    let valid = valid_linked_methods(kv, principal).await?;
+    let mut providers: Vec<_> = valid.into_iter().map(|m| m.provider).collect();
+    providers.sort();
+    providers.dedup();
+    Ok(providers)
+}
+
+/// Record `sub` in the reverse index of `principal`
+/// also backfills principals linked before the reverse index existed
+pub async fn index_sub(
+    kv: &KVStoreImpl,
+    principal: Principal,
+    provider: ProviderKind,
+    sub: &str,
+) -> Result<(), ServerFnError> {
+    let mut methods = read_linked_methods(kv, principal).await?;
+    let method = LinkedLoginMethod {
+        provider,
+        sub: sub.to_string(),
+    };
+    if methods.contains(&method) {
+        return Ok(());
+    }
+    methods.push(method);
+    write_linked_methods(kv, principal, &methods).await
+}
+
+/// Link a provider's subject to `principal`
+pub async fn link_sub(
+    kv: &KVStoreImpl,
+    principal: Principal,
+    provider: ProviderKind,
+    sub: &str,
+) -> Result<(), ServerFnError> {
+    // reverse index first, stale entries are dropped by [linked_providers]
+    index_sub(kv, principal, provider, sub).await?;
+    kv.write(principal_lookup_key(provider, sub), principal.to_text())
+        .await?;
+    Ok(())
+}
+
+/// Unlink `provider` from `principal`
+/// fails if no other login method would be left, passkeys and email addresses included
+pub async fn unlink_provider(
+    kv: &KVStoreImpl,
+    principal: Principal,
+    provider: ProviderKind,
+) -> Result<(), ServerFnError> {
+    let mut methods = valid_linked_methods(kv, principal).await?;
+    let unlinked = methods.iter().filter(|m| m.provider == provider).count();
+    if unlinked == 0 {
+        return Err(ServerFnError::new(format!(
+            "{} login is not linked",
+            provider.as_str()
+        )));
+    }
+    if count_login_methods(kv, principal).await? <= unlinked {
+        return Err(ServerFnError::new("Can't unlink the only login method"));
+    }
+
+    for method in methods.iter().filter(|m| m.provider == provider) {
+        kv.delete(principal_lookup_key(provider, &method.sub))
+            .await?;
+    }
+    methods.retain(|m| m.provider != provider);
+    write_linked_methods(kv, principal, &methods).await
+}
@@ -0,0 +1,251 @@
+//! Minimal OpenID Connect issuer for local development
+//! lets the whole login flow run offline, without a real provider
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use axum::{
+    extract::{Query, State},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form, Json, Router,
+};
+use chrono::DateTime;
+use ed25519_dalek::{
+    pkcs8::{EncodePrivateKey, LineEnding},
+    SigningKey,
+};
+use http::StatusCode;
+use openidconnect::{
+    core::{
+        CoreEdDsaPrivateSigningKey, CoreIdToken, CoreIdTokenClaims, CoreIdTokenFields,
+        CoreJsonWebKeySet, CoreJwsSigningAlgorithm, CoreTokenResponse, CoreTokenType,
+    },
+    AccessToken, Audience, AuthorizationCode, CsrfToken, EmptyAdditionalClaims,
+    EmptyExtraTokenFields, IssuerUrl, JsonWebKeyId, Nonce, PkceCodeChallenge, PkceCodeVerifier,
+    PrivateSigningKey, StandardClaims, SubjectIdentifier,
+};
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    component::auth_providers::ProviderKind,
+    consts::oidc::{MOCK_OIDC_CLIENT_ID, MOCK_OIDC_CLIENT_SECRET, MOCK_OIDC_PATH},
+    utils::current_epoch,
+};
+
+use super::config::OidcProviderConfig;
+
+const ID_TOKEN_LIFETIME_SECS: u64 = 60 * 60;
+
+#[derive(Deserialize)]
+struct AuthorizeQuery {
+    redirect_uri: String,
+    state: String,
+    nonce: Option<String>,
+    code_challenge: String,
+}
+
+#[derive(Deserialize)]
+struct ApproveQuery {
+    request: String,
+    sub: String,
+}
+
+#[derive(Deserialize)]
+struct TokenRequest {
+    code: String,
+    redirect_uri: String,
+    code_verifier: String,
+}
+
+/// Generate a fresh Ed25519 signing key with a random key id
+fn generate_signing_key() -> CoreEdDsaPrivateSigningKey {
+    let key = SigningKey::from_bytes(&rand::random());
+    let pem = key
+        .to_pkcs8_pem(LineEnding::LF)
+        .expect("failed to encode mock OIDC signing key");
+    let kid = JsonWebKeyId::new(CsrfToken::new_random().secret().clone());
+    CoreEdDsaPrivateSigningKey::from_ed25519_pem(&pem, Some(kid))
+        .expect("failed to load mock OIDC signing key")
+}
+
+struct Grant {
+    sub: String,
+    nonce: Option<String>,
+    code_challenge: String,
+}
+
+#[derive(Clone)]
+pub struct MockOidcIssuer {
+    issuer: IssuerUrl,
+    redirect_url: String,
+    /// key used to sign ID tokens, published at `/jwks`
+    signing_key: Arc<RwLock<CoreEdDsaPrivateSigningKey>>,
+    /// authorization requests waiting for the user to pick a subject
+    pending: Arc<Mutex<HashMap<String, AuthorizeQuery>>>,
+    /// authorization codes waiting to be exchanged
+    grants: Arc<Mutex<HashMap<String, Grant>>>,
+}
+
+impl MockOidcIssuer {
+    pub fn new(site_addr: SocketAddr) -> Self {
+        Self {
+            issuer: IssuerUrl::new(format!("http://{site_addr}{MOCK_OIDC_PATH}")).unwrap(),
+            redirect_url: format!(
+                "http://{site_addr}/auth/{}/redirect",
+                ProviderKind::MockOidc.as_str()
+            ),
+            signing_key: Arc::new(RwLock::new(generate_signing_key())),
+            pending: Arc::default(),
+            grants: Arc::default(),
+        }
+    }
+
+    pub fn provider_config(&self) -> OidcProviderConfig {
+        let issuer = self.issuer.as_str();
+        OidcProviderConfig {
+            client_id: Some(MOCK_OIDC_CLIENT_ID.into()),
+            client_secret: Some(MOCK_OIDC_CLIENT_SECRET.into()),
+            redirect_url: Some(self.redirect_url.clone()),
+            issuer_url: Some(issuer.into()),
+            auth_url: Some(format!("{issuer}/authorize")),
+            token_url: Some(format!("{issuer}/token")),
+            jwks_url: Some(format!("{issuer}/jwks")),
+            scopes: None,
+        }
+    }
+
+    /// Routes of the issuer, to be nested under [MOCK_OIDC_PATH]
+    pub fn router<S: Clone + Send + Sync + 'static>(self) -> Router<S> {
+        Router::new()
+            .route("/authorize", get(authorize))
+            .route("/approve", get(approve))
+            .route("/token", post(token))
+            .route("/jwks", get(jwks))
+            .route("/rotate", post(rotate))
+            .with_state(self)
+    }
+}
+
+fn bad_request(error: &str) -> Response {
+    (StatusCode::BAD_REQUEST, Json(json!({ "error": error }))).into_response()
+}
+
+async fn authorize(
+    State(issuer): State<MockOidcIssuer>,
+    Query(query): Query<AuthorizeQuery>,
+) -> Response {
+    if query.redirect_uri != issuer.redirect_url {
+        return bad_request("invalid_request");
+    }
+
+    let request_id = CsrfToken::new_random().secret().clone();
+    issuer
+        .pending
+        .lock()
+        .unwrap()
+        .insert(request_id.clone(), query);
+
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html>
+    <body>
+        <h1>Mock OpenID Connect login</h1>
+        <form action="{MOCK_OIDC_PATH}/approve" method="get">
+            <input type="hidden" name="request" value="{request_id}">
+            <label>Subject <input type="text" name="sub" value="mock-user" required></label>
+            <button type="submit">Login</button>
+        </form>
+    </body>
+</html>"#
+    ))
+    .into_response()
+}
+
+async fn approve(
+    State(issuer): State<MockOidcIssuer>,
+    Query(query): Query<ApproveQuery>,
+) -> Response {
+    let Some(pending) = issuer.pending.lock().unwrap().remove(&query.request) else {
+        return bad_request("invalid_request");
+    };
+
+    let code = CsrfToken::new_random().secret().clone();
+    issuer.grants.lock().unwrap().insert(
+        code.clone(),
+        Grant {
+            sub: query.sub,
+            nonce: pending.nonce,
+            code_challenge: pending.code_challenge,
+        },
+    );
+
+    let mut redirect = Url::parse(&pending.redirect_uri).unwrap();
+    redirect
+        .query_pairs_mut()
+        .append_pair("code", &code)
+        .append_pair("state", &pending.state);
+    Redirect::to(redirect.as_str()).into_response()
+}
+
+async fn token(State(issuer): State<MockOidcIssuer>, Form(req): Form<TokenRequest>) -> Response {
+    let Some(grant) = issuer.grants.lock().unwrap().remove(&req.code) else {
+        return bad_request("invalid_grant");
+    };
+    if req.redirect_uri != issuer.redirect_url {
+        return bad_request("invalid_grant");
+    }
+    let challenge =
+        PkceCodeChallenge::from_code_verifier_sha256(&PkceCodeVerifier::new(req.code_verifier));
+    if challenge.as_str() != grant.code_challenge {
+        return bad_request("invalid_grant");
+    }
+
+    let now = current_epoch().as_secs();
+    let claims = CoreIdTokenClaims::new(
+        issuer.issuer.clone(),
+        vec![Audience::new(MOCK_OIDC_CLIENT_ID.into())],
+        DateTime::from_timestamp((now + ID_TOKEN_LIFETIME_SECS) as i64, 0).unwrap(),
+        DateTime::from_timestamp(now as i64, 0).unwrap(),
+        StandardClaims::new(SubjectIdentifier::new(grant.sub)),
+        EmptyAdditionalClaims {},
+    )
+    .set_nonce(grant.nonce.map(Nonce::new));
+
+    let access_token = AccessToken::new(CsrfToken::new_random().secret().clone());
+    let id_token = match CoreIdToken::new(
+        claims,
+        &*issuer.signing_key.read().unwrap(),
+        CoreJwsSigningAlgorithm::EdDsaEd25519,
+        Some(&access_token),
+        Some(&AuthorizationCode::new(req.code)),
+    ) {
+        Ok(id_token) => id_token,
+        Err(e) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        }
+    };
+
+    let res = CoreTokenResponse::new(
+        access_token,
+        CoreTokenType::Bearer,
+        CoreIdTokenFields::new(Some(id_token), EmptyExtraTokenFields {}),
+    );
+    Json(res).into_response()
+}
+
+async fn jwks(State(issuer): State<MockOidcIssuer>) -> Json<CoreJsonWebKeySet> {
+    let key = issuer.signing_key.read().unwrap().as_verification_key();
+    Json(CoreJsonWebKeySet::new(vec![key]))
+}
+
+/// Replace the signing key, tokens signed with the old key no longer verify
+async fn rotate(State(issuer): State<MockOidcIssuer>) -> StatusCode {
+    *issuer.signing_key.write().unwrap() = generate_signing_key();
+    StatusCode::NO_CONTENT
+}
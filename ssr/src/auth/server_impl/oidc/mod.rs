@@ -0,0 +1,399 @@
+pub mod config;
+pub mod guest_merge;
+mod jwks;
+pub mod links;
+#[cfg(feature = "mock-oidc-ssr")]
+pub mod mock;
+#[cfg(test)]
+mod tests;
+
+use std::{collections::HashMap, sync::Arc};
+
+use axum_extra::extract::SignedCookieJar;
+use candid::Principal;
+use ic_agent::{identity::Secp256k1Identity, Identity};
+use leptos::{expect_context, ServerFnError};
+use leptos_axum::ResponseOptions;
+use openidconnect::{
+    core::{
+        CoreAuthenticationFlow, CoreClient, CoreIdToken, CoreIdTokenClaims, CoreIdTokenVerifier,
+        CoreJsonWebKeySet, CoreJwsSigningAlgorithm,
+    },
+    reqwest::async_http_client,
+    AuthorizationCode, ClaimsVerificationError, ClientId, CsrfToken, IssuerUrl, Nonce,
+    PkceCodeChallenge, PkceCodeVerifier, Scope, SignatureVerificationError,
+};
+
+use crate::{
+    auth::{
+        server_impl::{fetch_identity_from_kv, login_user_identity, try_extract_identity},
+        GuestActivity, LoginOutcome,
+    },
+    component::auth_providers::ProviderKind,
+};
+
+use super::{
+    cookies, extract_principal_from_cookie,
+    login_methods::has_login_method,
+    set_cookies,
+    store::{KVStoreImpl, KvKey, RecordKind},
+};
+
+pub use jwks::{JwksCache, JwksError};
+
+/// Algorithms accepted for ID token signatures
+/// shared secret algorithms are excluded, tokens must be signed by the issuer's keys
+const ALLOWED_ID_TOKEN_ALGS: &[CoreJwsSigningAlgorithm] = &[
+    CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha256,
+    CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha384,
+    CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha512,
+    CoreJwsSigningAlgorithm::RsaSsaPssSha256,
+    CoreJwsSigningAlgorithm::RsaSsaPssSha384,
+    CoreJwsSigningAlgorithm::RsaSsaPssSha512,
+    CoreJwsSigningAlgorithm::EcdsaP256Sha256,
+    CoreJwsSigningAlgorithm::EcdsaP384Sha384,
+    CoreJwsSigningAlgorithm::EdDsaEd25519,
+];
+
+/// An OpenID Connect provider that users can log in with
+#[derive(Clone)]
+pub struct OidcProvider {
+    client: CoreClient,
+    client_id: ClientId,
+    issuer: IssuerUrl,
+    jwks: JwksCache,
+    scopes: Vec<Scope>,
+}
+
+impl OidcProvider {
+    pub fn new(
+        client: CoreClient,
+        client_id: ClientId,
+        issuer: IssuerUrl,
+        jwks: JwksCache,
+        scopes: Vec<Scope>,
+    ) -> Self {
+        Self {
+            client,
+            client_id,
+            issuer,
+            jwks,
+            scopes,
+        }
+    }
+
+    /// Verifies signature, audience, issuer and expiry of ID tokens
+    fn id_token_verifier(&self, jwks: CoreJsonWebKeySet) -> CoreIdTokenVerifier<'static> {
+        CoreIdTokenVerifier::new_public_client(self.client_id.clone(), self.issuer.clone(), jwks)
+            .set_allowed_algs(ALLOWED_ID_TOKEN_ALGS.iter().cloned())
+    }
+
+    /// Claims of `id_token` once verified against the issuer's keys and `nonce`
+    async fn verify_id_token(
+        &self,
+        id_token: &CoreIdToken,
+        nonce: &Nonce,
+    ) -> Result<CoreIdTokenClaims, IdTokenError> {
+        let jwks = self.jwks.get().await?;
+        match id_token.claims(&self.id_token_verifier(jwks), nonce) {
+            // The issuer may have rotated its keys since we cached them
+            Err(ClaimsVerificationError::SignatureVerification(
+                SignatureVerificationError::NoMatchingKey,
+            )) => {
+                let Some(jwks) = self.jwks.refresh().await? else {
+                    return Err(ClaimsVerificationError::SignatureVerification(
+                        SignatureVerificationError::NoMatchingKey,
+                    )
+                    .into());
+                };
+                Ok(id_token
+                    .claims(&self.id_token_verifier(jwks), nonce)?
+                    .clone())
+            }
+            res => Ok(res?.clone()),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum IdTokenError {
+    #[error(transparent)]
+    Claims(#[from] ClaimsVerificationError),
+    #[error(transparent)]
+    Jwks(#[from] JwksError),
+}
+
+/// Registry of the OpenID Connect providers enabled on this server
+#[derive(Clone, Default)]
+pub struct OidcProviders(Arc<HashMap<ProviderKind, OidcProvider>>);
+
+impl OidcProviders {
+    pub fn new(providers: HashMap<ProviderKind, OidcProvider>) -> Self {
+        Self(Arc::new(providers))
+    }
+
+    /// Providers that are configured, in a stable order
+    pub fn enabled(&self) -> Vec<ProviderKind> {
+        let mut enabled: Vec<_> = self.0.keys().copied().collect();
+        enabled.sort();
+        enabled
+    }
+
+    fn get(&self, provider: ProviderKind) -> Result<&OidcProvider, ServerFnError> {
+        self.0.get(&provider).ok_or_else(|| {
+            ServerFnError::new(format!("{} login is not enabled", provider.as_str()))
+        })
+    }
+}
+
+fn pkce_verifier_cookie(provider: ProviderKind) -> String {
+    format!("{}-pkce-verifier", provider.as_str())
+}
+
+fn csrf_token_cookie(provider: ProviderKind) -> String {
+    format!("{}-csrf-token", provider.as_str())
+}
+
+fn nonce_cookie(provider: ProviderKind) -> String {
+    format!("{}-nonce", provider.as_str())
+}
+
+/// Present when the login links the provider to the current principal
+fn link_intent_cookie(provider: ProviderKind) -> String {
+    format!("{}-link-intent", provider.as_str())
+}
+
+/// `link` links the provider to the current principal instead of logging in
+pub async fn oidc_auth_url_impl(
+    provider: ProviderKind,
+    link: bool,
+) -> Result<String, ServerFnError> {
+    let providers: OidcProviders = expect_context();
+    let oidc = providers.get(provider)?;
+    let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+    let (auth_url, csrf_token, nonce) = oidc
+        .client
+        .authorize_url(
+            CoreAuthenticationFlow::AuthorizationCode,
+            CsrfToken::new_random,
+            Nonce::new_random,
+        )
+        .add_scopes(oidc.scopes.iter().cloned())
+        .set_pkce_challenge(pkce_challenge)
+        .url();
+
+    let mut jar = cookies::private_jar().await?;
+
+    jar = jar.add(cookies::flow_cookie(
+        pkce_verifier_cookie(provider),
+        pkce_verifier.secret().clone(),
+    ));
+    jar = jar.add(cookies::flow_cookie(
+        csrf_token_cookie(provider),
+        csrf_token.secret().clone(),
+    ));
+    jar = jar.add(cookies::flow_cookie(
+        nonce_cookie(provider),
+        nonce.secret().clone(),
+    ));
+    if link {
+        jar = jar.add(cookies::flow_cookie(link_intent_cookie(provider), "1"));
+    } else {
+        jar = jar.remove(link_intent_cookie(provider));
+    }
+
+    let resp: ResponseOptions = expect_context();
+    set_cookies(&resp, jar);
+
+    Ok(auth_url.to_string())
+}
+
+/// KV key mapping a provider's subject to a principal
+/// `google-login-{sub}` for Google, kept stable for existing users
+pub fn principal_lookup_key(provider: ProviderKind, sub_id: &str) -> KvKey {
+    KvKey::new(
+        RecordKind::OAuth,
+        format!("{}-login-{}", provider.as_str(), sub_id),
+    )
+}
+
+async fn try_extract_identity_from_sub(
+    kv: &KVStoreImpl,
+    provider: ProviderKind,
+    sub_id: &str,
+) -> Result<Option<Secp256k1Identity>, ServerFnError> {
+    let Some(principal) = links::principal_for_sub(kv, provider, sub_id).await? else {
+        return Ok(None);
+    };
+    let Some(identity_secret) = fetch_identity_from_kv(kv, principal).await? else {
+        return Ok(None);
+    };
+    links::index_sub(kv, principal, provider, sub_id).await?;
+
+    Ok(Some(Secp256k1Identity::from_private_key(identity_secret)))
+}
+
+async fn extract_identity_and_associate_with_sub(
+    kv: &KVStoreImpl,
+    jar: &SignedCookieJar,
+    provider: ProviderKind,
+    sub_id: &str,
+) -> Result<Secp256k1Identity, ServerFnError> {
+    let identity_secret = try_extract_identity(jar, kv).await?.ok_or_else(|| {
+        ServerFnError::new(format!(
+            "Attempting {} login without an identity",
+            provider.as_str()
+        ))
+    })?;
+    let identity = Secp256k1Identity::from_private_key(identity_secret);
+    let principal = identity.sender().unwrap();
+    links::link_sub(kv, principal, provider, sub_id).await?;
+
+    Ok(identity)
+}
+
+/// Link `sub_id` to the principal currently logged in
+async fn link_sub_to_current_identity(
+    kv: &KVStoreImpl,
+    jar: &SignedCookieJar,
+    provider: ProviderKind,
+    sub_id: &str,
+) -> Result<Secp256k1Identity, ServerFnError> {
+    let identity_secret = try_extract_identity(jar, kv)
+        .await?
+        .ok_or_else(|| ServerFnError::new("Not logged in"))?;
+    let identity = Secp256k1Identity::from_private_key(identity_secret);
+    let principal = identity.sender().unwrap();
+
+    match links::principal_for_sub(kv, provider, sub_id).await? {
+        Some(linked) if linked == principal => return Ok(identity),
+        Some(_) => {
+            return Err(ServerFnError::new(format!(
+                "This {} account is linked to another user",
+                provider.as_str()
+            )))
+        }
+        None => (),
+    }
+    if links::linked_providers(kv, principal)
+        .await?
+        .contains(&provider)
+    {
+        return Err(ServerFnError::new(format!(
+            "Another {} account is already linked",
+            provider.as_str()
+        )));
+    }
+    links::link_sub(kv, principal, provider, sub_id).await?;
+
+    Ok(identity)
+}
+
+/// Activity of the current guest that logging into `account` would lose
+/// `None` if the current principal is `account` or isn't a guest
+async fn activity_lost_by_switching(
+    kv: &KVStoreImpl,
+    jar: &SignedCookieJar,
+    account: Principal,
+) -> Result<Option<(Principal, GuestActivity)>, ServerFnError> {
+    let Some(guest) = extract_principal_from_cookie(jar, kv).await? else {
+        return Ok(None);
+    };
+    if guest == account || has_login_method(kv, guest).await? {
+        return Ok(None);
+    }
+    let activity = guest_merge::guest_activity(guest).await?;
+    Ok((!activity.is_empty()).then_some((guest, activity)))
+}
+
+pub async fn perform_oidc_auth_impl(
+    provider: ProviderKind,
+    provided_csrf: String,
+    auth_code: String,
+) -> Result<LoginOutcome, ServerFnError> {
+    let providers: OidcProviders = expect_context();
+    let oidc = providers.get(provider)?;
+
+    let mut jar = cookies::private_jar().await?;
+
+    let csrf_cookie_name = csrf_token_cookie(provider);
+    let csrf_cookie = jar
+        .get(&csrf_cookie_name)
+        .ok_or_else(|| ServerFnError::new("CSRF token cookie not found"))?;
+    if provided_csrf != csrf_cookie.value() {
+        return Err(ServerFnError::new("CSRF token mismatch"));
+    }
+
+    let pkce_cookie_name = pkce_verifier_cookie(provider);
+    let pkce_cookie = jar
+        .get(&pkce_cookie_name)
+        .ok_or_else(|| ServerFnError::new("PKCE verifier cookie not found"))?;
+    let pkce_verifier = PkceCodeVerifier::new(pkce_cookie.value().to_owned());
+
+    let nonce_cookie_name = nonce_cookie(provider);
+    let nonce = jar
+        .get(&nonce_cookie_name)
+        .map(|cookie| Nonce::new(cookie.value().to_owned()))
+        .ok_or_else(|| ServerFnError::new("Nonce cookie not found"))?;
+
+    let link_cookie_name = link_intent_cookie(provider);
+    let link = jar.get(&link_cookie_name).is_some();
+
+    jar = jar.remove(pkce_cookie_name);
+    jar = jar.remove(csrf_cookie_name);
+    jar = jar.remove(nonce_cookie_name);
+    jar = jar.remove(link_cookie_name);
+    let resp: ResponseOptions = expect_context();
+    set_cookies(&resp, jar);
+
+    let token_res = oidc
+        .client
+        .exchange_code(AuthorizationCode::new(auth_code))
+        .set_pkce_verifier(pkce_verifier)
+        .request_async(async_http_client)
+        .await?;
+
+    let id_token = token_res.extra_fields().id_token().ok_or_else(|| {
+        ServerFnError::new(format!("{} did not return an ID token", provider.as_str()))
+    })?;
+    let claims = oidc.verify_id_token(id_token, &nonce).await?;
+    let sub_id = claims.subject().as_str();
+
+    let kv: KVStoreImpl = expect_context();
+    let jar = cookies::signed_jar().await?;
+    let identity = if link {
+        link_sub_to_current_identity(&kv, &jar, provider, sub_id).await?
+    } else if let Some(identity) = try_extract_identity_from_sub(&kv, provider, sub_id).await? {
+        let account = identity.sender().unwrap();
+        if let Some((guest, activity)) = activity_lost_by_switching(&kv, &jar, account).await? {
+            guest_merge::defer_login(guest, account).await?;
+            return Ok(LoginOutcome::GuestHasActivity(activity));
+        }
+        identity
+    } else {
+        extract_identity_and_associate_with_sub(&kv, &jar, provider, sub_id).await?
+    };
+
+    let delegated = login_user_identity(&resp, jar, identity).await?;
+
+    Ok(LoginOutcome::LoggedIn(delegated))
+}
+
+/// Providers linked to the principal currently logged in
+pub async fn linked_login_methods_impl() -> Result<Vec<ProviderKind>, ServerFnError> {
+    let jar = cookies::signed_jar().await?;
+    let kv: KVStoreImpl = expect_context();
+    let Some(principal) = extract_principal_from_cookie(&jar, &kv).await? else {
+        return Ok(vec![]);
+    };
+    links::linked_providers(&kv, principal).await
+}
+
+pub async fn unlink_login_method_impl(provider: ProviderKind) -> Result<(), ServerFnError> {
+    let jar = cookies::signed_jar().await?;
+    let kv: KVStoreImpl = expect_context();
+    let principal = extract_principal_from_cookie(&jar, &kv)
+        .await?
+        .ok_or_else(|| ServerFnError::new("Not logged in"))?;
+    links::unlink_provider(&kv, principal, provider).await
+}
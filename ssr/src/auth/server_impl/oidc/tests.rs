@@ -0,0 +1,308 @@
+//! ID token verification against a local issuer and JWKS endpoint
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, RwLock,
+};
+
+use axum::{extract::State, routing::get, Json, Router};
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{
+    pkcs8::{EncodePrivateKey, LineEnding},
+    SigningKey,
+};
+use openidconnect::{
+    core::{
+        CoreClient, CoreEdDsaPrivateSigningKey, CoreIdToken, CoreIdTokenClaims, CoreJsonWebKey,
+        CoreJsonWebKeySet, CoreJwsSigningAlgorithm,
+    },
+    Audience, AuthUrl, ClaimsVerificationError, ClientId, EmptyAdditionalClaims, IssuerUrl,
+    JsonWebKeyId, JsonWebKeySetUrl, Nonce, PrivateSigningKey, SignatureVerificationError,
+    StandardClaims, SubjectIdentifier,
+};
+
+use super::{IdTokenError, JwksCache, OidcProvider};
+
+const ISSUER: &str = "https://issuer.test";
+const CLIENT_ID: &str = "yral-test";
+const NONCE: &str = "test-nonce";
+
+fn signing_key(kid: &str) -> CoreEdDsaPrivateSigningKey {
+    let key = SigningKey::from_bytes(&rand::random());
+    let pem = key.to_pkcs8_pem(LineEnding::LF).unwrap();
+    CoreEdDsaPrivateSigningKey::from_ed25519_pem(&pem, Some(JsonWebKeyId::new(kid.into()))).unwrap()
+}
+
+/// JWKS endpoint serving `keys`, counting fetches
+#[derive(Clone, Default)]
+struct FakeJwks {
+    keys: Arc<RwLock<Vec<CoreJsonWebKey>>>,
+    fetches: Arc<AtomicUsize>,
+}
+
+impl FakeJwks {
+    fn publish(&self, key: &CoreEdDsaPrivateSigningKey) {
+        *self.keys.write().unwrap() = vec![key.as_verification_key()];
+    }
+
+    fn fetches(&self) -> usize {
+        self.fetches.load(Ordering::SeqCst)
+    }
+
+    async fn serve(&self) -> JsonWebKeySetUrl {
+        async fn jwks(State(fake): State<FakeJwks>) -> Json<CoreJsonWebKeySet> {
+            fake.fetches.fetch_add(1, Ordering::SeqCst);
+            let keys = fake.keys.read().unwrap().clone();
+            Json(CoreJsonWebKeySet::new(keys))
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new()
+            .route("/jwks", get(jwks))
+            .with_state(self.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        JsonWebKeySetUrl::new(format!("http://{addr}/jwks")).unwrap()
+    }
+}
+
+async fn provider(fake: &FakeJwks) -> OidcProvider {
+    let client_id = ClientId::new(CLIENT_ID.into());
+    let issuer = IssuerUrl::new(ISSUER.into()).unwrap();
+    let client = CoreClient::new(
+        client_id.clone(),
+        None,
+        issuer.clone(),
+        AuthUrl::new(format!("{ISSUER}/authorize")).unwrap(),
+        None,
+        None,
+        Default::default(),
+    );
+    OidcProvider::new(
+        client,
+        client_id,
+        issuer,
+        JwksCache::new(fake.serve().await),
+        vec![],
+    )
+}
+
+struct TokenParams {
+    issuer: &'static str,
+    audience: &'static str,
+    expiry: DateTime<Utc>,
+    nonce: &'static str,
+}
+
+impl Default for TokenParams {
+    fn default() -> Self {
+        Self {
+            issuer: ISSUER,
+            audience: CLIENT_ID,
+            expiry: Utc::now() + chrono::Duration::hours(1),
+            nonce: NONCE,
+        }
+    }
+}
+
+fn id_token(key: &CoreEdDsaPrivateSigningKey, params: TokenParams) -> CoreIdToken {
+    let claims = CoreIdTokenClaims::new(
+        IssuerUrl::new(params.issuer.into()).unwrap(),
+        vec![Audience::new(params.audience.into())],
+        params.expiry,
+        Utc::now(),
+        StandardClaims::new(SubjectIdentifier::new("test-user".into())),
+        EmptyAdditionalClaims {},
+    )
+    .set_nonce(Some(Nonce::new(params.nonce.into())));
+    CoreIdToken::new(
+        claims,
+        key,
+        CoreJwsSigningAlgorithm::EdDsaEd25519,
+        None,
+        None,
+    )
+    .unwrap()
+}
+
+async fn verify(provider: &OidcProvider, token: &CoreIdToken) -> Result<String, IdTokenError> {
+    let claims = provider
+        .verify_id_token(token, &Nonce::new(NONCE.into()))
+        .await?;
+    Ok(claims.subject().to_string())
+}
+
+fn is_no_matching_key(res: &Result<String, IdTokenError>) -> bool {
+    matches!(
+        res,
+        Err(IdTokenError::Claims(
+            ClaimsVerificationError::SignatureVerification(
+                SignatureVerificationError::NoMatchingKey
+            )
+        ))
+    )
+}
+
+#[tokio::test]
+async fn accepts_valid_token() {
+    let fake = FakeJwks::default();
+    let key = signing_key("key-1");
+    fake.publish(&key);
+    let provider = provider(&fake).await;
+
+    let token = id_token(&key, TokenParams::default());
+    assert_eq!(verify(&provider, &token).await.unwrap(), "test-user");
+}
+
+#[tokio::test]
+async fn rejects_bad_signature() {
+    let fake = FakeJwks::default();
+    fake.publish(&signing_key("key-1"));
+    let provider = provider(&fake).await;
+
+    // same key id, different key
+    let forged = id_token(&signing_key("key-1"), TokenParams::default());
+    let res = verify(&provider, &forged).await;
+    assert!(matches!(
+        res,
+        Err(IdTokenError::Claims(
+            ClaimsVerificationError::SignatureVerification(_)
+        ))
+    ));
+    assert!(!is_no_matching_key(&res));
+}
+
+#[tokio::test]
+async fn rejects_other_audience() {
+    let fake = FakeJwks::default();
+    let key = signing_key("key-1");
+    fake.publish(&key);
+    let provider = provider(&fake).await;
+
+    let token = id_token(
+        &key,
+        TokenParams {
+            audience: "another-client",
+            ..Default::default()
+        },
+    );
+    assert!(matches!(
+        verify(&provider, &token).await,
+        Err(IdTokenError::Claims(
+            ClaimsVerificationError::InvalidAudience(_)
+        ))
+    ));
+}
+
+#[tokio::test]
+async fn rejects_other_issuer() {
+    let fake = FakeJwks::default();
+    let key = signing_key("key-1");
+    fake.publish(&key);
+    let provider = provider(&fake).await;
+
+    let token = id_token(
+        &key,
+        TokenParams {
+            issuer: "https://another-issuer.test",
+            ..Default::default()
+        },
+    );
+    assert!(matches!(
+        verify(&provider, &token).await,
+        Err(IdTokenError::Claims(
+            ClaimsVerificationError::InvalidIssuer(_)
+        ))
+    ));
+}
+
+#[tokio::test]
+async fn rejects_expired_token() {
+    let fake = FakeJwks::default();
+    let key = signing_key("key-1");
+    fake.publish(&key);
+    let provider = provider(&fake).await;
+
+    let token = id_token(
+        &key,
+        TokenParams {
+            expiry: Utc::now() - chrono::Duration::minutes(5),
+            ..Default::default()
+        },
+    );
+    assert!(matches!(
+        verify(&provider, &token).await,
+        Err(IdTokenError::Claims(ClaimsVerificationError::Expired(_)))
+    ));
+}
+
+#[tokio::test]
+async fn rejects_wrong_nonce() {
+    let fake = FakeJwks::default();
+    let key = signing_key("key-1");
+    fake.publish(&key);
+    let provider = provider(&fake).await;
+
+    let token = id_token(
+        &key,
+        TokenParams {
+            nonce: "another-nonce",
+            ..Default::default()
+        },
+    );
+    assert!(matches!(
+        verify(&provider, &token).await,
+        Err(IdTokenError::Claims(ClaimsVerificationError::InvalidNonce(
+            _
+        )))
+    ));
+}
+
+#[tokio::test]
+async fn refreshes_keys_once_rotated() {
+    let fake = FakeJwks::default();
+    let old_key = signing_key("key-1");
+    fake.publish(&old_key);
+    let provider = provider(&fake).await;
+
+    let token = id_token(&old_key, TokenParams::default());
+    verify(&provider, &token).await.unwrap();
+    assert_eq!(fake.fetches(), 1);
+
+    let new_key = signing_key("key-2");
+    fake.publish(&new_key);
+    let token = id_token(&new_key, TokenParams::default());
+    assert_eq!(verify(&provider, &token).await.unwrap(), "test-user");
+    assert_eq!(fake.fetches(), 2);
+
+    // tokens signed with the new key verify with the cached keys
+    verify(&provider, &token).await.unwrap();
+    assert_eq!(fake.fetches(), 2);
+}
+
+#[tokio::test]
+async fn throttles_refresh_on_unknown_keys() {
+    let fake = FakeJwks::default();
+    let key = signing_key("key-1");
+    fake.publish(&key);
+    let provider = provider(&fake).await;
+
+    let unknown = signing_key("unknown-1");
+    let res = verify(&provider, &id_token(&unknown, TokenParams::default())).await;
+    assert!(is_no_matching_key(&res));
+    // fetched, then refreshed once
+    assert_eq!(fake.fetches(), 2);
+
+    for i in 2..10 {
+        let unknown = signing_key(&format!("unknown-{i}"));
+        let res = verify(&provider, &id_token(&unknown, TokenParams::default())).await;
+        assert!(is_no_matching_key(&res));
+    }
+    assert_eq!(fake.fetches(), 2);
+
+    // known keys still verify while refreshes are throttled
+    verify(&provider, &id_token(&key, TokenParams::default()))
+        .await
+        .unwrap();
+}
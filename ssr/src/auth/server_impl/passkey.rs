@@ -0,0 +1,348 @@
+//! Passkey (WebAuthn) login
+//!
+//! Only ES256 credentials are accepted. The browser extracts the credential's public key
+//! (`AuthenticatorAttestationResponse.getPublicKey()`), so attestation objects are never parsed
+//!
+//! `passkey-challenge-{challenge}` holds pending challenges,
+//! `passkey-{credential id}` maps a credential to its principal and public key,
+//! `passkeys-{principal}` lists the credentials registered by a principal
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use candid::Principal;
+use ic_agent::identity::Secp256k1Identity;
+use leptos::{expect_context, ServerFnError};
+use leptos_axum::ResponseOptions;
+use p256::{
+    ecdsa::{signature::Verifier, Signature, VerifyingKey},
+    pkcs8::DecodePublicKey,
+};
+use rand_chacha::rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use web_time::Duration;
+
+use crate::{
+    auth::DelegatedIdentityWire,
+    component::auth_providers::passkey::{
+        PasskeyAssertion, PasskeyOptions, PasskeyRegistration, PasskeyUser,
+    },
+    utils::current_epoch,
+};
+
+use super::{
+    cookies, extract_principal_from_cookie, fetch_identity_from_kv, guest_gc, login_user_identity,
+    store::{KVStore, KVStoreImpl, KvKey, RecordKind},
+};
+
+/// COSE algorithm identifier of ES256 (ECDSA P-256 with SHA-256)
+const COSE_ALG_ES256: i64 = -7;
+const CHALLENGE_MAX_AGE: Duration = Duration::from_secs(60 * 5);
+/// Authenticator data flag, set if the user was present
+const FLAG_USER_PRESENT: u8 = 0x01;
+
+/// Relying party the passkeys are scoped to
+#[derive(Clone)]
+pub struct PasskeyConfig {
+    /// Domain of the site, e.g `yral.com`
+    pub rp_id: String,
+    pub rp_name: String,
+    /// Origin the browser reports, e.g `https://yral.com`
+    pub origin: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PendingChallenge {
+    /// Principal registering a passkey, `None` for logins
+    principal: Option<Principal>,
+    expiry_epoch_ms: u128,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredPasskey {
+    principal: Principal,
+    /// base64url encoded SubjectPublicKeyInfo
+    public_key: String,
+    sign_count: u32,
+}
+
+/// `clientDataJSON` of a WebAuthn response
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    ty: String,
+    challenge: String,
+    origin: String,
+}
+
+fn challenge_key(challenge: &str) -> KvKey {
+    KvKey::new(
+        RecordKind::Passkey,
+        format!("passkey-challenge-{challenge}"),
+    )
+}
+
+fn credential_key(credential_id: &str) -> KvKey {
+    KvKey::new(RecordKind::Passkey, format!("passkey-{credential_id}"))
+}
+
+fn principal_credentials_key(principal: Principal) -> KvKey {
+    KvKey::new(
+        RecordKind::Passkey,
+        format!("passkeys-{}", principal.to_text()),
+    )
+}
+
+fn decode(field: &str, value: &str) -> Result<Vec<u8>, ServerFnError> {
+    URL_SAFE_NO_PAD
+        .decode(value)
+        .map_err(|_| ServerFnError::new(format!("Invalid passkey {field}")))
+}
+
+async fn issue_challenge(
+    kv: &KVStoreImpl,
+    principal: Option<Principal>,
+) -> Result<String, ServerFnError> {
+    let mut raw = [0u8; 32];
+    OsRng.fill_bytes(&mut raw);
+    let challenge = URL_SAFE_NO_PAD.encode(raw);
+
+    let pending = PendingChallenge {
+        principal,
+        expiry_epoch_ms: (current_epoch() + CHALLENGE_MAX_AGE).as_millis(),
+    };
+    kv.write_with_ttl(
+        challenge_key(&challenge),
+        serde_json::to_string(&pending)?,
+        CHALLENGE_MAX_AGE,
+    )
+    .await?;
+    Ok(challenge)
+}
+
+/// Verify `clientDataJSON` and consume the challenge it answers
+async fn take_challenge(
+    kv: &KVStoreImpl,
+    client_data_json: &[u8],
+    expected_type: &str,
+) -> Result<PendingChallenge, ServerFnError> {
+    let config: PasskeyConfig = expect_context();
+    let client_data: ClientData = serde_json::from_slice(client_data_json)?;
+    if client_data.ty != expected_type {
+        return Err(ServerFnError::new("Unexpected passkey response type"));
+    }
+    if client_data.origin != config.origin {
+        return Err(ServerFnError::new("Passkey response from another origin"));
+    }
+
+    let key = challenge_key(&client_data.challenge);
+    let Some(raw) = kv.read(key.clone()).await? else {
+        return Err(ServerFnError::new("Unknown passkey challenge"));
+    };
+    // only the request that removes the challenge may use it
+    if !kv.delete(key).await? {
+        return Err(ServerFnError::new("Unknown passkey challenge"));
+    }
+    let pending: PendingChallenge = serde_json::from_str(&raw)?;
+    if current_epoch().as_millis() > pending.expiry_epoch_ms {
+        return Err(ServerFnError::new("Passkey challenge expired"));
+    }
+    Ok(pending)
+}
+
+/// Verify the authenticator data, returning its signature counter
+fn verify_authenticator_data(auth_data: &[u8]) -> Result<u32, ServerFnError> {
+    let config: PasskeyConfig = expect_context();
+    if auth_data.len() < 37 {
+        return Err(ServerFnError::new("Invalid passkey authenticator data"));
+    }
+    if auth_data[..32] != Sha256::digest(config.rp_id.as_bytes())[..] {
+        return Err(ServerFnError::new("Passkey belongs to another site"));
+    }
+    if auth_data[32] & FLAG_USER_PRESENT == 0 {
+        return Err(ServerFnError::new("Passkey user presence not verified"));
+    }
+    Ok(u32::from_be_bytes(auth_data[33..37].try_into().unwrap()))
+}
+
+async fn read_credential_ids(
+    kv: &KVStoreImpl,
+    principal: Principal,
+) -> Result<Vec<String>, ServerFnError> {
+    let Some(raw) = kv.read(principal_credentials_key(principal)).await? else {
+        return Ok(vec![]);
+    };
+    Ok(serde_json::from_str(&raw)?)
+}
+
+/// Number of passkeys registered by `principal`
+/// listed credentials that were never stored aren't counted
+pub async fn credential_count(
+    kv: &KVStoreImpl,
+    principal: Principal,
+) -> Result<usize, ServerFnError> {
+    let ids = read_credential_ids(kv, principal).await?;
+    if ids.is_empty() {
+        return Ok(0);
+    }
+    let stored = kv
+        .read_many(ids.iter().map(|id| credential_key(id)).collect())
+        .await?;
+    let mut count = 0;
+    for raw in stored.into_iter().flatten() {
+        let passkey: StoredPasskey = serde_json::from_str(&raw)?;
+        if passkey.principal == principal {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+async fn login_as(principal: Principal) -> Result<DelegatedIdentityWire, ServerFnError> {
+    let kv: KVStoreImpl = expect_context();
+    let identity_secret = fetch_identity_from_kv(&kv, principal)
+        .await?
+        .ok_or_else(|| ServerFnError::new("Passkey identity not found"))?;
+    let identity = Secp256k1Identity::from_private_key(identity_secret);
+
+    let jar = cookies::signed_jar().await?;
+    let resp: ResponseOptions = expect_context();
+    login_user_identity(&resp, jar, identity).await
+}
+
+/// Options to register a passkey for the principal currently logged in
+pub async fn passkey_registration_options_impl() -> Result<PasskeyOptions, ServerFnError> {
+    let config: PasskeyConfig = expect_context();
+    let kv: KVStoreImpl = expect_context();
+    let jar = cookies::signed_jar().await?;
+    let principal = extract_principal_from_cookie(&jar, &kv)
+        .await?
+        .ok_or_else(|| ServerFnError::new("Not logged in"))?;
+
+    let principal_text = principal.to_text();
+    Ok(PasskeyOptions {
+        challenge: issue_challenge(&kv, Some(principal)).await?,
+        rp_id: config.rp_id,
+        rp_name: config.rp_name,
+        user: Some(PasskeyUser {
+            id: URL_SAFE_NO_PAD.encode(principal.as_slice()),
+            name: principal_text,
+        }),
+        exclude_credentials: read_credential_ids(&kv, principal).await?,
+    })
+}
+
+/// Register a passkey for the principal the challenge was issued to, and log in with it
+pub async fn finish_passkey_registration_impl(
+    registration: PasskeyRegistration,
+) -> Result<DelegatedIdentityWire, ServerFnError> {
+    let kv: KVStoreImpl = expect_context();
+    let client_data_json = decode("client data", &registration.client_data_json)?;
+    let pending = take_challenge(&kv, &client_data_json, "webauthn.create").await?;
+    let principal = pending
+        .principal
+        .ok_or_else(|| ServerFnError::new("Not a registration challenge"))?;
+    let jar = cookies::signed_jar().await?;
+    if extract_principal_from_cookie(&jar, &kv).await? != Some(principal) {
+        return Err(ServerFnError::new(
+            "Passkey registration from another session",
+        ));
+    }
+
+    if registration.public_key_algorithm != COSE_ALG_ES256 {
+        return Err(ServerFnError::new("Unsupported passkey algorithm"));
+    }
+    let public_key = decode("public key", &registration.public_key)?;
+    VerifyingKey::from_public_key_der(&public_key)
+        .map_err(|_| ServerFnError::new("Invalid passkey public key"))?;
+    let sign_count = verify_authenticator_data(&decode(
+        "authenticator data",
+        &registration.authenticator_data,
+    )?)?;
+
+    let stored = StoredPasskey {
+        principal,
+        public_key: registration.public_key,
+        sign_count,
+    };
+    let cred_key = credential_key(&registration.id);
+    // claim the credential id atomically, concurrent registrations of the same passkey can't both win
+    if !kv
+        .compare_and_set(
+            cred_key.clone(),
+            None,
+            serde_json::to_string(&stored)?,
+            None,
+        )
+        .await?
+    {
+        return Err(ServerFnError::new("Passkey already registered"));
+    }
+    let mut credential_ids = read_credential_ids(&kv, principal).await?;
+    credential_ids.push(registration.id.clone());
+    if let Err(e) = kv
+        .write(
+            principal_credentials_key(principal),
+            serde_json::to_string(&credential_ids)?,
+        )
+        .await
+    {
+        // an unlisted credential couldn't be managed, release it
+        kv.delete(cred_key).await?;
+        return Err(e.into());
+    }
+    guest_gc::mark_linked(&kv, principal).await?;
+
+    login_as(principal).await
+}
+
+/// Options to log in with any passkey registered on this site
+pub async fn passkey_login_options_impl() -> Result<PasskeyOptions, ServerFnError> {
+    let config: PasskeyConfig = expect_context();
+    let kv: KVStoreImpl = expect_context();
+    Ok(PasskeyOptions {
+        challenge: issue_challenge(&kv, None).await?,
+        rp_id: config.rp_id,
+        rp_name: config.rp_name,
+        user: None,
+        exclude_credentials: vec![],
+    })
+}
+
+pub async fn perform_passkey_auth_impl(
+    assertion: PasskeyAssertion,
+) -> Result<DelegatedIdentityWire, ServerFnError> {
+    let kv: KVStoreImpl = expect_context();
+    let client_data_json = decode("client data", &assertion.client_data_json)?;
+    take_challenge(&kv, &client_data_json, "webauthn.get").await?;
+
+    let cred_key = credential_key(&assertion.id);
+    let mut stored: StoredPasskey = kv
+        .read(cred_key.clone())
+        .await?
+        .map(|raw| serde_json::from_str(&raw))
+        .transpose()?
+        .ok_or_else(|| ServerFnError::new("Unknown passkey"))?;
+
+    let auth_data = decode("authenticator data", &assertion.authenticator_data)?;
+    let sign_count = verify_authenticator_data(&auth_data)?;
+    let public_key = VerifyingKey::from_public_key_der(&decode("public key", &stored.public_key)?)
+        .map_err(|_| ServerFnError::new("Invalid passkey public key"))?;
+    let signature = Signature::from_der(&decode("signature", &assertion.signature)?)
+        .map_err(|_| ServerFnError::new("Invalid passkey signature"))?;
+    let mut signed = auth_data;
+    signed.extend_from_slice(&Sha256::digest(&client_data_json));
+    public_key
+        .verify(&signed, &signature)
+        .map_err(|_| ServerFnError::new("Invalid passkey signature"))?;
+
+    // authenticators that don't count signatures always report 0
+    if sign_count != 0 || stored.sign_count != 0 {
+        if sign_count <= stored.sign_count {
+            return Err(ServerFnError::new("Passkey may have been cloned"));
+        }
+        stored.sign_count = sign_count;
+        kv.write(cred_key, serde_json::to_string(&stored)?).await?;
+    }
+
+    login_as(stored.principal).await
+}
@@ -0,0 +1,81 @@
+//! Recovery phrases for base identities
+//!
+//! The phrase is the BIP-39 encoding of the identity's secp256k1 secret key (24 words),
+//! so an identity can be restored even if it was removed from the KV store
+
+use bip39::Mnemonic;
+use ic_agent::{identity::Secp256k1Identity, Identity};
+use leptos::{expect_context, ServerFnError};
+use leptos_axum::ResponseOptions;
+use web_time::Duration;
+
+use crate::auth::DelegatedIdentityWire;
+
+use super::{
+    cookies, extract_refresh_token, fetch_identity_from_kv, guest_gc, login_methods,
+    login_user_identity, save_identity, session, store::KVStoreImpl,
+};
+
+/// Sensitive actions need a login at most this old
+const RECENT_LOGIN_MAX_AGE: Duration = Duration::from_secs(5 * 60);
+
+/// Recovery phrase of the identity currently logged in
+/// accounts with a login method must have logged in within [RECENT_LOGIN_MAX_AGE],
+/// a guest's refresh token is its only credential, so it can always back up
+pub async fn recovery_phrase_impl() -> Result<String, ServerFnError> {
+    let jar = cookies::signed_jar().await?;
+    let kv: KVStoreImpl = expect_context();
+    let token = extract_refresh_token(&jar, &kv)
+        .await?
+        .ok_or_else(|| ServerFnError::new("Not logged in"))?;
+    let principal = token.principal;
+    let session_id = token.session_id.unwrap_or_default();
+
+    if login_methods::has_login_method(&kv, principal).await?
+        && !session::authenticated_within(&kv, &session_id, RECENT_LOGIN_MAX_AGE).await?
+    {
+        log::warn!(
+            "recovery phrase of {principal} refused, session {session_id} needs to log in again"
+        );
+        return Err(ServerFnError::new(
+            "Log in again to view your recovery phrase",
+        ));
+    }
+    let secret = fetch_identity_from_kv(&kv, principal)
+        .await?
+        .ok_or_else(|| ServerFnError::new("Not logged in"))?;
+
+    // backed up identities are kept even if they're never used again
+    guest_gc::mark_linked(&kv, principal).await?;
+
+    let mnemonic = Mnemonic::from_entropy(&secret.to_bytes())?;
+    log::info!("recovery phrase of {principal} revealed to session {session_id}");
+    Ok(mnemonic.to_string())
+}
+
+/// Restore the identity encoded by `phrase` and log in with it
+pub async fn recover_identity_impl(phrase: String) -> Result<DelegatedIdentityWire, ServerFnError> {
+    let normalized = phrase
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mnemonic = Mnemonic::parse_normalized(&normalized)
+        .map_err(|_| ServerFnError::new("Invalid recovery phrase"))?;
+    let secret = k256::SecretKey::from_slice(&mnemonic.to_entropy())
+        .map_err(|_| ServerFnError::new("Invalid recovery phrase"))?;
+
+    let kv: KVStoreImpl = expect_context();
+    let principal = Secp256k1Identity::from_private_key(secret.clone())
+        .sender()
+        .map_err(ServerFnError::new)?;
+    let identity = if fetch_identity_from_kv(&kv, principal).await?.is_some() {
+        Secp256k1Identity::from_private_key(secret)
+    } else {
+        save_identity(&kv, secret).await?
+    };
+
+    let jar = cookies::signed_jar().await?;
+    let resp: ResponseOptions = expect_context();
+    login_user_identity(&resp, jar, identity).await
+}
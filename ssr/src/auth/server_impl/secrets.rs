@@ -0,0 +1,188 @@
+//! Envelope encryption of identity secrets at rest
+//!
+//! Every record is encrypted with a random data key (AES-256-GCM),
+//! the data key is wrapped with a key encryption key derived from configuration.
+//! The key id stored with the record selects the key encryption key when decrypting,
+//! so keys can be rotated by moving the old key to the retired list.
+//! Records written before encryption was introduced are plaintext secp256k1 JWKs
+
+#[cfg(test)]
+mod tests;
+
+use std::sync::Arc;
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use hkdf::Hkdf;
+use k256::elliptic_curve::zeroize::Zeroizing;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use thiserror::Error;
+
+const KEK_INFO: &[u8] = b"yral-identity-kek";
+const KEY_ID_INFO: &[u8] = b"yral-identity-key-id";
+const NONCE_LEN: usize = 12;
+/// Minimum length of the configured keys, in bytes
+pub const MIN_KEY_LEN: usize = 32;
+
+#[derive(Error, Debug)]
+pub enum SecretsError {
+    #[error("record encrypted with unknown key `{0}`")]
+    UnknownKey(String),
+    #[error("failed to encrypt or decrypt record")]
+    Aead,
+    #[error("invalid record encoding: {0}")]
+    Encoding(#[from] hex::FromHexError),
+    #[error("invalid record: {0}")]
+    Record(#[from] serde_json::Error),
+    #[error("record is neither sealed nor a legacy identity")]
+    Corrupt,
+    #[error("keys must be at least {MIN_KEY_LEN} bytes, got {0}")]
+    KeyLength(usize),
+}
+
+/// Encrypted record, as stored in the KV store
+#[derive(Serialize, Deserialize)]
+struct SealedRecord {
+    /// Id of the key encryption key
+    kid: String,
+    /// Hex encoded nonce + wrapped data key
+    dek: String,
+    /// Hex encoded nonce + ciphertext
+    ct: String,
+}
+
+struct Kek {
+    id: String,
+    cipher: Aes256Gcm,
+}
+
+impl Kek {
+    fn derive(secret: &[u8]) -> Self {
+        let hk = Hkdf::<Sha256>::new(None, secret);
+        let mut kek = Zeroizing::new([0u8; 32]);
+        hk.expand(KEK_INFO, &mut kek[..])
+            .expect("32 bytes is a valid HKDF output length");
+        let mut id = [0u8; 8];
+        hk.expand(KEY_ID_INFO, &mut id)
+            .expect("8 bytes is a valid HKDF output length");
+
+        Self {
+            id: hex::encode(id),
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&kek[..])),
+        }
+    }
+}
+
+fn seal_with(cipher: &Aes256Gcm, aad: &[u8], msg: &[u8]) -> Result<Vec<u8>, SecretsError> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let mut sealed = nonce.to_vec();
+    sealed.extend(
+        cipher
+            .encrypt(&nonce, Payload { msg, aad })
+            .map_err(|_| SecretsError::Aead)?,
+    );
+    Ok(sealed)
+}
+
+fn open_with(
+    cipher: &Aes256Gcm,
+    aad: &[u8],
+    sealed: &[u8],
+) -> Result<Zeroizing<Vec<u8>>, SecretsError> {
+    if sealed.len() < NONCE_LEN {
+        return Err(SecretsError::Aead);
+    }
+    let (nonce, msg) = sealed.split_at(NONCE_LEN);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), Payload { msg, aad })
+        .map(Zeroizing::new)
+        .map_err(|_| SecretsError::Aead)
+}
+
+/// Only plaintext secp256k1 JWKs are accepted unsealed
+/// anything else, e.g a damaged envelope, is rejected
+fn is_legacy_record(record: &str) -> bool {
+    k256::SecretKey::from_jwk_str(record).is_ok()
+}
+
+/// Plaintext of a record
+pub struct Opened {
+    pub plaintext: Zeroizing<Vec<u8>>,
+    /// The record is unencrypted or uses a retired key, and should be sealed again
+    pub stale: bool,
+}
+
+/// Key encryption keys for identity secrets
+#[derive(Clone)]
+pub struct IdentityKeyring(Arc<KeyringInner>);
+
+struct KeyringInner {
+    primary: Kek,
+    retired: Vec<Kek>,
+}
+
+impl IdentityKeyring {
+    /// `primary` encrypts new records, `retired` keys are only used to decrypt
+    pub fn new(primary: &[u8], retired: &[Vec<u8>]) -> Result<Self, SecretsError> {
+        if let Some(short) = std::iter::once(primary)
+            .chain(retired.iter().map(Vec::as_slice))
+            .find(|secret| secret.len() < MIN_KEY_LEN)
+        {
+            return Err(SecretsError::KeyLength(short.len()));
+        }
+        Ok(Self(Arc::new(KeyringInner {
+            primary: Kek::derive(primary),
+            retired: retired.iter().map(|secret| Kek::derive(secret)).collect(),
+        })))
+    }
+
+    fn kek(&self, id: &str) -> Option<&Kek> {
+        std::iter::once(&self.0.primary)
+            .chain(&self.0.retired)
+            .find(|kek| kek.id == id)
+    }
+
+    /// Encrypt `secret` with a fresh data key
+    /// `aad` binds the record to its KV key
+    pub fn seal(&self, aad: &[u8], secret: &[u8]) -> Result<String, SecretsError> {
+        let dek = Aes256Gcm::generate_key(OsRng);
+        let ct = seal_with(&Aes256Gcm::new(&dek), aad, secret)?;
+        let wrapped_dek = seal_with(&self.0.primary.cipher, aad, dek.as_slice())?;
+
+        let record = SealedRecord {
+            kid: self.0.primary.id.clone(),
+            dek: hex::encode(wrapped_dek),
+            ct: hex::encode(ct),
+        };
+        Ok(serde_json::to_string(&record)?)
+    }
+
+    /// Decrypt a record written by [IdentityKeyring::seal]
+    /// legacy plaintext identities are returned as is
+    pub fn open(&self, aad: &[u8], record: &str) -> Result<Opened, SecretsError> {
+        let Ok(sealed) = serde_json::from_str::<SealedRecord>(record) else {
+            if !is_legacy_record(record) {
+                return Err(SecretsError::Corrupt);
+            }
+            return Ok(Opened {
+                plaintext: Zeroizing::new(record.as_bytes().to_vec()),
+                stale: true,
+            });
+        };
+        let kek = self
+            .kek(&sealed.kid)
+            .ok_or_else(|| SecretsError::UnknownKey(sealed.kid.clone()))?;
+
+        let dek = open_with(&kek.cipher, aad, &hex::decode(&sealed.dek)?)?;
+        let dek_cipher = Aes256Gcm::new_from_slice(&dek[..]).map_err(|_| SecretsError::Aead)?;
+        let plaintext = open_with(&dek_cipher, aad, &hex::decode(&sealed.ct)?)?;
+
+        Ok(Opened {
+            plaintext,
+            stale: kek.id != self.0.primary.id,
+        })
+    }
+}
@@ -0,0 +1,125 @@
+use k256::SecretKey;
+use rand_chacha::rand_core::OsRng;
+
+use super::{IdentityKeyring, SealedRecord, SecretsError, MIN_KEY_LEN};
+
+const PRIMARY: [u8; 32] = [1; 32];
+const RETIRED: [u8; 32] = [2; 32];
+const AAD: &[u8] = b"2vxsx-fae";
+
+fn keyring() -> IdentityKeyring {
+    IdentityKeyring::new(&PRIMARY, &[RETIRED.to_vec()]).unwrap()
+}
+
+fn tamper(record: &str, f: impl FnOnce(&mut SealedRecord)) -> String {
+    let mut sealed: SealedRecord = serde_json::from_str(record).unwrap();
+    f(&mut sealed);
+    serde_json::to_string(&sealed).unwrap()
+}
+
+#[test]
+fn round_trips() {
+    let keyring = keyring();
+    let record = keyring.seal(AAD, b"secret").unwrap();
+    assert!(!record.contains("secret"));
+
+    let opened = keyring.open(AAD, &record).unwrap();
+    assert_eq!(&opened.plaintext[..], b"secret");
+    assert!(!opened.stale);
+}
+
+#[test]
+fn retired_key_opens_and_is_stale() {
+    let old = IdentityKeyring::new(&RETIRED, &[]).unwrap();
+    let record = old.seal(AAD, b"secret").unwrap();
+
+    let opened = keyring().open(AAD, &record).unwrap();
+    assert_eq!(&opened.plaintext[..], b"secret");
+    assert!(opened.stale);
+}
+
+#[test]
+fn unknown_key_is_rejected() {
+    let other = IdentityKeyring::new(&[3; 32], &[]).unwrap();
+    let record = other.seal(AAD, b"secret").unwrap();
+
+    assert!(matches!(
+        keyring().open(AAD, &record),
+        Err(SecretsError::UnknownKey(_))
+    ));
+}
+
+#[test]
+fn legacy_plaintext_opens_and_is_stale() {
+    let jwk = SecretKey::random(&mut OsRng).to_jwk_string();
+
+    let opened = keyring().open(AAD, &jwk).unwrap();
+    assert_eq!(&opened.plaintext[..], jwk.as_bytes());
+    assert!(opened.stale);
+}
+
+#[test]
+fn corrupt_records_are_rejected() {
+    let keyring = keyring();
+    let record = keyring.seal(AAD, b"secret").unwrap();
+
+    assert!(matches!(
+        keyring.open(AAD, "not a record"),
+        Err(SecretsError::Corrupt)
+    ));
+    assert!(matches!(
+        keyring.open(AAD, &record[..record.len() / 2]),
+        Err(SecretsError::Corrupt)
+    ));
+
+    let truncated = tamper(&record, |sealed| {
+        sealed.ct.truncate(sealed.ct.len() - 2);
+    });
+    assert!(matches!(
+        keyring.open(AAD, &truncated),
+        Err(SecretsError::Aead)
+    ));
+    let short = tamper(&record, |sealed| sealed.ct = "00".repeat(4));
+    assert!(matches!(keyring.open(AAD, &short), Err(SecretsError::Aead)));
+
+    let flipped = tamper(&record, |sealed| {
+        let mut ct = hex::decode(&sealed.ct).unwrap();
+        *ct.last_mut().unwrap() ^= 1;
+        sealed.ct = hex::encode(ct);
+    });
+    assert!(matches!(
+        keyring.open(AAD, &flipped),
+        Err(SecretsError::Aead)
+    ));
+
+    let not_hex = tamper(&record, |sealed| sealed.dek = "zz".into());
+    assert!(matches!(
+        keyring.open(AAD, &not_hex),
+        Err(SecretsError::Encoding(_))
+    ));
+}
+
+#[test]
+fn record_of_another_principal_is_rejected() {
+    let keyring = keyring();
+    let record = keyring.seal(AAD, b"secret").unwrap();
+
+    assert!(matches!(
+        keyring.open(b"aaaaa-aa", &record),
+        Err(SecretsError::Aead)
+    ));
+}
+
+#[test]
+fn short_keys_are_rejected() {
+    let short = vec![1; MIN_KEY_LEN - 1];
+
+    assert!(matches!(
+        IdentityKeyring::new(&short, &[]),
+        Err(SecretsError::KeyLength(len)) if len == MIN_KEY_LEN - 1
+    ));
+    assert!(matches!(
+        IdentityKeyring::new(&PRIMARY, &[short]),
+        Err(SecretsError::KeyLength(_))
+    ));
+}
@@ -0,0 +1,323 @@
+//! Server side registry of refresh token sessions
+//!
+//! `session-{id}` holds a [Session], `sessions-{principal}` lists the session ids of a principal.
+//! A session is revoked by deleting its record.
+//! Both expire [SESSION_TTL] after their last write, active sessions are rewritten as they're touched.
+//! `legacy-session-{principal}` marks the refresh token issued before sessions were tracked as migrated
+
+use candid::Principal;
+use http::{header, HeaderMap};
+use leptos::ServerFnError;
+use rand_chacha::rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use web_time::Duration;
+
+use crate::{consts::auth::REFRESH_MAX_AGE, utils::current_epoch};
+
+use super::store::{KVStore, KVStoreImpl, KvKey, RecordKind};
+
+/// `last_used_ms` is only updated if it's older than this, avoids a write on every request
+const TOUCH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// Outlives the last refresh token of a session, which may be issued up to [TOUCH_INTERVAL] after its last write
+const SESSION_TTL: Duration =
+    Duration::from_secs(REFRESH_MAX_AGE.as_secs() + TOUCH_INTERVAL.as_secs());
+/// Attempts to update a session or the session list of a principal
+const CAS_RETRIES: usize = 5;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Session {
+    pub principal: Principal,
+    /// Short description of the device, e.g `Chrome on Android`
+    pub device: String,
+    pub created_at_ms: u64,
+    pub last_used_ms: u64,
+    /// Last explicit login in this session, 0 if the session was never logged into
+    #[serde(default)]
+    pub authenticated_at_ms: u64,
+}
+
+fn session_key(id: &str) -> KvKey {
+    KvKey::new(RecordKind::Session, format!("session-{id}"))
+}
+
+fn principal_sessions_key(principal: Principal) -> KvKey {
+    KvKey::new(
+        RecordKind::Session,
+        format!("sessions-{}", principal.to_text()),
+    )
+}
+
+fn legacy_token_key(principal: Principal) -> KvKey {
+    KvKey::new(
+        RecordKind::Session,
+        format!("legacy-session-{}", principal.to_text()),
+    )
+}
+
+/// Claim the migration of `principal`'s refresh token issued before sessions were tracked
+/// only the first claim succeeds, such tokens must be rejected afterwards
+pub async fn claim_legacy_token(
+    kv: &KVStoreImpl,
+    principal: Principal,
+) -> Result<bool, ServerFnError> {
+    // legacy tokens have all expired by then
+    Ok(kv
+        .compare_and_set(
+            legacy_token_key(principal),
+            None,
+            now_ms().to_string(),
+            Some(REFRESH_MAX_AGE),
+        )
+        .await?)
+}
+
+/// `principal`'s refresh token issued before sessions were tracked was already migrated
+pub async fn legacy_token_claimed(
+    kv: &KVStoreImpl,
+    principal: Principal,
+) -> Result<bool, ServerFnError> {
+    Ok(kv.read(legacy_token_key(principal)).await?.is_some())
+}
+
+fn now_ms() -> u64 {
+    current_epoch().as_millis() as u64
+}
+
+async fn read_session(kv: &KVStoreImpl, id: &str) -> Result<Option<Session>, ServerFnError> {
+    let Some(raw) = kv.read(session_key(id)).await? else {
+        return Ok(None);
+    };
+    Ok(Some(serde_json::from_str(&raw)?))
+}
+
+async fn write_session(kv: &KVStoreImpl, id: &str, session: &Session) -> Result<(), ServerFnError> {
+    kv.write_with_ttl(
+        session_key(id),
+        serde_json::to_string(session)?,
+        SESSION_TTL,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Atomically apply `update` to session `id`, false if the session doesn't exist
+/// a session revoked concurrently is never written back
+async fn update_session(
+    kv: &KVStoreImpl,
+    id: &str,
+    update: impl Fn(&mut Session),
+) -> Result<bool, ServerFnError> {
+    let key = session_key(id);
+    for _ in 0..CAS_RETRIES {
+        let Some(raw) = kv.read(key.clone()).await? else {
+            return Ok(false);
+        };
+        let mut session: Session = serde_json::from_str(&raw)?;
+        update(&mut session);
+        if kv
+            .compare_and_set(
+                key.clone(),
+                Some(raw),
+                serde_json::to_string(&session)?,
+                Some(SESSION_TTL),
+            )
+            .await?
+        {
+            return Ok(true);
+        }
+    }
+    Err(ServerFnError::new("Session is busy, try again"))
+}
+
+async fn read_session_ids(
+    kv: &KVStoreImpl,
+    principal: Principal,
+) -> Result<Vec<String>, ServerFnError> {
+    let Some(raw) = kv.read(principal_sessions_key(principal)).await? else {
+        return Ok(vec![]);
+    };
+    Ok(serde_json::from_str(&raw)?)
+}
+
+/// Atomically apply `update` to the session ids of `principal`, extending the list's expiry
+async fn update_session_ids(
+    kv: &KVStoreImpl,
+    principal: Principal,
+    update: impl Fn(&mut Vec<String>),
+) -> Result<(), ServerFnError> {
+    let key = principal_sessions_key(principal);
+    for _ in 0..CAS_RETRIES {
+        let current = kv.read(key.clone()).await?;
+        let mut ids: Vec<String> = match &current {
+            Some(raw) => serde_json::from_str(raw)?,
+            None => vec![],
+        };
+        update(&mut ids);
+        // an empty list expires on its own
+        if kv
+            .compare_and_set(
+                key.clone(),
+                current,
+                serde_json::to_string(&ids)?,
+                Some(SESSION_TTL),
+            )
+            .await?
+        {
+            return Ok(());
+        }
+    }
+    Err(ServerFnError::new("Session list is busy, try again"))
+}
+
+fn add_id(id: &str) -> impl Fn(&mut Vec<String>) + '_ {
+    move |ids| {
+        if !ids.iter().any(|sid| sid == id) {
+            ids.push(id.to_string());
+        }
+    }
+}
+
+/// Register a new session for `principal`, returns its id
+pub async fn create_session(
+    kv: &KVStoreImpl,
+    principal: Principal,
+    device: String,
+) -> Result<String, ServerFnError> {
+    let mut id_bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut id_bytes);
+    let id = hex::encode(id_bytes);
+
+    let now = now_ms();
+    let session = Session {
+        principal,
+        device,
+        created_at_ms: now,
+        last_used_ms: now,
+        authenticated_at_ms: 0,
+    };
+    write_session(kv, &id, &session).await?;
+    update_session_ids(kv, principal, add_id(&id)).await?;
+
+    Ok(id)
+}
+
+/// Checks that session `id` is active and belongs to `principal`
+/// and records its use
+pub async fn touch_session(
+    kv: &KVStoreImpl,
+    id: &str,
+    principal: Principal,
+) -> Result<bool, ServerFnError> {
+    let Some(session) = read_session(kv, id).await? else {
+        return Ok(false);
+    };
+    if session.principal != principal {
+        return Ok(false);
+    }
+
+    let now = now_ms();
+    if now.saturating_sub(session.last_used_ms) > TOUCH_INTERVAL.as_millis() as u64 {
+        if !update_session(kv, id, |session| session.last_used_ms = now).await? {
+            return Ok(false);
+        }
+        // keeps the list alive as long as its sessions, and restores the id if it went missing
+        update_session_ids(kv, principal, add_id(id)).await?;
+    }
+    Ok(true)
+}
+
+/// Record an explicit login in session `id`
+pub async fn mark_authenticated(kv: &KVStoreImpl, id: &str) -> Result<(), ServerFnError> {
+    let now = now_ms();
+    update_session(kv, id, |session| session.authenticated_at_ms = now).await?;
+    Ok(())
+}
+
+/// Session `id` was logged into within `max_age`
+pub async fn authenticated_within(
+    kv: &KVStoreImpl,
+    id: &str,
+    max_age: Duration,
+) -> Result<bool, ServerFnError> {
+    let Some(session) = read_session(kv, id).await? else {
+        return Ok(false);
+    };
+    Ok(now_ms().saturating_sub(session.authenticated_at_ms) <= max_age.as_millis() as u64)
+}
+
+/// Revoke session `id`, refresh tokens referring to it are rejected afterwards
+pub async fn revoke_session(kv: &KVStoreImpl, id: &str) -> Result<(), ServerFnError> {
+    let Some(session) = read_session(kv, id).await? else {
+        return Ok(());
+    };
+    kv.delete(session_key(id)).await?;
+
+    update_session_ids(kv, session.principal, |ids| ids.retain(|sid| sid != id)).await
+}
+
+/// Active sessions of `principal`, ids of revoked sessions are dropped from the index
+pub async fn list_sessions(
+    kv: &KVStoreImpl,
+    principal: Principal,
+) -> Result<Vec<(String, Session)>, ServerFnError> {
+    let ids = read_session_ids(kv, principal).await?;
+    let records = kv
+        .read_many(ids.iter().map(|id| session_key(id)).collect())
+        .await?;
+    let mut sessions = Vec::with_capacity(ids.len());
+    for (id, raw) in ids.iter().zip(records) {
+        if let Some(raw) = raw {
+            sessions.push((id.clone(), serde_json::from_str(&raw)?));
+        }
+    }
+    if sessions.len() != ids.len() {
+        // only drop the ids found revoked, others may have been added meanwhile
+        let revoked: Vec<_> = ids
+            .iter()
+            .filter(|id| !sessions.iter().any(|(sid, _)| sid == *id))
+            .cloned()
+            .collect();
+        update_session_ids(kv, principal, |ids| ids.retain(|id| !revoked.contains(id))).await?;
+    }
+    Ok(sessions)
+}
+
+/// Short label of the device from its user agent, e.g `Chrome on Android`
+pub fn device_label(headers: &HeaderMap) -> String {
+    let Some(ua) = headers
+        .get(header::USER_AGENT)
+        .and_then(|ua| ua.to_str().ok())
+    else {
+        return "Unknown device".into();
+    };
+
+    // order matters, most user agents mention several browsers
+    let browser = [
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("SamsungBrowser/", "Samsung Internet"),
+        ("Firefox/", "Firefox"),
+        ("Chrome/", "Chrome"),
+        ("Safari/", "Safari"),
+    ]
+    .into_iter()
+    .find(|(token, _)| ua.contains(token))
+    .map(|(_, name)| name);
+    let os = [
+        ("Android", "Android"),
+        ("iPhone", "iOS"),
+        ("iPad", "iPadOS"),
+        ("Windows", "Windows"),
+        ("Mac OS X", "macOS"),
+        ("Linux", "Linux"),
+    ]
+    .into_iter()
+    .find(|(token, _)| ua.contains(token))
+    .map(|(_, name)| name);
+
+    match (browser, os) {
+        (Some(browser), Some(os)) => format!("{browser} on {os}"),
+        (Some(name), None) | (None, Some(name)) => name.into(),
+        (None, None) => "Unknown device".into(),
+    }
+}
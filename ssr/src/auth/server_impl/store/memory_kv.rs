@@ -0,0 +1,180 @@
+use std::{
+    collections::BTreeMap,
+    ops::Bound,
+    sync::{Arc, Mutex},
+};
+
+use tokio::task::JoinHandle;
+use web_time::{Duration, Instant};
+
+use super::{KVError, KVStore, KeyPage, KvKey, RecordKind};
+
+struct Entry {
+    value: String,
+    expires_at: Option<Instant>,
+}
+
+impl Entry {
+    fn is_live(&self, now: Instant) -> bool {
+        self.expires_at.map_or(true, |expires_at| expires_at > now)
+    }
+}
+
+/// Process local store, everything is lost on restart
+/// expired keys are treated as missing until swept, see [MemoryKV::spawn_sweeper]
+#[derive(Clone, Default)]
+pub struct MemoryKV(Arc<Mutex<BTreeMap<String, Entry>>>);
+
+impl MemoryKV {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with_map<R>(&self, f: impl FnOnce(&mut BTreeMap<String, Entry>) -> R) -> R {
+        let mut map = self.0.lock().unwrap();
+        f(&mut map)
+    }
+
+    /// Remove expired keys, returning the number of keys removed
+    pub fn sweep_expired(&self) -> usize {
+        let now = Instant::now();
+        self.with_map(|map| {
+            let before = map.len();
+            map.retain(|_, entry| entry.is_live(now));
+            before - map.len()
+        })
+    }
+
+    /// Periodically remove expired keys in the background
+    pub fn spawn_sweeper(&self, interval: Duration) -> JoinHandle<()> {
+        let kv = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                kv.sweep_expired();
+            }
+        })
+    }
+
+    fn insert(&self, key: String, value: String, expires_at: Option<Instant>) {
+        self.with_map(|map| {
+            map.insert(key, Entry { value, expires_at });
+        })
+    }
+}
+
+impl KVStore for MemoryKV {
+    async fn read(&self, key: KvKey) -> Result<Option<String>, KVError> {
+        let key = key.key;
+        let now = Instant::now();
+        Ok(self.with_map(|map| match map.get(&key) {
+            Some(entry) if entry.is_live(now) => Some(entry.value.clone()),
+            Some(_) => {
+                map.remove(&key);
+                None
+            }
+            None => None,
+        }))
+    }
+
+    async fn read_many(&self, keys: Vec<KvKey>) -> Result<Vec<Option<String>>, KVError> {
+        let now = Instant::now();
+        Ok(self.with_map(|map| {
+            keys.iter()
+                .map(|key| {
+                    map.get(&key.key)
+                        .filter(|entry| entry.is_live(now))
+                        .map(|entry| entry.value.clone())
+                })
+                .collect()
+        }))
+    }
+
+    async fn write(&self, key: KvKey, value: String) -> Result<(), KVError> {
+        self.insert(key.key, value, None);
+        Ok(())
+    }
+
+    async fn write_with_ttl(
+        &self,
+        key: KvKey,
+        value: String,
+        ttl: Duration,
+    ) -> Result<(), KVError> {
+        self.insert(key.key, value, Some(Instant::now() + ttl));
+        Ok(())
+    }
+
+    async fn ttl(&self, key: KvKey) -> Result<Option<Duration>, KVError> {
+        let now = Instant::now();
+        Ok(self.with_map(|map| {
+            map.get(&key.key)
+                .filter(|entry| entry.is_live(now))
+                .and_then(|entry| entry.expires_at)
+                .map(|expires_at| expires_at - now)
+        }))
+    }
+
+    async fn delete(&self, key: KvKey) -> Result<bool, KVError> {
+        let now = Instant::now();
+        Ok(self.with_map(|map| map.remove(&key.key).is_some_and(|entry| entry.is_live(now))))
+    }
+
+    async fn compare_and_set(
+        &self,
+        key: KvKey,
+        expected: Option<String>,
+        value: String,
+        ttl: Option<Duration>,
+    ) -> Result<bool, KVError> {
+        let key = key.key;
+        let now = Instant::now();
+        Ok(self.with_map(|map| {
+            let current = map
+                .get(&key)
+                .filter(|entry| entry.is_live(now))
+                .map(|entry| &entry.value);
+            if current != expected.as_ref() {
+                return false;
+            }
+            map.insert(
+                key,
+                Entry {
+                    value,
+                    expires_at: ttl.map(|ttl| now + ttl),
+                },
+            );
+            true
+        }))
+    }
+
+    /// Every kind shares a single namespace, keys are listed in order and the cursor is the last key
+    async fn list_keys_page(
+        &self,
+        _kind: RecordKind,
+        prefix: String,
+        cursor: Option<String>,
+        limit: usize,
+    ) -> Result<KeyPage, KVError> {
+        let now = Instant::now();
+        let start = match cursor {
+            Some(cursor) => Bound::Excluded(cursor),
+            None => Bound::Included(prefix.clone()),
+        };
+        let limit = limit.max(1);
+        Ok(self.with_map(|map| {
+            let keys: Vec<_> = map
+                .range((start, Bound::Unbounded))
+                .take_while(|(key, _)| key.starts_with(&prefix))
+                .filter(|(_, entry)| entry.is_live(now))
+                .take(limit)
+                .map(|(key, _)| key.clone())
+                .collect();
+            let cursor = (keys.len() == limit)
+                .then(|| keys.last().cloned())
+                .flatten();
+            KeyPage { keys, cursor }
+        }))
+    }
+}
@@ -0,0 +1,425 @@
+//! Copy every key between KV backends, or to and from JSON-lines dumps
+//!
+//! Keys are copied a page at a time. After each page the destination is synced
+//! and a [Checkpoint] is saved, an interrupted run resumes from the last one.
+//!
+//! Keys don't record their [RecordKind], they're listed per kind from a KV source.
+//! Kinds sharing a key prefix in the source are listed once, under the first of them,
+//! so the destination must store them under a shared prefix as well
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use futures::future::try_join_all;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use web_time::Duration;
+
+use super::{
+    redb_kv::ReDBKV,
+    redis_kv::{KeyPrefixes, RedisKV, RedisTopology},
+    KVError, KVStore, KVStoreImpl, KvKey, RecordKind,
+};
+
+/// Keys listed from the source at once
+const PAGE_SIZE: usize = 500;
+
+#[derive(Error, Debug)]
+pub enum MigrateError {
+    #[error("invalid endpoint `{0}`, expected `redb:<path>`, `jsonl:<path>`, a redis url, `redis-sentinel:<master>@<urls>` or `redis-cluster:<urls>`")]
+    InvalidEndpoint(String),
+    #[error("the source stores {0:?} and {1:?} keys under the same prefix, the destination can't tell them apart")]
+    SplitKinds(RecordKind, RecordKind),
+    #[error(transparent)]
+    KV(#[from] KVError),
+    #[error("io err: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid dump record: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("checksum mismatch for key `{0}`")]
+    Checksum(String),
+    #[error("key `{0}` was not written correctly")]
+    Verify(String),
+}
+
+/// Source or destination of a migration
+pub enum Endpoint {
+    Kv(KVStoreImpl),
+    Jsonl(PathBuf),
+}
+
+impl Endpoint {
+    /// Parse `redb:<path>`, `jsonl:<path>` or a [RedisTopology] spec
+    /// redis keys are stored under `prefixes`, as with `REDIS_KEY_PREFIXES`
+    pub async fn parse(spec: &str, prefixes: KeyPrefixes) -> Result<Self, MigrateError> {
+        if let Some(path) = spec.strip_prefix("redb:") {
+            let kv = ReDBKV::open(path).map_err(KVError::from)?;
+            Ok(Self::Kv(KVStoreImpl::ReDB(kv)))
+        } else if let Some(path) = spec.strip_prefix("jsonl:") {
+            Ok(Self::Jsonl(path.into()))
+        } else if let Some(topology) = RedisTopology::parse(spec) {
+            let kv = RedisKV::connect(topology, prefixes)
+                .await
+                .map_err(KVError::from)?;
+            Ok(Self::Kv(KVStoreImpl::Redis(kv)))
+        } else {
+            Err(MigrateError::InvalidEndpoint(spec.to_string()))
+        }
+    }
+}
+
+/// A single key, as stored in dumps
+#[derive(Serialize, Deserialize)]
+pub struct Record {
+    /// Dumps written before kinds were recorded hold unprefixed keys
+    #[serde(default)]
+    pub kind: RecordKind,
+    pub key: String,
+    pub value: String,
+    /// Remaining time to live, `None` if the key doesn't expire
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_ms: Option<u64>,
+    /// [checksum] of the key and value
+    pub checksum: String,
+}
+
+impl Record {
+    fn new(key: KvKey, value: String, ttl: Option<Duration>) -> Self {
+        Self {
+            checksum: checksum(&key.key, &value),
+            ttl_ms: ttl.map(|ttl| ttl.as_millis() as u64),
+            kind: key.kind,
+            key: key.key,
+            value,
+        }
+    }
+}
+
+/// Hex encoded SHA-256 of the length prefixed key followed by the value
+pub fn checksum(key: &str, value: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update((key.len() as u64).to_le_bytes());
+    hasher.update(key.as_bytes());
+    hasher.update(value.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+#[derive(Default)]
+pub struct MigrateOptions {
+    /// Read and verify the source without writing anything
+    pub dry_run: bool,
+    /// File holding the last [Checkpoint], used to resume interrupted runs
+    /// removed once the migration completes
+    pub progress: Option<PathBuf>,
+}
+
+pub struct MigrateSummary {
+    pub copied: usize,
+    /// This run resumed from the checkpoint of an interrupted one
+    pub resumed: bool,
+    /// Hex encoded SHA-256 of the checksums of every record copied by this run, in order
+    pub digest: String,
+}
+
+/// Position of a migration, saved once everything before it is durably written
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+struct Checkpoint {
+    /// Index of the kind being listed from a KV source
+    kind: usize,
+    /// Listing cursor within that kind, or the byte offset of the next line of a dump
+    cursor: Option<String>,
+    /// Length of the dump being written
+    sink_len: u64,
+}
+
+impl Checkpoint {
+    fn load(path: &Path) -> Result<Option<Self>, MigrateError> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&fs::read_to_string(path)?)?))
+    }
+
+    /// Written to a temporary file first, so a crash never leaves a partial checkpoint
+    fn save(&self, path: &Path) -> Result<(), MigrateError> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+/// Kinds to list from `from`, one per key prefix
+fn listed_kinds(
+    from: &KVStoreImpl,
+    to: Option<&KVStoreImpl>,
+) -> Result<Vec<RecordKind>, MigrateError> {
+    let mut kinds: Vec<RecordKind> = vec![];
+    for kind in RecordKind::ALL {
+        let Some(&listed) = kinds
+            .iter()
+            .find(|listed| from.key_prefix(**listed) == from.key_prefix(kind))
+        else {
+            kinds.push(kind);
+            continue;
+        };
+        if to.is_some_and(|to| to.key_prefix(listed) != to.key_prefix(kind)) {
+            return Err(MigrateError::SplitKinds(listed, kind));
+        }
+    }
+    Ok(kinds)
+}
+
+enum Source {
+    Kv {
+        kv: KVStoreImpl,
+        kinds: Vec<RecordKind>,
+        kind: usize,
+        cursor: Option<String>,
+    },
+    Jsonl {
+        reader: BufReader<File>,
+        offset: u64,
+    },
+}
+
+impl Source {
+    fn open(from: &Endpoint, to: &Endpoint, at: &Checkpoint) -> Result<Self, MigrateError> {
+        Ok(match from {
+            Endpoint::Kv(kv) => {
+                let to = match to {
+                    Endpoint::Kv(to) => Some(to),
+                    Endpoint::Jsonl(_) => None,
+                };
+                Self::Kv {
+                    kv: kv.clone(),
+                    kinds: listed_kinds(kv, to)?,
+                    kind: at.kind,
+                    cursor: at.cursor.clone(),
+                }
+            }
+            Endpoint::Jsonl(path) => {
+                let offset = match &at.cursor {
+                    Some(offset) => offset.parse().map_err(|_| {
+                        std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            format!("invalid dump offset `{offset}`"),
+                        )
+                    })?,
+                    None => 0,
+                };
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(offset))?;
+                Self::Jsonl {
+                    reader: BufReader::new(file),
+                    offset,
+                }
+            }
+        })
+    }
+
+    /// Where the next page starts
+    fn position(&self) -> Checkpoint {
+        match self {
+            Self::Kv { kind, cursor, .. } => Checkpoint {
+                kind: *kind,
+                cursor: cursor.clone(),
+                sink_len: 0,
+            },
+            Self::Jsonl { offset, .. } => Checkpoint {
+                kind: 0,
+                cursor: Some(offset.to_string()),
+                sink_len: 0,
+            },
+        }
+    }
+
+    /// The next page of records, `None` once the source is exhausted
+    async fn next_page(&mut self) -> Result<Option<Vec<Record>>, MigrateError> {
+        match self {
+            Self::Kv {
+                kv,
+                kinds,
+                kind,
+                cursor,
+            } => {
+                while let Some(&listed) = kinds.get(*kind) {
+                    let page = kv
+                        .list_keys_page(listed, String::new(), cursor.take(), PAGE_SIZE)
+                        .await?;
+                    match page.cursor {
+                        Some(next) => *cursor = Some(next),
+                        None => *kind += 1,
+                    }
+                    if page.keys.is_empty() {
+                        continue;
+                    }
+
+                    let keys: Vec<_> = page
+                        .keys
+                        .into_iter()
+                        .map(|key| KvKey::new(listed, key))
+                        .collect();
+                    // read before the values, a key expiring in between is skipped rather than persisted
+                    let ttls = try_join_all(keys.iter().map(|key| kv.ttl(key.clone()))).await?;
+                    let values = kv.read_many(keys.clone()).await?;
+                    let records = keys
+                        .into_iter()
+                        .zip(values)
+                        .zip(ttls)
+                        // keys deleted or expired since they were listed are skipped
+                        .filter_map(|((key, value), ttl)| Some(Record::new(key, value?, ttl)))
+                        .collect();
+                    return Ok(Some(records));
+                }
+                Ok(None)
+            }
+            Self::Jsonl { reader, offset } => {
+                let mut records = vec![];
+                let mut line = String::new();
+                while records.len() < PAGE_SIZE {
+                    line.clear();
+                    let read = reader.read_line(&mut line)?;
+                    if read == 0 {
+                        break;
+                    }
+                    *offset += read as u64;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let record: Record = serde_json::from_str(&line)?;
+                    if record.checksum != checksum(&record.key, &record.value) {
+                        return Err(MigrateError::Checksum(record.key));
+                    }
+                    records.push(record);
+                }
+                Ok((!records.is_empty()).then_some(records))
+            }
+        }
+    }
+}
+
+enum Sink {
+    Kv(KVStoreImpl),
+    Jsonl(BufWriter<File>),
+}
+
+impl Sink {
+    /// A resumed dump is cut back to `resume_len`, dropping records written after the checkpoint
+    fn open(endpoint: &Endpoint, resume_len: Option<u64>) -> Result<Self, MigrateError> {
+        Ok(match endpoint {
+            Endpoint::Kv(kv) => Self::Kv(kv.clone()),
+            Endpoint::Jsonl(path) => {
+                let mut file = OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .truncate(resume_len.is_none())
+                    .open(path)?;
+                if let Some(len) = resume_len {
+                    file.set_len(len)?;
+                    file.seek(SeekFrom::End(0))?;
+                }
+                Self::Jsonl(BufWriter::new(file))
+            }
+        })
+    }
+
+    async fn put(&mut self, record: &Record) -> Result<(), MigrateError> {
+        match self {
+            Self::Kv(kv) => {
+                let key = KvKey::new(record.kind, record.key.clone());
+                match record.ttl_ms {
+                    Some(ttl_ms) => {
+                        kv.write_with_ttl(
+                            key.clone(),
+                            record.value.clone(),
+                            Duration::from_millis(ttl_ms),
+                        )
+                        .await?
+                    }
+                    None => kv.write(key.clone(), record.value.clone()).await?,
+                }
+                let written = kv.read(key).await?;
+                if written.as_deref() != Some(record.value.as_str()) {
+                    return Err(MigrateError::Verify(record.key.clone()));
+                }
+            }
+            Self::Jsonl(writer) => {
+                serde_json::to_writer(&mut *writer, record)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Make everything written so far durable, returns the length of the dump
+    fn sync(&mut self) -> Result<u64, MigrateError> {
+        let Self::Jsonl(writer) = self else {
+            return Ok(0);
+        };
+        writer.flush()?;
+        writer.get_ref().sync_data()?;
+        Ok(writer.get_mut().stream_position()?)
+    }
+}
+
+/// Copy every key from `from` to `to`, verifying each written value
+pub async fn migrate(
+    from: &Endpoint,
+    to: &Endpoint,
+    options: &MigrateOptions,
+) -> Result<MigrateSummary, MigrateError> {
+    let progress = options.progress.as_deref().filter(|_| !options.dry_run);
+    let resume_from = progress.map(Checkpoint::load).transpose()?.flatten();
+    let mut source = Source::open(from, to, &resume_from.clone().unwrap_or_default())?;
+    let mut sink = if options.dry_run {
+        None
+    } else {
+        Some(Sink::open(
+            to,
+            resume_from.as_ref().map(|checkpoint| checkpoint.sink_len),
+        )?)
+    };
+
+    let mut digest = Sha256::new();
+    let mut copied = 0;
+    while let Some(records) = source.next_page().await? {
+        for record in &records {
+            if let Some(sink) = sink.as_mut() {
+                sink.put(record).await?;
+            }
+            digest.update(record.checksum.as_bytes());
+            copied += 1;
+        }
+
+        if let (Some(sink), Some(progress)) = (sink.as_mut(), progress) {
+            let checkpoint = Checkpoint {
+                sink_len: sink.sync()?,
+                ..source.position()
+            };
+            checkpoint.save(progress)?;
+        }
+    }
+
+    if let Some(sink) = sink.as_mut() {
+        sink.sync()?;
+        if let Some(path) = progress {
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+    }
+
+    Ok(MigrateSummary {
+        copied,
+        resumed: resume_from.is_some(),
+        digest: hex::encode(digest.finalize()),
+    })
+}
@@ -0,0 +1,161 @@
+use std::{fs, path::PathBuf};
+
+use web_time::Duration;
+
+use super::{
+    super::{memory_kv::MemoryKV, KVStore, KVStoreImpl, KvKey, RecordKind},
+    migrate, Checkpoint, Endpoint, MigrateOptions, PAGE_SIZE,
+};
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("kv-migrate-{:016x}-{name}", rand::random::<u64>()))
+}
+
+fn kv_key(key: impl Into<String>) -> KvKey {
+    KvKey::new(RecordKind::Other, key)
+}
+
+/// A memory store with `count` keys, the first of which expires
+async fn source(count: usize) -> KVStoreImpl {
+    let kv = KVStoreImpl::Memory(MemoryKV::new());
+    kv.write_with_ttl(
+        kv_key("key-0000"),
+        "value-0".into(),
+        Duration::from_secs(600),
+    )
+    .await
+    .unwrap();
+    for i in 1..count {
+        kv.write(kv_key(format!("key-{i:04}")), format!("value-{i}"))
+            .await
+            .unwrap();
+    }
+    kv
+}
+
+async fn contents(kv: &KVStoreImpl) -> Vec<(String, String)> {
+    let keys = kv
+        .list_keys(RecordKind::Other, String::new())
+        .await
+        .unwrap();
+    let values = kv
+        .read_many(keys.iter().map(|key| kv_key(key.clone())).collect())
+        .await
+        .unwrap();
+    keys.into_iter()
+        .zip(values)
+        .map(|(key, value)| (key, value.unwrap()))
+        .collect()
+}
+
+#[tokio::test]
+async fn copies_memory_to_memory() {
+    let from = source(PAGE_SIZE + 10).await;
+    let to = KVStoreImpl::Memory(MemoryKV::new());
+
+    let summary = migrate(
+        &Endpoint::Kv(from.clone()),
+        &Endpoint::Kv(to.clone()),
+        &MigrateOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(summary.copied, PAGE_SIZE + 10);
+    assert!(!summary.resumed);
+    assert_eq!(contents(&to).await, contents(&from).await);
+    assert!(to.ttl(kv_key("key-0000")).await.unwrap().is_some());
+    assert_eq!(to.ttl(kv_key("key-0001")).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn dumps_and_restores() {
+    let from = source(PAGE_SIZE + 10).await;
+    let dump = temp_path("dump.jsonl");
+    let to = KVStoreImpl::Memory(MemoryKV::new());
+
+    let dumped = migrate(
+        &Endpoint::Kv(from.clone()),
+        &Endpoint::Jsonl(dump.clone()),
+        &MigrateOptions::default(),
+    )
+    .await
+    .unwrap();
+    let restored = migrate(
+        &Endpoint::Jsonl(dump.clone()),
+        &Endpoint::Kv(to.clone()),
+        &MigrateOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(restored.copied, dumped.copied);
+    assert_eq!(restored.digest, dumped.digest);
+    assert_eq!(contents(&to).await, contents(&from).await);
+    assert!(to.ttl(kv_key("key-0000")).await.unwrap().is_some());
+    fs::remove_file(dump).unwrap();
+}
+
+#[tokio::test]
+async fn resumes_interrupted_dump() {
+    let from = Endpoint::Kv(source(2 * PAGE_SIZE + 10).await);
+    let complete = temp_path("complete.jsonl");
+    migrate(
+        &from,
+        &Endpoint::Jsonl(complete.clone()),
+        &MigrateOptions::default(),
+    )
+    .await
+    .unwrap();
+    let complete_dump = fs::read_to_string(&complete).unwrap();
+
+    // interrupted after the first page was checkpointed, in the middle of writing the second
+    let first_page: String = complete_dump
+        .split_inclusive('\n')
+        .take(PAGE_SIZE)
+        .collect();
+    let dump = temp_path("dump.jsonl");
+    fs::write(
+        &dump,
+        format!("{first_page}{{\"kind\":\"identity\",\"key\":\"key-05"),
+    )
+    .unwrap();
+    let progress = temp_path("progress.json");
+    Checkpoint {
+        kind: 0,
+        cursor: Some(format!("key-{:04}", PAGE_SIZE - 1)),
+        sink_len: first_page.len() as u64,
+    }
+    .save(&progress)
+    .unwrap();
+
+    let summary = migrate(
+        &from,
+        &Endpoint::Jsonl(dump.clone()),
+        &MigrateOptions {
+            dry_run: false,
+            progress: Some(progress.clone()),
+        },
+    )
+    .await
+    .unwrap();
+
+    assert!(summary.resumed);
+    assert_eq!(summary.copied, PAGE_SIZE + 10);
+    // ttls are the remaining time at each run, compare the keys and values
+    let records = |dump: &str| -> Vec<(String, String)> {
+        dump.lines()
+            .map(|line| {
+                let record: super::Record = serde_json::from_str(line).unwrap();
+                (record.key, record.value)
+            })
+            .collect()
+    };
+    assert_eq!(
+        records(&fs::read_to_string(&dump).unwrap()),
+        records(&complete_dump)
+    );
+    assert!(!progress.exists());
+    fs::remove_file(dump).unwrap();
+    fs::remove_file(complete).unwrap();
+}
@@ -1,9 +1,18 @@
+pub mod memory_kv;
+pub mod migrate;
 pub mod redb_kv;
 pub mod redis_kv;
+#[cfg(test)]
+mod tests;
 
-use enum_dispatch::enum_dispatch;
 use redis::RedisError;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use web_time::Duration;
+
+use tracing::Instrument;
+
+use crate::{metrics::time_kv_op, telemetry::parent_span};
 
 #[derive(Error, Debug)]
 pub enum KVError {
@@ -15,17 +24,202 @@ pub enum KVError {
     Redis(#[from] RedisError),
     #[error("{0}")]
     Bb8(#[from] bb8::RunError<RedisError>),
+    #[error("invalid key listing cursor `{0}`")]
+    Cursor(String),
+}
+
+/// Kinds of records in the KV store, backends may store each kind under its own key prefix
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordKind {
+    /// Base identities, keyed by principal, and their metadata
+    Identity,
+    /// `{provider}-login-{sub}` and `login-methods-{principal}`
+    OAuth,
+    /// `session-{id}` and `sessions-{principal}`
+    Session,
+    Passkey,
+    #[serde(rename = "email")]
+    EmailLogin,
+    #[default]
+    Other,
+}
+
+impl RecordKind {
+    pub const ALL: [Self; 6] = [
+        Self::Identity,
+        Self::OAuth,
+        Self::Session,
+        Self::Passkey,
+        Self::EmailLogin,
+        Self::Other,
+    ];
+}
+
+/// Key of a record, along with its kind
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct KvKey {
+    pub kind: RecordKind,
+    pub key: String,
+}
+
+impl KvKey {
+    pub fn new(kind: RecordKind, key: impl Into<String>) -> Self {
+        Self {
+            kind,
+            key: key.into(),
+        }
+    }
 }
 
-#[enum_dispatch]
+/// A page of [KVStore::list_keys_page]
+#[derive(Debug)]
+pub struct KeyPage {
+    pub keys: Vec<String>,
+    /// Where the next page starts, `None` once every key was listed
+    pub cursor: Option<String>,
+}
+
+/// Keys per page of [KVStore::list_keys]
+const LIST_PAGE_SIZE: usize = 1000;
+
 pub(crate) trait KVStore: Send {
-    async fn read(&self, key: String) -> Result<Option<String>, KVError>;
-    async fn write(&self, key: String, value: String) -> Result<(), KVError>;
+    async fn read(&self, key: KvKey) -> Result<Option<String>, KVError>;
+    /// Read several keys at once, values are in the same order as `keys`
+    async fn read_many(&self, keys: Vec<KvKey>) -> Result<Vec<Option<String>>, KVError>;
+    /// Write a value that never expires, clearing any previous expiry
+    async fn write(&self, key: KvKey, value: String) -> Result<(), KVError>;
+    /// Write a value that expires after `ttl`
+    async fn write_with_ttl(&self, key: KvKey, value: String, ttl: Duration)
+        -> Result<(), KVError>;
+    /// Remaining time to live of `key`, `None` if the key doesn't expire or doesn't exist
+    async fn ttl(&self, key: KvKey) -> Result<Option<Duration>, KVError>;
+    /// Returns true if the key existed
+    async fn delete(&self, key: KvKey) -> Result<bool, KVError>;
+    /// Atomically write `value` if the current value is `expected`
+    /// `expected = None` only writes if the key doesn't exist.
+    /// The value expires after `ttl`, or never if `None`
+    /// Returns true if the value was written
+    async fn compare_and_set(
+        &self,
+        key: KvKey,
+        expected: Option<String>,
+        value: String,
+        ttl: Option<Duration>,
+    ) -> Result<bool, KVError>;
+    /// About `limit` keys of `kind` starting with `prefix`, from `cursor` or the start if `None`
+    /// kinds sharing a key prefix in the backend are listed together.
+    /// Keys present during the whole listing are returned at least once, pages may be empty
+    async fn list_keys_page(
+        &self,
+        kind: RecordKind,
+        prefix: String,
+        cursor: Option<String>,
+        limit: usize,
+    ) -> Result<KeyPage, KVError>;
+    /// Every key of `kind` starting with `prefix`, see [KVStore::list_keys_page]
+    async fn list_keys(&self, kind: RecordKind, prefix: String) -> Result<Vec<String>, KVError> {
+        let mut keys = vec![];
+        let mut cursor = None;
+        loop {
+            let page = self
+                .list_keys_page(kind, prefix.clone(), cursor, LIST_PAGE_SIZE)
+                .await?;
+            keys.extend(page.keys);
+            cursor = page.cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        keys.sort_unstable();
+        keys.dedup();
+        Ok(keys)
+    }
 }
 
 #[derive(Clone)]
-#[enum_dispatch(KVStore)]
 pub enum KVStoreImpl {
+    Memory(memory_kv::MemoryKV),
     ReDB(redb_kv::ReDBKV),
     Redis(redis_kv::RedisKV),
 }
+
+/// Call `method` on the backend in a span, recording the latency of the operation
+macro_rules! dispatch {
+    ($self:ident.$method:ident($($arg:expr),*)) => {
+        time_kv_op(stringify!($method), async {
+            match $self {
+                Self::Memory(kv) => kv.$method($($arg),*).await,
+                Self::ReDB(kv) => kv.$method($($arg),*).await,
+                Self::Redis(kv) => kv.$method($($arg),*).await,
+            }
+        })
+        .instrument(tracing::info_span!(parent: &parent_span(), "kv", op = stringify!($method)))
+        .await
+    };
+}
+
+impl KVStore for KVStoreImpl {
+    async fn read(&self, key: KvKey) -> Result<Option<String>, KVError> {
+        dispatch!(self.read(key))
+    }
+
+    async fn read_many(&self, keys: Vec<KvKey>) -> Result<Vec<Option<String>>, KVError> {
+        dispatch!(self.read_many(keys))
+    }
+
+    async fn write(&self, key: KvKey, value: String) -> Result<(), KVError> {
+        dispatch!(self.write(key, value))
+    }
+
+    async fn write_with_ttl(
+        &self,
+        key: KvKey,
+        value: String,
+        ttl: Duration,
+    ) -> Result<(), KVError> {
+        dispatch!(self.write_with_ttl(key, value, ttl))
+    }
+
+    async fn ttl(&self, key: KvKey) -> Result<Option<Duration>, KVError> {
+        dispatch!(self.ttl(key))
+    }
+
+    async fn delete(&self, key: KvKey) -> Result<bool, KVError> {
+        dispatch!(self.delete(key))
+    }
+
+    async fn compare_and_set(
+        &self,
+        key: KvKey,
+        expected: Option<String>,
+        value: String,
+        ttl: Option<Duration>,
+    ) -> Result<bool, KVError> {
+        dispatch!(self.compare_and_set(key, expected, value, ttl))
+    }
+
+    async fn list_keys_page(
+        &self,
+        kind: RecordKind,
+        prefix: String,
+        cursor: Option<String>,
+        limit: usize,
+    ) -> Result<KeyPage, KVError> {
+        dispatch!(self.list_keys_page(kind, prefix, cursor, limit))
+    }
+
+    async fn list_keys(&self, kind: RecordKind, prefix: String) -> Result<Vec<String>, KVError> {
+        dispatch!(self.list_keys(kind, prefix))
+    }
+}
+
+impl KVStoreImpl {
+    /// Prefix the backend stores keys of `kind` under
+    pub fn key_prefix(&self, kind: RecordKind) -> &str {
+        match self {
+            Self::Redis(kv) => kv.prefixes().prefix(kind),
+            Self::Memory(_) | Self::ReDB(_) => "",
+        }
+    }
+}
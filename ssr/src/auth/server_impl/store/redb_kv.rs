@@ -1,23 +1,51 @@
-use std::sync::Arc;
+use std::{ops::Bound, path::Path, sync::Arc};
 
-use redb::{Database, TableDefinition};
-use tokio::task::spawn_blocking;
+use redb::{Database, ReadableTable, TableDefinition};
+use tokio::task::{spawn_blocking, JoinHandle};
+use web_time::Duration;
 
-use super::{KVError, KVStore};
+use crate::utils::current_epoch;
+
+use super::{KVError, KVStore, KeyPage, KvKey, RecordKind};
+
+pub const DEFAULT_PATH: &str = "./redb-kv.db";
 
 const TABLE: TableDefinition<&str, &str> = TableDefinition::new("kv");
 const RAW_METADATA_TABLE: TableDefinition<&str, &str> = TableDefinition::new("kv-meta");
+/// Expiry of keys written with a TTL, in epoch milliseconds
+const EXPIRY_TABLE: TableDefinition<&str, u64> = TableDefinition::new("kv-expiry");
+
+fn now_ms() -> u64 {
+    current_epoch().as_millis() as u64
+}
+
+/// Whether `key` has expired, expired keys are treated as missing until swept
+fn is_expired(
+    expiry: &impl ReadableTable<&'static str, u64>,
+    key: &str,
+    now_ms: u64,
+) -> Result<bool, redb::Error> {
+    Ok(expiry
+        .get(key)?
+        .is_some_and(|expires_at| expires_at.value() <= now_ms))
+}
 
 #[derive(Clone)]
 pub struct ReDBKV(Arc<Database>);
 
 impl ReDBKV {
     pub fn new() -> Result<Self, redb::Error> {
-        let db = Database::create("./redb-kv.db")?;
+        Self::open(DEFAULT_PATH)
+    }
+
+    /// Open or create the database at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, redb::Error> {
+        let db = Database::create(path)?;
         let write_txn = db.begin_write()?;
         {
             write_txn.open_table(TABLE)?;
             write_txn.open_table(RAW_METADATA_TABLE)?;
+            write_txn.open_table(EXPIRY_TABLE)?;
         }
         write_txn.commit()?;
         Ok(Self(Arc::new(db)))
@@ -31,16 +59,90 @@ impl ReDBKV {
         let db = self.0.clone();
         spawn_blocking(move || f(&db).map_err(|e| e.into()))
     }
+
+    /// Remove expired keys, returning the number of keys removed
+    pub async fn sweep_expired(&self) -> Result<usize, KVError> {
+        self.spawn_blocking(|db| {
+            let now = now_ms();
+            let write_txn = db.begin_write()?;
+            let removed = {
+                let mut table = write_txn.open_table(TABLE)?;
+                let mut expiry = write_txn.open_table(EXPIRY_TABLE)?;
+                let expired: Vec<String> = expiry
+                    .iter()?
+                    .filter_map(|entry| {
+                        entry
+                            .map(|(key, expires_at)| {
+                                (expires_at.value() <= now).then(|| key.value().to_string())
+                            })
+                            .transpose()
+                    })
+                    .collect::<Result<_, _>>()?;
+                for key in &expired {
+                    table.remove(key.as_str())?;
+                    expiry.remove(key.as_str())?;
+                }
+                expired.len()
+            };
+            write_txn.commit()?;
+            Ok(removed)
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Periodically remove expired keys in the background
+    pub fn spawn_sweeper(&self, interval: Duration) -> JoinHandle<()> {
+        let kv = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = kv.sweep_expired().await {
+                    log::warn!("failed to sweep expired keys {e}");
+                }
+            }
+        })
+    }
+
+    fn write_expiring(
+        &self,
+        key: String,
+        value: String,
+        expires_at: Option<u64>,
+    ) -> JoinHandle<Result<(), KVError>> {
+        self.spawn_blocking(move |db| {
+            let write_txn = db.begin_write()?;
+            {
+                let mut table = write_txn.open_table(TABLE)?;
+                let mut expiry = write_txn.open_table(EXPIRY_TABLE)?;
+                table.insert(key.as_str(), value.as_str())?;
+                if let Some(expires_at) = expires_at {
+                    expiry.insert(key.as_str(), expires_at)?;
+                } else {
+                    expiry.remove(key.as_str())?;
+                }
+            }
+            write_txn.commit()?;
+            Ok::<_, redb::Error>(())
+        })
+    }
 }
 
 impl KVStore for ReDBKV {
-    async fn read(&self, key: String) -> Result<Option<String>, KVError> {
+    async fn read(&self, key: KvKey) -> Result<Option<String>, KVError> {
+        let key = key.key;
         self.spawn_blocking(move |db| {
             let read_txn = db.begin_read()?;
             let value = {
                 let table = read_txn.open_table(TABLE)?;
-                let v = table.get(key.as_str())?;
-                v.map(|ag| ag.value().to_string())
+                let expiry = read_txn.open_table(EXPIRY_TABLE)?;
+                if is_expired(&expiry, &key, now_ms())? {
+                    None
+                } else {
+                    let v = table.get(key.as_str())?;
+                    v.map(|ag| ag.value().to_string())
+                }
             };
             Ok(value)
         })
@@ -48,15 +150,150 @@ impl KVStore for ReDBKV {
         .unwrap()
     }
 
-    async fn write(&self, key: String, value: String) -> Result<(), KVError> {
+    async fn read_many(&self, keys: Vec<KvKey>) -> Result<Vec<Option<String>>, KVError> {
+        let keys: Vec<_> = keys.into_iter().map(|key| key.key).collect();
+        self.spawn_blocking(move |db| {
+            let now = now_ms();
+            let read_txn = db.begin_read()?;
+            let table = read_txn.open_table(TABLE)?;
+            let expiry = read_txn.open_table(EXPIRY_TABLE)?;
+            let mut values = Vec::with_capacity(keys.len());
+            for key in &keys {
+                let value = if is_expired(&expiry, key, now)? {
+                    None
+                } else {
+                    table.get(key.as_str())?.map(|v| v.value().to_string())
+                };
+                values.push(value);
+            }
+            Ok(values)
+        })
+        .await
+        .unwrap()
+    }
+
+    async fn write(&self, key: KvKey, value: String) -> Result<(), KVError> {
+        self.write_expiring(key.key, value, None).await.unwrap()
+    }
+
+    async fn write_with_ttl(
+        &self,
+        key: KvKey,
+        value: String,
+        ttl: Duration,
+    ) -> Result<(), KVError> {
+        let expires_at = now_ms() + ttl.as_millis() as u64;
+        self.write_expiring(key.key, value, Some(expires_at))
+            .await
+            .unwrap()
+    }
+
+    async fn ttl(&self, key: KvKey) -> Result<Option<Duration>, KVError> {
+        let key = key.key;
+        self.spawn_blocking(move |db| {
+            let now = now_ms();
+            let read_txn = db.begin_read()?;
+            let expiry = read_txn.open_table(EXPIRY_TABLE)?;
+            let expires_at = expiry.get(key.as_str())?.map(|v| v.value());
+            Ok(expires_at
+                .filter(|&expires_at| expires_at > now)
+                .map(|expires_at| Duration::from_millis(expires_at - now)))
+        })
+        .await
+        .unwrap()
+    }
+
+    async fn delete(&self, key: KvKey) -> Result<bool, KVError> {
+        let key = key.key;
         self.spawn_blocking(move |db| {
             let write_txn = db.begin_write()?;
-            {
+            let existed = {
                 let mut table = write_txn.open_table(TABLE)?;
-                table.insert(key.as_str(), value.as_str())?;
-            }
+                let mut expiry = write_txn.open_table(EXPIRY_TABLE)?;
+                let expired = is_expired(&expiry, &key, now_ms())?;
+                expiry.remove(key.as_str())?;
+                let prev = table.remove(key.as_str())?;
+                prev.is_some() && !expired
+            };
             write_txn.commit()?;
-            Ok::<_, redb::Error>(())
+            Ok::<_, redb::Error>(existed)
+        })
+        .await
+        .unwrap()
+    }
+
+    async fn compare_and_set(
+        &self,
+        key: KvKey,
+        expected: Option<String>,
+        value: String,
+        ttl: Option<Duration>,
+    ) -> Result<bool, KVError> {
+        let key = key.key;
+        // redb serializes write transactions, so the read and write are atomic
+        self.spawn_blocking(move |db| {
+            let now = now_ms();
+            let write_txn = db.begin_write()?;
+            let written = {
+                let mut table = write_txn.open_table(TABLE)?;
+                let mut expiry = write_txn.open_table(EXPIRY_TABLE)?;
+                let current = if is_expired(&expiry, &key, now)? {
+                    None
+                } else {
+                    table.get(key.as_str())?.map(|v| v.value().to_string())
+                };
+                if current == expected {
+                    table.insert(key.as_str(), value.as_str())?;
+                    if let Some(ttl) = ttl {
+                        expiry.insert(key.as_str(), now + ttl.as_millis() as u64)?;
+                    } else {
+                        expiry.remove(key.as_str())?;
+                    }
+                    true
+                } else {
+                    false
+                }
+            };
+            write_txn.commit()?;
+            Ok::<_, redb::Error>(written)
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Every kind shares a single namespace, keys are listed in order and the cursor is the last key
+    async fn list_keys_page(
+        &self,
+        _kind: RecordKind,
+        prefix: String,
+        cursor: Option<String>,
+        limit: usize,
+    ) -> Result<KeyPage, KVError> {
+        let limit = limit.max(1);
+        self.spawn_blocking(move |db| {
+            let now = now_ms();
+            let read_txn = db.begin_read()?;
+            let table = read_txn.open_table(TABLE)?;
+            let expiry = read_txn.open_table(EXPIRY_TABLE)?;
+            let start = match &cursor {
+                Some(cursor) => Bound::Excluded(cursor.as_str()),
+                None => Bound::Included(prefix.as_str()),
+            };
+            let mut keys = vec![];
+            for entry in table.range::<&str>((start, Bound::Unbounded))? {
+                let (key, _) = entry?;
+                let key = key.value();
+                if !key.starts_with(&prefix) || keys.len() == limit {
+                    break;
+                }
+                if !is_expired(&expiry, key, now)? {
+                    keys.push(key.to_string());
+                }
+            }
+            let cursor = (keys.len() == limit)
+                .then(|| keys.last().cloned())
+                .flatten();
+            Ok(KeyPage { keys, cursor })
         })
         .await
         .unwrap()
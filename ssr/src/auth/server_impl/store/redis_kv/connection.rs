@@ -0,0 +1,198 @@
+use async_trait::async_trait;
+use bb8::ManageConnection;
+use redis::{
+    aio::{ConnectionLike, MultiplexedConnection},
+    cluster::ClusterClient,
+    cluster_async::ClusterConnection,
+    sentinel::{SentinelClient, SentinelServerType},
+    Client, Cmd, ConnectionAddr, ConnectionInfo, Pipeline, RedisError, RedisFuture, Value,
+};
+use tokio::sync::Mutex;
+
+/// How to reach the redis deployment
+#[derive(Clone, Debug)]
+pub enum RedisTopology {
+    /// A single server, e.g `redis://127.0.0.1:6379`
+    Standalone(String),
+    /// The master of `master_name`, as reported by `sentinels`
+    Sentinel {
+        sentinels: Vec<String>,
+        master_name: String,
+    },
+    /// A cluster reachable through any of the seed nodes
+    Cluster(Vec<String>),
+}
+
+#[derive(Clone)]
+pub enum RedisConnection {
+    Single(MultiplexedConnection),
+    Cluster(ClusterConnection),
+}
+
+impl ConnectionLike for RedisConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        match self {
+            Self::Single(con) => con.req_packed_command(cmd),
+            Self::Cluster(con) => con.req_packed_command(cmd),
+        }
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        match self {
+            Self::Single(con) => con.req_packed_commands(cmd, offset, count),
+            Self::Cluster(con) => con.req_packed_commands(cmd, offset, count),
+        }
+    }
+
+    fn get_db(&self) -> i64 {
+        match self {
+            Self::Single(con) => con.get_db(),
+            Self::Cluster(con) => con.get_db(),
+        }
+    }
+}
+
+/// bb8 connection manager for every [RedisTopology]
+pub enum RedisManager {
+    Standalone(Client),
+    Sentinel(Mutex<SentinelClient>),
+    Cluster(ClusterClient),
+}
+
+impl RedisManager {
+    pub fn new(topology: RedisTopology) -> Result<Self, RedisError> {
+        Ok(match topology {
+            RedisTopology::Standalone(url) => Self::Standalone(Client::open(url)?),
+            RedisTopology::Sentinel {
+                sentinels,
+                master_name,
+            } => Self::Sentinel(Mutex::new(SentinelClient::build(
+                sentinels,
+                master_name,
+                None,
+                SentinelServerType::Master,
+            )?)),
+            RedisTopology::Cluster(nodes) => Self::Cluster(ClusterClient::new(nodes)?),
+        })
+    }
+}
+
+#[async_trait]
+impl ManageConnection for RedisManager {
+    type Connection = RedisConnection;
+    type Error = RedisError;
+
+    async fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        Ok(match self {
+            Self::Standalone(client) => {
+                RedisConnection::Single(client.get_multiplexed_async_connection().await?)
+            }
+            // the master is looked up again on every new connection, following failovers
+            Self::Sentinel(client) => {
+                RedisConnection::Single(client.lock().await.get_async_connection().await?)
+            }
+            Self::Cluster(client) => RedisConnection::Cluster(client.get_async_connection().await?),
+        })
+    }
+
+    async fn is_valid(&self, con: &mut Self::Connection) -> Result<(), Self::Error> {
+        let pong: String = redis::cmd("PING").query_async(con).await?;
+        if pong != "PONG" {
+            return Err((redis::ErrorKind::ResponseError, "ping request").into());
+        }
+        Ok(())
+    }
+
+    fn has_broken(&self, _: &mut Self::Connection) -> bool {
+        false
+    }
+}
+
+/// Clients for every master of the cluster, as SCAN only covers a single node
+/// sorted by address, so listings can resume on the same node.
+/// `seed` is the connection info of any node, its credentials and TLS settings are reused
+pub async fn cluster_masters(
+    con: &mut RedisConnection,
+    seed: &ConnectionInfo,
+) -> Result<Vec<Client>, RedisError> {
+    let nodes: String = redis::cmd("CLUSTER").arg("NODES").query_async(con).await?;
+    // <id> <ip:port@cport[,hostname]> <flags> ...
+    let mut addrs = vec![];
+    for line in nodes.lines() {
+        let mut fields = line.split_whitespace();
+        let (Some(_), Some(addr), Some(flags)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let flags: Vec<_> = flags.split(',').collect();
+        if !flags.contains(&"master") || flags.iter().any(|flag| flag.starts_with("fail")) {
+            continue;
+        }
+        let addr = addr.split(['@', ',']).next().unwrap_or_default();
+        let Some((host, port)) = addr.rsplit_once(':') else {
+            continue;
+        };
+        let Ok(port) = port.parse::<u16>() else {
+            continue;
+        };
+        addrs.push((host.to_string(), port));
+    }
+    addrs.sort_unstable();
+    addrs.dedup();
+
+    let mut masters = Vec::with_capacity(addrs.len());
+    for (host, port) in addrs {
+        let addr = match &seed.addr {
+            ConnectionAddr::TcpTls {
+                insecure,
+                tls_params,
+                ..
+            } => ConnectionAddr::TcpTls {
+                host,
+                port,
+                insecure: *insecure,
+                tls_params: tls_params.clone(),
+            },
+            _ => ConnectionAddr::Tcp(host, port),
+        };
+        masters.push(Client::open(ConnectionInfo {
+            addr,
+            redis: seed.redis.clone(),
+        })?);
+    }
+    Ok(masters)
+}
+
+impl RedisTopology {
+    /// Parse `redis://…`, `redis-sentinel:<master>@<url>,<url>…` or `redis-cluster:<url>,<url>…`
+    pub fn parse(spec: &str) -> Option<Self> {
+        fn urls(list: &str) -> Option<Vec<String>> {
+            let urls: Vec<_> = list
+                .split(',')
+                .map(str::trim)
+                .filter(|url| !url.is_empty())
+                .map(str::to_string)
+                .collect();
+            (!urls.is_empty()).then_some(urls)
+        }
+
+        if let Some(nodes) = spec.strip_prefix("redis-cluster:") {
+            Some(Self::Cluster(urls(nodes)?))
+        } else if let Some(rest) = spec.strip_prefix("redis-sentinel:") {
+            let (master_name, sentinels) = rest.split_once('@')?;
+            Some(Self::Sentinel {
+                sentinels: urls(sentinels)?,
+                master_name: master_name.to_string(),
+            })
+        } else if spec.starts_with("redis://") || spec.starts_with("rediss://") {
+            Some(Self::Standalone(spec.to_string()))
+        } else {
+            None
+        }
+    }
+}
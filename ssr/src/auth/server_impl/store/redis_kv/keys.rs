@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+
+use super::super::{KvKey, RecordKind};
+
+impl RecordKind {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "identity" => Self::Identity,
+            "oauth" => Self::OAuth,
+            "session" => Self::Session,
+            "passkey" => Self::Passkey,
+            "email" => Self::EmailLogin,
+            "other" => Self::Other,
+            _ => return None,
+        })
+    }
+}
+
+/// Prefixes prepended to keys before they're sent to redis
+/// the default is no prefix, which is how keys were stored before namespacing
+#[derive(Clone, Default, Debug)]
+pub struct KeyPrefixes {
+    default: String,
+    overrides: HashMap<RecordKind, String>,
+}
+
+impl KeyPrefixes {
+    /// Parse comma separated `kind=prefix` pairs, e.g `default=yral:,session=yral:session:`
+    /// kinds are `default`, `identity`, `oauth`, `session`, `passkey`, `email` and `other`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut prefixes = Self::default();
+        for pair in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+        {
+            let (kind, prefix) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected `kind=prefix`, got `{pair}`"))?;
+            if kind == "default" {
+                prefixes.default = prefix.to_string();
+                continue;
+            }
+            let kind = RecordKind::from_name(kind)
+                .ok_or_else(|| format!("unknown record kind `{kind}`"))?;
+            prefixes.overrides.insert(kind, prefix.to_string());
+        }
+        Ok(prefixes)
+    }
+
+    /// Prefix of the keys of `kind`
+    pub fn prefix(&self, kind: RecordKind) -> &str {
+        self.overrides
+            .get(&kind)
+            .map(String::as_str)
+            .unwrap_or(&self.default)
+    }
+
+    /// Key stored in redis for `key`
+    pub fn physical(&self, key: &KvKey) -> String {
+        format!("{}{}", self.prefix(key.kind), key.key)
+    }
+}
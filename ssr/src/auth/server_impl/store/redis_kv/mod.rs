@@ -0,0 +1,311 @@
+mod connection;
+mod keys;
+
+use std::{collections::HashMap, sync::Arc};
+
+use futures::future::try_join_all;
+use redis::{
+    cluster_routing::get_slot, AsyncCommands, ConnectionInfo, IntoConnectionInfo, RedisError,
+};
+use web_time::Duration;
+
+pub use connection::RedisTopology;
+pub use keys::KeyPrefixes;
+
+use connection::{cluster_masters, RedisConnection, RedisManager};
+
+use super::{KVError, KVStore, KeyPage, KvKey, RecordKind};
+
+#[derive(Clone)]
+pub struct RedisKV {
+    pool: bb8::Pool<RedisManager>,
+    prefixes: Arc<KeyPrefixes>,
+    /// Connection info of the first cluster node, `None` if this isn't a cluster
+    cluster_seed: Option<Arc<ConnectionInfo>>,
+}
+
+impl RedisKV {
+    pub async fn new(redis_url: &str) -> Result<Self, RedisError> {
+        Self::connect(
+            RedisTopology::Standalone(redis_url.to_string()),
+            KeyPrefixes::default(),
+        )
+        .await
+    }
+
+    pub async fn connect(
+        topology: RedisTopology,
+        prefixes: KeyPrefixes,
+    ) -> Result<Self, RedisError> {
+        let cluster_seed = match &topology {
+            RedisTopology::Cluster(nodes) => nodes
+                .first()
+                .map(|node| node.as_str().into_connection_info())
+                .transpose()?
+                .map(Arc::new),
+            _ => None,
+        };
+        let manager = RedisManager::new(topology)?;
+        Ok(Self {
+            pool: bb8::Pool::builder().build(manager).await?,
+            prefixes: Arc::new(prefixes),
+            cluster_seed,
+        })
+    }
+
+    pub fn prefixes(&self) -> &KeyPrefixes {
+        &self.prefixes
+    }
+}
+
+/// One `SCAN` call, returns the next cursor, 0 once the node was fully scanned
+async fn scan_page(
+    con: &mut RedisConnection,
+    cursor: u64,
+    pattern: &str,
+    count: usize,
+) -> Result<(u64, Vec<String>), RedisError> {
+    redis::cmd("SCAN")
+        .arg(cursor)
+        .arg("MATCH")
+        .arg(pattern)
+        .arg("COUNT")
+        .arg(count)
+        .query_async(con)
+        .await
+}
+
+const AUTH_FIELD: &str = "auth";
+
+/// KEYS[1]: key, ARGV[1]: field, ARGV[2]: value, ARGV[3]: ttl in ms or empty to persist
+const WRITE_SCRIPT: &str = r"
+redis.call('HSET', KEYS[1], ARGV[1], ARGV[2])
+if ARGV[3] == '' then
+    redis.call('PERSIST', KEYS[1])
+else
+    redis.call('PEXPIRE', KEYS[1], ARGV[3])
+end
+";
+
+/// KEYS[1]: key, ARGV[1]: field, ARGV[2]: '1' if a value is expected,
+/// ARGV[3]: expected value, ARGV[4]: new value, ARGV[5]: ttl in ms or empty to persist
+const COMPARE_AND_SET_SCRIPT: &str = r"
+local current = redis.call('HGET', KEYS[1], ARGV[1])
+if ARGV[2] == '1' then
+    if current ~= ARGV[3] then
+        return 0
+    end
+elseif current then
+    return 0
+end
+redis.call('HSET', KEYS[1], ARGV[1], ARGV[4])
+if ARGV[5] == '' then
+    redis.call('PERSIST', KEYS[1])
+else
+    redis.call('PEXPIRE', KEYS[1], ARGV[5])
+end
+return 1
+";
+
+fn ttl_arg(ttl: Option<Duration>) -> String {
+    ttl.map(|ttl| ttl.as_millis().max(1).to_string())
+        .unwrap_or_default()
+}
+
+/// `HGET` of our field for every key, in order
+fn hget_pipeline(keys: &[String]) -> redis::Pipeline {
+    let mut pipe = redis::pipe();
+    for key in keys {
+        pipe.hget(key, AUTH_FIELD);
+    }
+    pipe
+}
+
+/// Escape glob special characters for `SCAN MATCH`
+fn escape_pattern(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for c in prefix.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('*');
+    pattern
+}
+
+impl RedisKV {
+    // scripts are sent with EVAL, so they also work on clusters without loading them on every node
+    async fn write_inner(
+        &self,
+        key: KvKey,
+        value: String,
+        ttl: Option<Duration>,
+    ) -> Result<(), KVError> {
+        let mut con = self.pool.get().await?;
+        redis::cmd("EVAL")
+            .arg(WRITE_SCRIPT)
+            .arg(1)
+            .arg(self.prefixes.physical(&key))
+            .arg(AUTH_FIELD)
+            .arg(value)
+            .arg(ttl_arg(ttl))
+            .query_async::<_, ()>(&mut *con)
+            .await?;
+        Ok(())
+    }
+}
+
+impl KVStore for RedisKV {
+    async fn read(&self, key: KvKey) -> Result<Option<String>, KVError> {
+        let mut con = self.pool.get().await?;
+        let value: Option<String> = con.hget(self.prefixes.physical(&key), AUTH_FIELD).await?;
+        Ok(value)
+    }
+
+    async fn read_many(&self, keys: Vec<KvKey>) -> Result<Vec<Option<String>>, KVError> {
+        if keys.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut con = self.pool.get().await?;
+        let physical: Vec<_> = keys.iter().map(|key| self.prefixes.physical(key)).collect();
+        let RedisConnection::Cluster(cluster) = &*con else {
+            return Ok(hget_pipeline(&physical).query_async(&mut *con).await?);
+        };
+
+        // pipelines can't cross slots on clusters, send one per slot
+        let mut by_slot: HashMap<u16, Vec<usize>> = HashMap::new();
+        for (idx, key) in physical.iter().enumerate() {
+            by_slot
+                .entry(get_slot(key.as_bytes()))
+                .or_default()
+                .push(idx);
+        }
+        let batches = by_slot.into_values().map(|indices| {
+            let mut cluster = cluster.clone();
+            let keys: Vec<_> = indices.iter().map(|&idx| physical[idx].clone()).collect();
+            async move {
+                let values: Vec<Option<String>> =
+                    hget_pipeline(&keys).query_async(&mut cluster).await?;
+                Ok::<_, RedisError>(indices.into_iter().zip(values))
+            }
+        });
+        let mut values = vec![None; keys.len()];
+        for (idx, value) in try_join_all(batches).await?.into_iter().flatten() {
+            values[idx] = value;
+        }
+        Ok(values)
+    }
+
+    async fn write(&self, key: KvKey, value: String) -> Result<(), KVError> {
+        self.write_inner(key, value, None).await
+    }
+
+    async fn write_with_ttl(
+        &self,
+        key: KvKey,
+        value: String,
+        ttl: Duration,
+    ) -> Result<(), KVError> {
+        // the expiry applies to the whole hash
+        self.write_inner(key, value, Some(ttl)).await
+    }
+
+    async fn ttl(&self, key: KvKey) -> Result<Option<Duration>, KVError> {
+        let mut con = self.pool.get().await?;
+        // -1 if the key has no expiry, -2 if it doesn't exist
+        let ttl_ms: i64 = con.pttl(self.prefixes.physical(&key)).await?;
+        Ok((ttl_ms >= 0).then(|| Duration::from_millis(ttl_ms as u64)))
+    }
+
+    async fn delete(&self, key: KvKey) -> Result<bool, KVError> {
+        let mut con = self.pool.get().await?;
+        let removed: usize = con.hdel(self.prefixes.physical(&key), AUTH_FIELD).await?;
+        Ok(removed > 0)
+    }
+
+    async fn compare_and_set(
+        &self,
+        key: KvKey,
+        expected: Option<String>,
+        value: String,
+        ttl: Option<Duration>,
+    ) -> Result<bool, KVError> {
+        let mut con = self.pool.get().await?;
+        let written: bool = redis::cmd("EVAL")
+            .arg(COMPARE_AND_SET_SCRIPT)
+            .arg(1)
+            .arg(self.prefixes.physical(&key))
+            .arg(AUTH_FIELD)
+            .arg(if expected.is_some() { "1" } else { "0" })
+            .arg(expected.unwrap_or_default())
+            .arg(value)
+            .arg(ttl_arg(ttl))
+            .query_async(&mut *con)
+            .await?;
+        Ok(written)
+    }
+
+    /// The cursor is `{node}:{scan cursor}`, nodes being the masters of a cluster in address order
+    async fn list_keys_page(
+        &self,
+        kind: RecordKind,
+        prefix: String,
+        cursor: Option<String>,
+        limit: usize,
+    ) -> Result<KeyPage, KVError> {
+        let (node, scan_cursor) = match &cursor {
+            Some(cursor) => cursor
+                .split_once(':')
+                .and_then(|(node, scan)| Some((node.parse().ok()?, scan.parse().ok()?)))
+                .ok_or_else(|| KVError::Cursor(cursor.clone()))?,
+            None => (0usize, 0u64),
+        };
+        let mut con = self.pool.get().await?;
+        let ns = self.prefixes.prefix(kind);
+        let pattern = escape_pattern(&format!("{ns}{prefix}"));
+        let limit = limit.max(1);
+        let (next, physical, nodes) = match &self.cluster_seed {
+            Some(seed) => {
+                let masters = cluster_masters(&mut con, seed).await?;
+                let Some(master) = masters.get(node) else {
+                    return Err(KVError::Cursor(cursor.unwrap_or_default()));
+                };
+                let mut node_con =
+                    RedisConnection::Single(master.get_multiplexed_async_connection().await?);
+                let (next, physical) =
+                    scan_page(&mut node_con, scan_cursor, &pattern, limit).await?;
+                (next, physical, masters.len())
+            }
+            None => {
+                let (next, physical) = scan_page(&mut *con, scan_cursor, &pattern, limit).await?;
+                (next, physical, 1)
+            }
+        };
+        drop(con);
+        let cursor = if next != 0 {
+            Some(format!("{node}:{next}"))
+        } else if node + 1 < nodes {
+            Some(format!("{}:0", node + 1))
+        } else {
+            None
+        };
+
+        let mut keys: Vec<_> = physical
+            .iter()
+            .filter_map(|key| key.strip_prefix(ns))
+            .map(|key| KvKey::new(kind, key))
+            .collect();
+        keys.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+        keys.dedup();
+
+        // the hash may be missing our field
+        let values = self.read_many(keys.clone()).await?;
+        let keys = keys
+            .into_iter()
+            .zip(values)
+            .filter_map(|(key, value)| value.map(|_| key.key))
+            .collect();
+        Ok(KeyPage { keys, cursor })
+    }
+}
@@ -0,0 +1,267 @@
+//! Behaviour every [KVStore] backend must share
+//!
+//! Redis is only tested if `REDIS_TEST_URL` points to a disposable server
+
+use web_time::Duration;
+
+use super::{memory_kv::MemoryKV, redb_kv::ReDBKV, KVStore, KvKey, RecordKind};
+
+const SHORT_TTL: Duration = Duration::from_millis(300);
+
+/// Keys unique to a test run, so runs sharing a backend don't interfere
+fn namespace() -> String {
+    format!("kv-test-{:016x}-", rand::random::<u64>())
+}
+
+fn kv_key(key: impl Into<String>) -> KvKey {
+    KvKey::new(RecordKind::Other, key)
+}
+
+async fn expire() {
+    tokio::time::sleep(SHORT_TTL * 3).await;
+}
+
+async fn reads_and_writes(kv: &impl KVStore, ns: &str) {
+    let key = kv_key(format!("{ns}rw"));
+    assert_eq!(kv.read(key.clone()).await.unwrap(), None);
+
+    kv.write(key.clone(), "a".into()).await.unwrap();
+    assert_eq!(kv.read(key.clone()).await.unwrap().as_deref(), Some("a"));
+    kv.write(key.clone(), "b".into()).await.unwrap();
+    assert_eq!(kv.read(key.clone()).await.unwrap().as_deref(), Some("b"));
+
+    let missing = kv_key(format!("{ns}rw-missing"));
+    assert_eq!(
+        kv.read_many(vec![missing, key]).await.unwrap(),
+        vec![None, Some("b".to_string())]
+    );
+    assert_eq!(
+        kv.read_many(vec![]).await.unwrap(),
+        Vec::<Option<String>>::new()
+    );
+}
+
+async fn expires_keys(kv: &impl KVStore, ns: &str) {
+    let key = kv_key(format!("{ns}ttl"));
+    kv.write_with_ttl(key.clone(), "a".into(), SHORT_TTL)
+        .await
+        .unwrap();
+    let ttl = kv.ttl(key.clone()).await.unwrap().unwrap();
+    assert!(!ttl.is_zero() && ttl <= SHORT_TTL);
+
+    expire().await;
+    assert_eq!(kv.read(key.clone()).await.unwrap(), None);
+    assert_eq!(kv.read_many(vec![key.clone()]).await.unwrap(), vec![None]);
+    assert_eq!(kv.ttl(key.clone()).await.unwrap(), None);
+    assert!(!kv.delete(key).await.unwrap());
+}
+
+async fn write_clears_expiry(kv: &impl KVStore, ns: &str) {
+    let key = kv_key(format!("{ns}persist"));
+    kv.write_with_ttl(key.clone(), "a".into(), SHORT_TTL)
+        .await
+        .unwrap();
+    kv.write(key.clone(), "b".into()).await.unwrap();
+    assert_eq!(kv.ttl(key.clone()).await.unwrap(), None);
+
+    expire().await;
+    assert_eq!(kv.read(key).await.unwrap().as_deref(), Some("b"));
+}
+
+async fn deletes(kv: &impl KVStore, ns: &str) {
+    let key = kv_key(format!("{ns}delete"));
+    assert!(!kv.delete(key.clone()).await.unwrap());
+
+    kv.write(key.clone(), "a".into()).await.unwrap();
+    assert!(kv.delete(key.clone()).await.unwrap());
+    assert_eq!(kv.read(key.clone()).await.unwrap(), None);
+    assert!(!kv.delete(key).await.unwrap());
+}
+
+async fn compares_and_sets(kv: &impl KVStore, ns: &str) {
+    let key = kv_key(format!("{ns}cas"));
+    assert!(kv
+        .compare_and_set(key.clone(), None, "a".into(), None)
+        .await
+        .unwrap());
+    // the key exists now
+    assert!(!kv
+        .compare_and_set(key.clone(), None, "b".into(), None)
+        .await
+        .unwrap());
+    assert!(!kv
+        .compare_and_set(key.clone(), Some("stale".into()), "b".into(), None)
+        .await
+        .unwrap());
+    assert_eq!(kv.read(key.clone()).await.unwrap().as_deref(), Some("a"));
+
+    assert!(kv
+        .compare_and_set(key.clone(), Some("a".into()), "b".into(), None)
+        .await
+        .unwrap());
+    assert_eq!(kv.read(key.clone()).await.unwrap().as_deref(), Some("b"));
+    assert_eq!(kv.ttl(key).await.unwrap(), None);
+}
+
+async fn compares_and_sets_with_ttl(kv: &impl KVStore, ns: &str) {
+    let key = kv_key(format!("{ns}cas-ttl"));
+    assert!(kv
+        .compare_and_set(key.clone(), None, "a".into(), Some(SHORT_TTL))
+        .await
+        .unwrap());
+    let ttl = kv.ttl(key.clone()).await.unwrap().unwrap();
+    assert!(!ttl.is_zero() && ttl <= SHORT_TTL);
+
+    // expired keys count as missing
+    expire().await;
+    assert!(!kv
+        .compare_and_set(key.clone(), Some("a".into()), "b".into(), None)
+        .await
+        .unwrap());
+    assert!(kv
+        .compare_and_set(key.clone(), None, "b".into(), None)
+        .await
+        .unwrap());
+    assert_eq!(kv.ttl(key).await.unwrap(), None);
+}
+
+async fn lists_keys_by_prefix(kv: &impl KVStore, ns: &str) {
+    for name in ["list-a-1", "list-a-2", "list-ab", "list-b-1"] {
+        kv.write(kv_key(format!("{ns}{name}")), "v".into())
+            .await
+            .unwrap();
+    }
+    kv.write_with_ttl(kv_key(format!("{ns}list-a-expired")), "v".into(), SHORT_TTL)
+        .await
+        .unwrap();
+    expire().await;
+
+    let mut keys = kv
+        .list_keys(RecordKind::Other, format!("{ns}list-a-"))
+        .await
+        .unwrap();
+    keys.sort();
+    assert_eq!(keys, vec![format!("{ns}list-a-1"), format!("{ns}list-a-2")]);
+
+    let mut keys = kv
+        .list_keys(RecordKind::Other, format!("{ns}list-a"))
+        .await
+        .unwrap();
+    keys.sort();
+    assert_eq!(
+        keys,
+        vec![
+            format!("{ns}list-a-1"),
+            format!("{ns}list-a-2"),
+            format!("{ns}list-ab"),
+        ]
+    );
+
+    assert!(kv
+        .list_keys(RecordKind::Other, format!("{ns}list-c"))
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+async fn pages_keys(kv: &impl KVStore, ns: &str) {
+    let mut written: Vec<_> = (0..25).map(|i| format!("{ns}page-{i:02}")).collect();
+    for key in &written {
+        kv.write(kv_key(key.clone()), "v".into()).await.unwrap();
+    }
+
+    let mut listed = vec![];
+    let mut cursor = None;
+    loop {
+        let page = kv
+            .list_keys_page(RecordKind::Other, format!("{ns}page-"), cursor, 10)
+            .await
+            .unwrap();
+        listed.extend(page.keys);
+        cursor = page.cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    listed.sort();
+    listed.dedup();
+    written.sort();
+    assert_eq!(listed, written);
+}
+
+async fn conforms(kv: impl KVStore) {
+    let ns = namespace();
+    reads_and_writes(&kv, &ns).await;
+    expires_keys(&kv, &ns).await;
+    write_clears_expiry(&kv, &ns).await;
+    deletes(&kv, &ns).await;
+    compares_and_sets(&kv, &ns).await;
+    compares_and_sets_with_ttl(&kv, &ns).await;
+    lists_keys_by_prefix(&kv, &ns).await;
+    pages_keys(&kv, &ns).await;
+}
+
+#[tokio::test]
+async fn memory_kv_conforms() {
+    conforms(MemoryKV::new()).await;
+}
+
+#[tokio::test]
+async fn memory_kv_sweeps_expired_keys() {
+    let kv = MemoryKV::new();
+    kv.write_with_ttl(kv_key("expiring"), "a".into(), SHORT_TTL)
+        .await
+        .unwrap();
+    kv.write(kv_key("kept"), "b".into()).await.unwrap();
+    expire().await;
+
+    assert_eq!(kv.sweep_expired(), 1);
+    assert_eq!(
+        kv.list_keys(RecordKind::Other, String::new())
+            .await
+            .unwrap(),
+        vec!["kept"]
+    );
+}
+
+#[tokio::test]
+async fn redb_kv_conforms() {
+    let path = std::env::temp_dir().join(format!("{}redb.db", namespace()));
+    conforms(ReDBKV::open(&path).unwrap()).await;
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn redb_kv_sweeps_expired_keys() {
+    let path = std::env::temp_dir().join(format!("{}redb.db", namespace()));
+    let kv = ReDBKV::open(&path).unwrap();
+    kv.write_with_ttl(kv_key("expiring"), "a".into(), SHORT_TTL)
+        .await
+        .unwrap();
+    kv.write(kv_key("kept"), "b".into()).await.unwrap();
+    expire().await;
+
+    assert_eq!(kv.sweep_expired().await.unwrap(), 1);
+    assert_eq!(
+        kv.list_keys(RecordKind::Other, String::new())
+            .await
+            .unwrap(),
+        vec!["kept"]
+    );
+    drop(kv);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn redis_kv_conforms() {
+    use super::redis_kv::{KeyPrefixes, RedisKV, RedisTopology};
+
+    let Ok(url) = std::env::var("REDIS_TEST_URL") else {
+        eprintln!("REDIS_TEST_URL isn't set, skipping");
+        return;
+    };
+    let kv = RedisKV::connect(RedisTopology::Standalone(url), KeyPrefixes::default())
+        .await
+        .unwrap();
+    conforms(kv).await;
+}
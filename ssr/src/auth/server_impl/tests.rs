@@ -0,0 +1,310 @@
+//! Auth server functions running in process against the memory KV store
+//!
+//! Each [Browser] keeps the cookies set by previous calls, like a real browser would
+
+use std::{collections::BTreeMap, future::Future};
+
+use futures::future::join_all;
+
+use axum_extra::extract::cookie::{Cookie, Key};
+use candid::Principal;
+use http::{header, request::Parts, Request};
+use ic_agent::identity::Secp256k1Identity;
+use k256::SecretKey;
+use leptos::{create_runtime, expect_context, provide_context, ServerFnError};
+use leptos_axum::ResponseOptions;
+use rand_chacha::rand_core::OsRng;
+
+use crate::auth::DelegatedIdentityWire;
+
+use super::{
+    active_sessions_impl, cookies,
+    cookies::CookieKeys,
+    extract_or_generate_identity_impl, login_user_identity, logout_identity_impl,
+    renew_identity_impl, revoke_other_sessions_impl, revoke_session_impl, save_identity,
+    secrets::IdentityKeyring,
+    session,
+    store::{memory_kv::MemoryKV, KVStoreImpl},
+    try_extract_identity,
+};
+
+/// State shared by every browser, as on a single server
+#[derive(Clone)]
+struct Server {
+    kv: KVStoreImpl,
+    cookie_keys: CookieKeys,
+    keyring: IdentityKeyring,
+}
+
+impl Server {
+    fn new() -> Self {
+        Self {
+            kv: KVStoreImpl::Memory(MemoryKV::new()),
+            cookie_keys: CookieKeys::new(Key::from(&[7u8; 64]), vec![]),
+            keyring: IdentityKeyring::new(&[9u8; 32], &[]).unwrap(),
+        }
+    }
+}
+
+struct Browser {
+    server: Server,
+    user_agent: &'static str,
+    cookies: BTreeMap<String, String>,
+}
+
+impl Browser {
+    fn new(server: &Server, user_agent: &'static str) -> Self {
+        Self {
+            server: server.clone(),
+            user_agent,
+            cookies: BTreeMap::new(),
+        }
+    }
+
+    fn request_parts(&self) -> Parts {
+        let cookie = self
+            .cookies
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join("; ");
+        let mut req = Request::builder().header(header::USER_AGENT, self.user_agent);
+        if !cookie.is_empty() {
+            req = req.header(header::COOKIE, cookie);
+        }
+        req.body(()).unwrap().into_parts().0
+    }
+
+    /// Run `server_fn` as a request from this browser, keeping the cookies it sets
+    async fn call<T, Fut>(&mut self, server_fn: impl FnOnce() -> Fut) -> Result<T, ServerFnError>
+    where
+        Fut: Future<Output = Result<T, ServerFnError>>,
+    {
+        let runtime = create_runtime();
+        let resp = ResponseOptions::default();
+        provide_context(self.request_parts());
+        provide_context(resp.clone());
+        provide_context(self.server.kv.clone());
+        provide_context(self.server.cookie_keys.clone());
+        provide_context(self.server.keyring.clone());
+
+        let res = server_fn().await;
+        runtime.dispose();
+
+        for set_cookie in resp.0.read().headers.get_all(header::SET_COOKIE) {
+            let cookie = Cookie::parse(set_cookie.to_str().unwrap().to_string()).unwrap();
+            self.cookies
+                .insert(cookie.name().to_string(), cookie.value().to_string());
+        }
+        res
+    }
+
+    async fn identity(&mut self) -> Principal {
+        let wire = self.call(extract_or_generate_identity_impl).await.unwrap();
+        principal_of(&wire)
+    }
+
+    /// Log in with a new identity, as a login provider would
+    async fn login(&mut self) -> Principal {
+        let kv = self.server.kv.clone();
+        let wire = self
+            .call(|| async move {
+                let jar = cookies::signed_jar().await?;
+                let resp: ResponseOptions = expect_context();
+                let identity = save_identity(&kv, SecretKey::random(&mut OsRng)).await?;
+                login_user_identity(&resp, jar, identity).await
+            })
+            .await
+            .unwrap();
+        principal_of(&wire)
+    }
+
+    /// Log into the identity another browser is logged into
+    async fn login_as(&mut self, other: &mut Browser) -> Principal {
+        let kv = self.server.kv.clone();
+        let secret = other
+            .call(|| async move {
+                let jar = cookies::signed_jar().await?;
+                try_extract_identity(&jar, &kv).await
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        let wire = self
+            .call(|| async move {
+                let jar = cookies::signed_jar().await?;
+                let resp: ResponseOptions = expect_context();
+                let identity = Secp256k1Identity::from_private_key(secret);
+                login_user_identity(&resp, jar, identity).await
+            })
+            .await
+            .unwrap();
+        principal_of(&wire)
+    }
+}
+
+fn principal_of(wire: &DelegatedIdentityWire) -> Principal {
+    Principal::self_authenticating(&wire.from_key)
+}
+
+#[tokio::test]
+async fn login_refresh_logout() {
+    let server = Server::new();
+    let mut browser = Browser::new(&server, "Mozilla/5.0 (X11; Linux x86_64) Firefox/126.0");
+
+    let guest = browser.identity().await;
+    assert_eq!(browser.identity().await, guest);
+
+    let user = browser.login().await;
+    assert_ne!(user, guest);
+    // the guest's session is replaced by the user's
+    assert!(session::list_sessions(&server.kv, guest)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // refreshing keeps the identity and the session
+    assert_eq!(browser.identity().await, user);
+    let renewed = browser.call(renew_identity_impl).await.unwrap().unwrap();
+    assert_eq!(principal_of(&renewed), user);
+    let sessions = browser.call(active_sessions_impl).await.unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert!(sessions[0].current);
+    assert_eq!(sessions[0].device, "Firefox on Linux");
+
+    let after_logout = principal_of(&browser.call(logout_identity_impl).await.unwrap());
+    assert_ne!(after_logout, user);
+    assert!(session::list_sessions(&server.kv, user)
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(browser.identity().await, after_logout);
+}
+
+#[tokio::test]
+async fn revoked_session_is_logged_out() {
+    let server = Server::new();
+    let mut phone = Browser::new(&server, "Mozilla/5.0 (Linux; Android 14) Chrome/125.0");
+    let mut laptop = Browser::new(&server, "Mozilla/5.0 (X11; Linux x86_64) Firefox/126.0");
+
+    let user = phone.login().await;
+    assert_eq!(laptop.login_as(&mut phone).await, user);
+    assert_eq!(
+        session::list_sessions(&server.kv, user)
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+
+    let laptop_session = laptop
+        .call(active_sessions_impl)
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|session| session.current)
+        .unwrap();
+    session::revoke_session(&server.kv, &laptop_session.id)
+        .await
+        .unwrap();
+
+    // the laptop's refresh token no longer works, it gets a new guest identity
+    assert_ne!(laptop.identity().await, user);
+    assert_eq!(phone.identity().await, user);
+}
+
+async fn current_session(browser: &mut Browser) -> String {
+    browser
+        .call(active_sessions_impl)
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|session| session.current)
+        .unwrap()
+        .id
+}
+
+#[tokio::test]
+async fn revoke_other_sessions_keeps_the_current_one() {
+    let server = Server::new();
+    let mut phone = Browser::new(&server, "Mozilla/5.0 (Linux; Android 14) Chrome/125.0");
+    let mut laptop = Browser::new(&server, "Mozilla/5.0 (X11; Linux x86_64) Firefox/126.0");
+    let mut tablet = Browser::new(&server, "Mozilla/5.0 (iPad; CPU OS 17_0) Safari/605.1");
+
+    let user = phone.login().await;
+    laptop.login_as(&mut phone).await;
+    tablet.login_as(&mut phone).await;
+    let phone_session = current_session(&mut phone).await;
+
+    phone.call(revoke_other_sessions_impl).await.unwrap();
+
+    let sessions = phone.call(active_sessions_impl).await.unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].id, phone_session);
+    assert_eq!(phone.identity().await, user);
+    assert_ne!(laptop.identity().await, user);
+    assert_ne!(tablet.identity().await, user);
+}
+
+#[tokio::test]
+async fn revoke_current_session_logs_out() {
+    let server = Server::new();
+    let mut phone = Browser::new(&server, "Mozilla/5.0 (Linux; Android 14) Chrome/125.0");
+    let mut laptop = Browser::new(&server, "Mozilla/5.0 (X11; Linux x86_64) Firefox/126.0");
+
+    let user = phone.login().await;
+    laptop.login_as(&mut phone).await;
+    let phone_session = current_session(&mut phone).await;
+
+    phone
+        .call(|| revoke_session_impl(phone_session))
+        .await
+        .unwrap();
+
+    assert_ne!(phone.identity().await, user);
+    assert_eq!(laptop.identity().await, user);
+    assert_eq!(
+        session::list_sessions(&server.kv, user)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn revoke_session_of_another_principal_is_rejected() {
+    let server = Server::new();
+    let mut victim = Browser::new(&server, "Mozilla/5.0 (Linux; Android 14) Chrome/125.0");
+    let mut attacker = Browser::new(&server, "Mozilla/5.0 (X11; Linux x86_64) Firefox/126.0");
+
+    let user = victim.login().await;
+    attacker.login().await;
+    let victim_session = current_session(&mut victim).await;
+
+    let res = attacker.call(|| revoke_session_impl(victim_session)).await;
+    assert!(res.is_err());
+    assert_eq!(victim.identity().await, user);
+}
+
+#[tokio::test]
+async fn concurrent_sessions_are_all_listed() {
+    let server = Server::new();
+    let principal = Principal::self_authenticating(b"concurrent");
+
+    let created = join_all(
+        (0..10).map(|_| session::create_session(&server.kv, principal, "Unknown device".into())),
+    )
+    .await;
+    let mut created: Vec<_> = created.into_iter().map(Result::unwrap).collect();
+    created.sort();
+
+    let mut listed: Vec<_> = session::list_sessions(&server.kv, principal)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+    listed.sort();
+    assert_eq!(listed, created);
+}
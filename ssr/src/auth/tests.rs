@@ -0,0 +1,159 @@
+//! Validation and wire format of delegated identities
+
+use ic_agent::{
+    identity::{Delegation, Secp256k1Identity, SignedDelegation},
+    Identity,
+};
+use rand_chacha::rand_core::OsRng;
+use serde_json::{json, Value};
+
+use crate::utils::current_epoch;
+
+use super::{DelegatedIdentityWire, DelegationError, WireEnvelope, MAX_DELEGATION_CHAIN_LEN};
+
+fn random_identity() -> Secp256k1Identity {
+    Secp256k1Identity::from_private_key(k256::SecretKey::random(&mut OsRng))
+}
+
+fn wire() -> DelegatedIdentityWire {
+    DelegatedIdentityWire::delegate_short_lived_identity(&random_identity())
+}
+
+fn expired_wire() -> DelegatedIdentityWire {
+    let from = random_identity();
+    let to_secret = k256::SecretKey::random(&mut OsRng);
+    let delegation = Delegation {
+        pubkey: Secp256k1Identity::from_private_key(to_secret.clone())
+            .public_key()
+            .unwrap(),
+        expiration: current_epoch().as_nanos() as u64 - 1,
+        targets: None,
+    };
+    let sig = from.sign_delegation(&delegation).unwrap();
+
+    DelegatedIdentityWire {
+        from_key: sig.public_key.unwrap(),
+        to_secret: to_secret.to_jwk(),
+        delegation_chain: vec![SignedDelegation {
+            delegation,
+            signature: sig.signature.unwrap(),
+        }],
+    }
+}
+
+#[test]
+fn accepts_valid_delegation() {
+    wire().validate().unwrap();
+}
+
+#[test]
+fn rejects_empty_chain() {
+    let mut wire = wire();
+    wire.delegation_chain.clear();
+    assert!(matches!(wire.validate(), Err(DelegationError::EmptyChain)));
+}
+
+#[test]
+fn rejects_long_chain() {
+    let mut wire = wire();
+    let signed = wire.delegation_chain[0].clone();
+    wire.delegation_chain = vec![signed; MAX_DELEGATION_CHAIN_LEN + 1];
+    assert!(matches!(
+        wire.validate(),
+        Err(DelegationError::ChainTooLong(len)) if len == MAX_DELEGATION_CHAIN_LEN + 1
+    ));
+}
+
+#[test]
+fn rejects_expired_delegation() {
+    assert!(matches!(
+        expired_wire().validate(),
+        Err(DelegationError::Expired)
+    ));
+}
+
+#[test]
+fn rejects_secret_on_another_curve() {
+    let mut wire = wire();
+    let jwk = serde_json::to_string(&wire.to_secret)
+        .unwrap()
+        .replace("secp256k1", "P-256");
+    wire.to_secret = serde_json::from_str(&jwk).unwrap();
+    assert!(matches!(
+        wire.validate(),
+        Err(DelegationError::InvalidSecret(_))
+    ));
+}
+
+#[test]
+fn rejects_mismatched_secret() {
+    let mut wire = wire();
+    wire.to_secret = k256::SecretKey::random(&mut OsRng).to_jwk();
+    assert!(matches!(wire.validate(), Err(DelegationError::KeyMismatch)));
+}
+
+#[test]
+fn rejects_unsupported_version() {
+    let mut envelope = WireEnvelope::from(wire());
+    envelope.version = 2;
+    assert!(matches!(
+        DelegatedIdentityWire::try_from(envelope),
+        Err(DelegationError::UnsupportedVersion(2))
+    ));
+
+    let mut value = serde_json::to_value(wire()).unwrap();
+    value["version"] = json!(2);
+    assert!(serde_json::from_value::<DelegatedIdentityWire>(value).is_err());
+}
+
+#[test]
+fn serializes_v1_flattened() {
+    let wire = wire();
+    let value = serde_json::to_value(&wire).unwrap();
+    assert_eq!(value["version"], json!(1));
+    assert_eq!(
+        value["from_key"],
+        serde_json::to_value(&wire.from_key).unwrap()
+    );
+    assert_eq!(
+        value["to_secret"],
+        serde_json::to_value(&wire.to_secret).unwrap()
+    );
+    assert_eq!(
+        value["delegation_chain"],
+        serde_json::to_value(&wire.delegation_chain).unwrap()
+    );
+}
+
+#[test]
+fn round_trips_v1() {
+    let value = serde_json::to_value(wire()).unwrap();
+    let decoded: DelegatedIdentityWire = serde_json::from_value(value.clone()).unwrap();
+    assert_eq!(serde_json::to_value(decoded).unwrap(), value);
+}
+
+#[test]
+fn accepts_unversioned_payload() {
+    let mut value = serde_json::to_value(wire()).unwrap();
+    value.as_object_mut().unwrap().remove("version");
+    let decoded: DelegatedIdentityWire = serde_json::from_value(value).unwrap();
+    decoded.validate().unwrap();
+
+    let reencoded: Value = serde_json::to_value(decoded).unwrap();
+    assert_eq!(reencoded["version"], json!(1));
+}
+
+#[test]
+fn rejects_invalid_payload() {
+    let mut wire = wire();
+    wire.to_secret = k256::SecretKey::random(&mut OsRng).to_jwk();
+    let value = serde_json::to_value(wire).unwrap();
+    assert!(serde_json::from_value::<DelegatedIdentityWire>(value).is_err());
+}
+
+#[test]
+fn decodes_expired_payload() {
+    let value = serde_json::to_value(expired_wire()).unwrap();
+    let decoded: DelegatedIdentityWire = serde_json::from_value(value).unwrap();
+    assert!(matches!(decoded.validate(), Err(DelegationError::Expired)));
+}
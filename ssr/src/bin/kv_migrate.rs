@@ -0,0 +1,88 @@
+//! Copy the KV store between backends, or to and from JSON-lines dumps
+//!
+//! Usage: `kv-migrate <from> <to> [--dry-run] [--progress <file>] [--from-prefixes <spec>] [--to-prefixes <spec>]`
+//! where endpoints are `redb:<path>`, `jsonl:<path>`, a redis url,
+//! `redis-sentinel:<master>@<urls>` or `redis-cluster:<urls>`.
+//! Prefixes use the `REDIS_KEY_PREFIXES` format
+
+use std::{env, process::ExitCode};
+
+use hot_or_not_web_leptos_ssr::auth::server_impl::store::{
+    migrate::{migrate, Endpoint, MigrateOptions},
+    redis_kv::KeyPrefixes,
+};
+
+const USAGE: &str = "usage: kv-migrate <from> <to> [--dry-run] [--progress <file>] [--from-prefixes <spec>] [--to-prefixes <spec>]
+endpoints: redb:<path> | jsonl:<path> | redis://<host>[:port][/db]
+           | redis-sentinel:<master>@<url>,<url>... | redis-cluster:<url>,<url>...
+prefixes:  comma separated kind=prefix pairs, as in REDIS_KEY_PREFIXES";
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let mut endpoints = vec![];
+    let mut options = MigrateOptions::default();
+    let mut from_prefixes = KeyPrefixes::default();
+    let mut to_prefixes = KeyPrefixes::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry-run" => options.dry_run = true,
+            "--progress" | "--from-prefixes" | "--to-prefixes" => {
+                let Some(value) = args.next() else {
+                    eprintln!("{USAGE}");
+                    return ExitCode::FAILURE;
+                };
+                if arg == "--progress" {
+                    options.progress = Some(value.into());
+                    continue;
+                }
+                let prefixes = match KeyPrefixes::parse(&value) {
+                    Ok(prefixes) => prefixes,
+                    Err(e) => {
+                        eprintln!("invalid {arg}: {e}");
+                        return ExitCode::FAILURE;
+                    }
+                };
+                if arg == "--from-prefixes" {
+                    from_prefixes = prefixes;
+                } else {
+                    to_prefixes = prefixes;
+                }
+            }
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            _ => endpoints.push(arg),
+        }
+    }
+    let [from, to] = endpoints.as_slice() else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+
+    let res = async {
+        let from = Endpoint::parse(from, from_prefixes).await?;
+        let to = Endpoint::parse(to, to_prefixes).await?;
+        migrate(&from, &to, &options).await
+    }
+    .await;
+
+    match res {
+        Ok(summary) => {
+            let action = if options.dry_run { "checked" } else { "copied" };
+            let resumed = if summary.resumed {
+                ", resuming an interrupted run"
+            } else {
+                ""
+            };
+            println!("{action} {} keys{resumed}", summary.copied);
+            println!("digest {}", summary.digest);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("migration failed: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
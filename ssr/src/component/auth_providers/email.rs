@@ -0,0 +1,71 @@
+use leptos::*;
+use leptos_icons::*;
+
+use super::{LoginProvButton, LoginProvCtx, ProviderKind};
+
+#[server]
+async fn send_email_login_link(email: String) -> Result<(), ServerFnError> {
+    use crate::auth::server_impl::email::send_login_link_impl;
+    send_login_link_impl(email).await
+}
+
+#[component]
+pub fn EmailProvider() -> impl IntoView {
+    let ctx: LoginProvCtx = expect_context();
+    let email = create_rw_signal(String::new());
+    let status = create_rw_signal(None::<Result<(), String>>);
+
+    let send_action = create_action(move |email: &String| {
+        let email = email.clone();
+        async move {
+            let res = send_email_login_link(email)
+                .await
+                .map_err(|e| e.to_string());
+            status.set(Some(res));
+            ctx.set_processing.set(None);
+        }
+    });
+
+    view! {
+        <div class="flex flex-col items-center gap-2 w-full">
+            <input
+                type="email"
+                placeholder="Email address"
+                class="w-full rounded-full bg-neutral-800 px-4 py-2 text-white"
+                prop:value=email
+                on:input=move |ev| email.set(event_target_value(&ev))
+            />
+            <LoginProvButton
+                prov=ProviderKind::Email
+                class="flex flex-row items-center justify-between gap-2 rounded-full bg-neutral-600 pr-4"
+                disabled=Signal::derive(move || email.with(|e| e.trim().is_empty()))
+                on_click=move |ev| {
+                    ev.stop_propagation();
+                    status.set(None);
+                    send_action.dispatch(email.get_untracked());
+                }
+            >
+
+                <div class="grid grid-cols-1 place-items-center bg-white p-2 rounded-full">
+                    <Icon class="text-xl rounded-full text-black" icon=icondata::AiMailOutlined/>
+                </div>
+                <span class="text-white">Email me a login link</span>
+            </LoginProvButton>
+            {move || {
+                status
+                    .get()
+                    .map(|res| match res {
+                        Ok(()) => {
+                            view! {
+                                <span class="text-sm text-white/50">
+                                    Check your inbox and open the link to log in
+                                </span>
+                            }
+                        }
+                        Err(e) => view! { <span class="text-sm text-red-500">{e}</span> },
+                    })
+            }}
+
+        </div>
+    }
+}
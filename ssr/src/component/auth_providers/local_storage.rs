@@ -3,24 +3,20 @@ use k256::elliptic_curve::JwkEcKey;
 use leptos::*;
 use leptos_use::{storage::use_local_storage, utils::JsonCodec};
 
-use crate::auth::DelegatedIdentityWire;
+use crate::{auth::DelegatedIdentityWire, consts::IDENTITY_JWK_STORE};
 
 use super::{LoginProvButton, LoginProvCtx, ProviderKind};
 
-const IDENTITY_JWK_STORE: &str = "id-jwk-insecure";
-
 #[server]
 async fn perform_local_storage_auth(
     secp256k1_key: Option<JwkEcKey>,
 ) -> Result<(DelegatedIdentityWire, JwkEcKey), ServerFnError> {
     use crate::auth::server_impl::{
-        store::KVStoreImpl, try_extract_identity, update_user_identity,
+        cookies, store::KVStoreImpl, try_extract_identity, update_user_identity,
     };
-    use axum_extra::extract::{cookie::Key, SignedCookieJar};
-    use leptos_axum::{extract_with_state, ResponseOptions};
+    use leptos_axum::ResponseOptions;
 
-    let key: Key = expect_context();
-    let jar: SignedCookieJar = extract_with_state(&key).await?;
+    let jar = cookies::signed_jar().await?;
     let kv: KVStoreImpl = expect_context();
     let base_key = if let Some(id) = secp256k1_key.as_ref() {
         k256::SecretKey::from_jwk(id)?
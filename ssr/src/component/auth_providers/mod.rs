@@ -1,12 +1,17 @@
-#[cfg(any(feature = "oauth-ssr", feature = "oauth-hydrate"))]
-mod google;
+#[cfg(any(feature = "email-ssr", feature = "email-hydrate"))]
+mod email;
 #[cfg(feature = "local-auth")]
 mod local_storage;
+#[cfg(any(feature = "oauth-ssr", feature = "oauth-hydrate"))]
+pub mod oidc;
+#[cfg(any(feature = "passkey-ssr", feature = "passkey-hydrate"))]
+pub mod passkey;
 
 use candid::Principal;
 use ic_agent::Identity;
 use leptos::*;
 use leptos_use::{storage::use_local_storage, utils::FromToStringCodec};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     auth::DelegatedIdentityWire,
@@ -58,12 +63,74 @@ async fn handle_user_login(
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum ProviderKind {
     #[cfg(feature = "local-auth")]
     LocalStorage,
     #[cfg(any(feature = "oauth-ssr", feature = "oauth-hydrate"))]
     Google,
+    #[cfg(any(feature = "oauth-ssr", feature = "oauth-hydrate"))]
+    Apple,
+    /// GitHub, through an OpenID Connect bridge
+    #[cfg(any(feature = "oauth-ssr", feature = "oauth-hydrate"))]
+    GitHub,
+    /// Self-hosted Keycloak realm
+    #[cfg(any(feature = "oauth-ssr", feature = "oauth-hydrate"))]
+    Keycloak,
+    /// Local OpenID Connect issuer, for development
+    #[cfg(any(feature = "mock-oidc-ssr", feature = "mock-oidc-hydrate"))]
+    MockOidc,
+    /// WebAuthn passkey
+    #[cfg(any(feature = "passkey-ssr", feature = "passkey-hydrate"))]
+    Passkey,
+    /// Magic link sent by email
+    #[cfg(any(feature = "email-ssr", feature = "email-hydrate"))]
+    Email,
+}
+
+impl ProviderKind {
+    /// Stable identifier of the provider
+    /// used in routes, cookies, KV keys and analytics
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "local-auth")]
+            Self::LocalStorage => "local_storage",
+            #[cfg(any(feature = "oauth-ssr", feature = "oauth-hydrate"))]
+            Self::Google => "google",
+            #[cfg(any(feature = "oauth-ssr", feature = "oauth-hydrate"))]
+            Self::Apple => "apple",
+            #[cfg(any(feature = "oauth-ssr", feature = "oauth-hydrate"))]
+            Self::GitHub => "github",
+            #[cfg(any(feature = "oauth-ssr", feature = "oauth-hydrate"))]
+            Self::Keycloak => "keycloak",
+            #[cfg(any(feature = "mock-oidc-ssr", feature = "mock-oidc-hydrate"))]
+            Self::MockOidc => "mock",
+            #[cfg(any(feature = "passkey-ssr", feature = "passkey-hydrate"))]
+            Self::Passkey => "passkey",
+            #[cfg(any(feature = "email-ssr", feature = "email-hydrate"))]
+            Self::Email => "email",
+        }
+    }
+
+    /// All providers that log in through OpenID Connect
+    #[cfg(any(feature = "oauth-ssr", feature = "oauth-hydrate"))]
+    pub fn oidc_providers() -> Vec<Self> {
+        vec![
+            Self::Google,
+            Self::Apple,
+            Self::GitHub,
+            Self::Keycloak,
+            #[cfg(any(feature = "mock-oidc-ssr", feature = "mock-oidc-hydrate"))]
+            Self::MockOidc,
+        ]
+    }
+
+    #[cfg(any(feature = "oauth-ssr", feature = "oauth-hydrate"))]
+    pub fn from_oidc_str(s: &str) -> Option<Self> {
+        Self::oidc_providers()
+            .into_iter()
+            .find(|prov| prov.as_str() == s)
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -171,7 +238,15 @@ pub fn LoginProviders(show_modal: RwSignal<bool>, lock_closing: RwSignal<bool>)
                 }
                 {
                     #[cfg(any(feature = "oauth-ssr", feature = "oauth-hydrate"))]
-                    view! { <google::GoogleAuthProvider></google::GoogleAuthProvider> }
+                    view! { <oidc::OidcAuthProviders></oidc::OidcAuthProviders> }
+                }
+                {
+                    #[cfg(any(feature = "passkey-ssr", feature = "passkey-hydrate"))]
+                    view! { <passkey::PasskeyProvider></passkey::PasskeyProvider> }
+                }
+                {
+                    #[cfg(any(feature = "email-ssr", feature = "email-hydrate"))]
+                    view! { <email::EmailProvider></email::EmailProvider> }
                 }
 
             </div>
@@ -0,0 +1,250 @@
+use leptos::*;
+use leptos_icons::*;
+use leptos_use::{use_event_listener, use_interval_fn, use_window};
+
+use crate::{
+    auth::{DelegatedIdentityWire, GuestActivity, LoginOutcome},
+    page::oidc_redirect::OidcAuthMessage,
+    utils::icon::icon_gen,
+};
+
+use super::{LoginProvButton, LoginProvCtx, ProviderKind};
+
+icon_gen!(
+    GoogleLogoSymbol,
+    view_box = "0 0 48 48",
+    r###"<path fill="#EA4335" d="M24 9.5c3.54 0 6.71 1.22 9.21 3.6l6.85-6.85C35.9 2.38 30.47 0 24 0 14.62 0 6.51 5.38 2.56 13.22l7.98 6.19C12.43 13.72 17.74 9.5 24 9.5z"></path><path fill="#4285F4" d="M46.98 24.55c0-1.57-.15-3.09-.38-4.55H24v9.02h12.94c-.58 2.96-2.26 5.48-4.78 7.18l7.73 6c4.51-4.18 7.09-10.36 7.09-17.65z"></path><path fill="#FBBC05" d="M10.53 28.59c-.48-1.45-.76-2.99-.76-4.59s.27-3.14.76-4.59l-7.98-6.19C.92 16.46 0 20.12 0 24c0 3.88.92 7.54 2.56 10.78l7.97-6.19z"></path><path fill="#34A853" d="M24 48c6.48 0 11.93-2.13 15.89-5.81l-7.73-6c-2.15 1.45-4.92 2.3-8.16 2.3-6.26 0-11.57-4.22-13.47-9.91l-7.98 6.19C6.51 42.62 14.62 48 24 48z"></path><path fill="none" d="M0 0h48v48H0z"></path>"###
+);
+
+#[server]
+pub async fn enabled_oidc_providers() -> Result<Vec<ProviderKind>, ServerFnError> {
+    use crate::auth::server_impl::oidc::OidcProviders;
+
+    let providers: OidcProviders = expect_context();
+    Ok(providers.enabled())
+}
+
+#[server]
+async fn transfer_guest_and_login() -> Result<DelegatedIdentityWire, ServerFnError> {
+    use crate::auth::server_impl::oidc::guest_merge::transfer_guest_and_login_impl;
+    transfer_guest_and_login_impl().await
+}
+
+#[server]
+async fn keep_guest_account() -> Result<(), ServerFnError> {
+    use crate::auth::server_impl::oidc::guest_merge::keep_guest_account_impl;
+    keep_guest_account_impl().await
+}
+
+pub fn provider_label(prov: ProviderKind) -> &'static str {
+    match prov {
+        ProviderKind::Google => "Google Sign-In",
+        ProviderKind::Apple => "Sign in with Apple",
+        ProviderKind::GitHub => "GitHub Sign-In",
+        ProviderKind::Keycloak => "Single Sign-On",
+        #[cfg(any(feature = "mock-oidc-ssr", feature = "mock-oidc-hydrate"))]
+        ProviderKind::MockOidc => "Mock Sign-In",
+        #[allow(unreachable_patterns)]
+        _ => "Sign-In",
+    }
+}
+
+pub fn provider_icon(prov: ProviderKind) -> icondata::Icon {
+    match prov {
+        ProviderKind::Google => GoogleLogoSymbol,
+        ProviderKind::Apple => icondata::AiAppleFilled,
+        ProviderKind::GitHub => icondata::AiGithubFilled,
+        #[cfg(any(feature = "mock-oidc-ssr", feature = "mock-oidc-hydrate"))]
+        ProviderKind::MockOidc => icondata::AiExperimentOutlined,
+        _ => icondata::AiKeyOutlined,
+    }
+}
+
+#[component]
+pub fn OidcAuthProvider(
+    prov: ProviderKind,
+    /// Set when the login is deferred because the guest account has activity
+    pending_merge: RwSignal<Option<GuestActivity>>,
+) -> impl IntoView {
+    let ctx: LoginProvCtx = expect_context();
+    let current_text = move || {
+        if ctx.processing.get() == Some(prov) {
+            "Signing In..."
+        } else {
+            provider_label(prov)
+        }
+    };
+    let done_guard = create_rw_signal(false);
+    let close_popup_store = store_value::<Option<Callback<()>>>(None);
+    let close_popup =
+        move || _ = close_popup_store.with_value(|cb| cb.as_ref().map(|close_cb| close_cb(())));
+
+    let on_click = move || {
+        let window = window();
+        let origin = window.origin();
+        let redirect_uri = format!("{origin}/auth/{}/perform_redirect", prov.as_str());
+        // Open a popup window with the redirect URL
+        let target = window
+            .open_with_url(&redirect_uri)
+            .transpose()
+            .and_then(|w| w.ok())
+            .unwrap();
+
+        // Check if the target window was closed by the user
+        let target_c = target.clone();
+        _ = use_interval_fn(
+            move || {
+                // Target window was closed by user
+                if target.closed().unwrap_or_default() && !done_guard() {
+                    ctx.set_processing.set(None);
+                }
+            },
+            500,
+        );
+
+        _ = use_event_listener(use_window(), ev::message, move |msg| {
+            if msg.origin() != origin {
+                return;
+            }
+
+            let Some(data) = msg.data().as_string() else {
+                log::warn!("received invalid message: {:?}", msg.data());
+                return;
+            };
+            let res = match serde_json::from_str::<OidcAuthMessage>(&data)
+                .map_err(|e| e.to_string())
+                .and_then(|r| r)
+            {
+                Ok(res) => res,
+                Err(e) => {
+                    log::warn!("error processing {:?}. msg {data}", e);
+                    close_popup();
+                    return;
+                }
+            };
+            done_guard.set(true);
+            _ = target_c.close();
+            match res {
+                LoginOutcome::LoggedIn(id) => ctx.login_complete.set(id),
+                LoginOutcome::GuestHasActivity(activity) => pending_merge.set(Some(activity)),
+            }
+        });
+    };
+
+    view! {
+        <LoginProvButton
+            prov
+            class="flex flex-row items-center justify-between gap-2 rounded-full bg-neutral-600 pr-4"
+            on_click=move |ev| {
+                ev.stop_propagation();
+                on_click()
+            }
+        >
+
+            <div class="grid grid-cols-1 place-items-center bg-white p-2 rounded-full">
+                <Icon class="text-xl rounded-full text-black" icon=provider_icon(prov)/>
+            </div>
+            <span class="text-white">{current_text}</span>
+        </LoginProvButton>
+    }
+}
+
+fn describe_activity(activity: GuestActivity) -> String {
+    let mut assets = vec![format!("{} COYNs", activity.balance)];
+    if activity.has_bets {
+        assets.push("bets".into());
+    }
+    if activity.has_posts {
+        assets.push("videos".into());
+    }
+    assets.join(", ")
+}
+
+/// Lets the user keep their guest account or transfer it into the account they logged into
+#[component]
+fn GuestMergePrompt(
+    activity: GuestActivity,
+    pending_merge: RwSignal<Option<GuestActivity>>,
+) -> impl IntoView {
+    let ctx: LoginProvCtx = expect_context();
+    let error = create_rw_signal(None::<String>);
+
+    let transfer_action = create_action(move |()| async move {
+        match transfer_guest_and_login().await {
+            Ok(id) => {
+                pending_merge.set(None);
+                ctx.login_complete.set(id);
+            }
+            Err(e) => error.set(Some(e.to_string())),
+        }
+    });
+    let keep_action = create_action(move |()| async move {
+        if let Err(e) = keep_guest_account().await {
+            log::warn!("failed to drop pending login {e}");
+        }
+        pending_merge.set(None);
+        ctx.set_processing.set(None);
+    });
+    let busy = move || transfer_action.pending()() || keep_action.pending()();
+
+    view! {
+        <div class="flex flex-col w-full gap-4 text-center">
+            <span class="text-sm">
+                {format!(
+                    "This login belongs to another account. Your guest account has {}.",
+                    describe_activity(activity),
+                )}
+            </span>
+            <button
+                class="rounded-full bg-primary-600 py-2 disabled:opacity-50"
+                disabled=busy
+                on:click=move |_| transfer_action.dispatch(())
+            >
+                Transfer to my account
+            </button>
+            <button
+                class="rounded-full bg-neutral-600 py-2 disabled:opacity-50"
+                disabled=busy
+                on:click=move |_| keep_action.dispatch(())
+            >
+                Keep guest account
+            </button>
+            <Show when=move || error.with(|e| e.is_some())>
+                <span class="text-sm text-red-500">{move || error.get()}</span>
+            </Show>
+        </div>
+    }
+}
+
+/// Login buttons for every OpenID Connect provider enabled on the server
+#[component]
+pub fn OidcAuthProviders() -> impl IntoView {
+    let providers = create_resource(|| (), |_| enabled_oidc_providers());
+    let pending_merge = create_rw_signal(None::<GuestActivity>);
+
+    view! {
+        <Show
+            when=move || pending_merge.with(|p| p.is_none())
+            fallback=move || {
+                pending_merge
+                    .get()
+                    .map(|activity| view! { <GuestMergePrompt activity pending_merge/> })
+            }
+        >
+
+            <Suspense>
+                {move || {
+                    providers()
+                        .and_then(|res| res.ok())
+                        .map(|providers| {
+                            providers
+                                .into_iter()
+                                .map(|prov| view! { <OidcAuthProvider prov pending_merge/> })
+                                .collect_view()
+                        })
+                }}
+
+            </Suspense>
+        </Show>
+    }
+}
@@ -0,0 +1,182 @@
+use leptos::*;
+use leptos_icons::*;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::DelegatedIdentityWire;
+
+use super::{LoginProvButton, LoginProvCtx, ProviderKind};
+
+/// Parameters for `navigator.credentials.create` and `navigator.credentials.get`
+/// binary values are base64url encoded
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    pub rp_name: String,
+    /// Account the passkey is created for, only set for registration
+    pub user: Option<PasskeyUser>,
+    /// Credentials already registered by the user
+    pub exclude_credentials: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PasskeyUser {
+    pub id: String,
+    pub name: String,
+}
+
+/// Newly created passkey, binary values are base64url encoded
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyRegistration {
+    pub id: String,
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    /// DER encoded SubjectPublicKeyInfo
+    pub public_key: String,
+    /// COSE algorithm identifier
+    pub public_key_algorithm: i64,
+}
+
+/// Signature over a login challenge, binary values are base64url encoded
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyAssertion {
+    pub id: String,
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+}
+
+#[server]
+async fn passkey_registration_options() -> Result<PasskeyOptions, ServerFnError> {
+    use crate::auth::server_impl::passkey::passkey_registration_options_impl;
+    passkey_registration_options_impl().await
+}
+
+#[server]
+async fn finish_passkey_registration(
+    registration: PasskeyRegistration,
+) -> Result<DelegatedIdentityWire, ServerFnError> {
+    use crate::auth::server_impl::passkey::finish_passkey_registration_impl;
+    finish_passkey_registration_impl(registration).await
+}
+
+#[server]
+async fn passkey_login_options() -> Result<PasskeyOptions, ServerFnError> {
+    use crate::auth::server_impl::passkey::passkey_login_options_impl;
+    passkey_login_options_impl().await
+}
+
+#[server]
+async fn perform_passkey_auth(
+    assertion: PasskeyAssertion,
+) -> Result<DelegatedIdentityWire, ServerFnError> {
+    use crate::auth::server_impl::passkey::perform_passkey_auth_impl;
+    perform_passkey_auth_impl(assertion).await
+}
+
+async fn create_credential(options: PasskeyOptions) -> Result<PasskeyRegistration, ServerFnError> {
+    #[cfg(feature = "hydrate")]
+    {
+        use crate::js::webauthn::create_passkey;
+
+        let options_json = serde_json::to_string(&options)?;
+        let registration = create_passkey(&options_json)
+            .await
+            .map_err(ServerFnError::new)?;
+        Ok(serde_json::from_str(&registration)?)
+    }
+    #[cfg(not(feature = "hydrate"))]
+    {
+        _ = options;
+        Err(ServerFnError::new(
+            "Passkeys are only available in the browser",
+        ))
+    }
+}
+
+async fn get_credential(options: PasskeyOptions) -> Result<PasskeyAssertion, ServerFnError> {
+    #[cfg(feature = "hydrate")]
+    {
+        use crate::js::webauthn::get_passkey;
+
+        let options_json = serde_json::to_string(&options)?;
+        let assertion = get_passkey(&options_json)
+            .await
+            .map_err(ServerFnError::new)?;
+        Ok(serde_json::from_str(&assertion)?)
+    }
+    #[cfg(not(feature = "hydrate"))]
+    {
+        _ = options;
+        Err(ServerFnError::new(
+            "Passkeys are only available in the browser",
+        ))
+    }
+}
+
+/// Register a passkey for the current identity and log in with it
+async fn register_passkey() -> Result<DelegatedIdentityWire, ServerFnError> {
+    let options = passkey_registration_options().await?;
+    let registration = create_credential(options).await?;
+    finish_passkey_registration(registration).await
+}
+
+async fn login_with_passkey() -> Result<DelegatedIdentityWire, ServerFnError> {
+    let options = passkey_login_options().await?;
+    let assertion = get_credential(options).await?;
+    perform_passkey_auth(assertion).await
+}
+
+#[component]
+pub fn PasskeyProvider() -> impl IntoView {
+    let ctx: LoginProvCtx = expect_context();
+
+    let login_action = create_action(move |&register: &bool| async move {
+        let res = if register {
+            register_passkey().await
+        } else {
+            login_with_passkey().await
+        };
+
+        match res {
+            Ok(delegation) => ctx.login_complete.set(delegation),
+            Err(e) => {
+                log::warn!("passkey login failed {e}");
+                ctx.set_processing.set(None);
+            }
+        }
+    });
+
+    view! {
+        <div class="flex flex-col items-center gap-2">
+            <LoginProvButton
+                prov=ProviderKind::Passkey
+                class="flex flex-row items-center justify-between gap-2 rounded-full bg-neutral-600 pr-4"
+                on_click=move |ev| {
+                    ev.stop_propagation();
+                    login_action.dispatch(false);
+                }
+            >
+
+                <div class="grid grid-cols-1 place-items-center bg-white p-2 rounded-full">
+                    <Icon class="text-xl rounded-full text-black" icon=icondata::AiKeyOutlined/>
+                </div>
+                <span class="text-white">Sign in with a passkey</span>
+            </LoginProvButton>
+            <LoginProvButton
+                prov=ProviderKind::Passkey
+                class="text-sm text-white/50 underline"
+                on_click=move |ev| {
+                    ev.stop_propagation();
+                    login_action.dispatch(true);
+                }
+            >
+
+                Create a passkey for this account
+            </LoginProvButton>
+        </div>
+    }
+}
@@ -1,16 +1,18 @@
 use candid::Principal;
+use ic_agent::{identity::DelegatedIdentity, Identity};
 use leptos::*;
 use leptos_router::*;
 
 use crate::{
-    auth::extract_or_generate_identity,
+    auth::{extract_or_generate_identity, renew_identity, DelegatedIdentityWire},
+    consts::auth::{DELEGATION_RENEWAL_MARGIN, DELEGATION_RENEWAL_RETRY},
     state::{
         auth::AuthState,
         canisters::{do_canister_auth, AuthCansResource, Canisters},
         local_storage::use_referrer_store,
     },
     try_or_redirect,
-    utils::MockPartialEq,
+    utils::{current_epoch, MockPartialEq},
 };
 
 #[derive(Params, PartialEq, Clone)]
@@ -30,6 +32,81 @@ fn CtxProvider(
     children()
 }
 
+/// Outcome of renewing the delegation
+enum Renewal {
+    /// Same principal, `cans` acting with the fresh delegation
+    Renewed(DelegatedIdentityWire, Canisters<true>),
+    /// Logged into another account in the meantime
+    Switched(DelegatedIdentityWire),
+    /// The refresh token expired or its session was revoked
+    Revoked,
+}
+
+/// Fresh delegation from the refresh token
+async fn renew_delegation(cans: &Canisters<true>) -> Result<Renewal, ServerFnError> {
+    let Some(wire) = renew_identity().await? else {
+        return Ok(Renewal::Revoked);
+    };
+    let id: DelegatedIdentity = wire.clone().try_into()?;
+    if id.sender().ok() != Some(cans.user_principal()) {
+        return Ok(Renewal::Switched(wire));
+    }
+    Ok(Renewal::Renewed(wire, cans.renewed(id)))
+}
+
+/// Renew the delegation shortly before it expires
+/// so long lived tabs can keep making canister calls
+fn schedule_delegation_renewal(
+    auth: AuthState,
+    canisters_res: AuthCansResource,
+    canisters_store: RwSignal<Option<Canisters<true>>>,
+) {
+    let retry = create_trigger();
+    let navigate = use_navigate();
+    let renew = create_action(move |cans: &Canisters<true>| {
+        let cans = cans.clone();
+        let navigate = navigate.clone();
+        async move {
+            match renew_delegation(&cans).await {
+                Ok(Renewal::Renewed(wire, renewed)) => {
+                    // swap the identity in place, without authenticating from scratch
+                    auth.set_untracked(Some(wire));
+                    canisters_res.set(Ok(renewed.clone()));
+                    canisters_store.set(Some(renewed));
+                }
+                Ok(Renewal::Switched(wire)) => auth.set(Some(wire)),
+                // don't silently continue as a new guest, log out like the user would
+                Ok(Renewal::Revoked) => {
+                    log::info!("session revoked, logging out");
+                    navigate("/logout", Default::default());
+                }
+                Err(e) => {
+                    log::warn!("failed to renew delegation {e}");
+                    retry.notify();
+                }
+            }
+        }
+    });
+
+    create_effect(move |_| {
+        retry.track();
+        let Some(cans) = canisters_store() else {
+            return;
+        };
+        let renew_at = web_time::Duration::from_nanos(cans.expiry_ns())
+            .saturating_sub(DELEGATION_RENEWAL_MARGIN);
+        let delay = renew_at
+            .saturating_sub(current_epoch())
+            .max(DELEGATION_RENEWAL_RETRY);
+        let handle = set_timeout_with_handle(move || renew.dispatch(cans), delay);
+        on_cleanup(move || {
+            if let Ok(handle) = handle {
+                handle.clear();
+            }
+        });
+    });
+}
+
 #[component]
 pub fn BaseRoute() -> impl IntoView {
     let auth = AuthState::default();
@@ -63,6 +140,8 @@ pub fn BaseRoute() -> impl IntoView {
         },
     );
 
+    schedule_delegation_renewal(auth, canisters_res, canisters_store);
+
     view! {
         <CtxProvider auth canisters_res>
             <Outlet/>
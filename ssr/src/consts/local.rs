@@ -1,10 +1,12 @@
 use once_cell::sync::Lazy;
 use reqwest::Url;
 
+use super::network;
+
 pub static METADATA_API_BASE: Lazy<Url> =
-    Lazy::new(|| Url::parse("http://localhost:8001").unwrap());
+    Lazy::new(|| Url::parse(network::LOCAL_METADATA_API_BASE).unwrap());
 
-pub const AGENT_URL: &str = "http://localhost:4943";
+pub const AGENT_URL: &str = network::LOCAL_AGENT_URL;
 
 pub const YRAL_BACKEND_CONTAINER_TAG: &str = "76bfd0fa78e4f862a4b30601f4ff3143aa974ee7";
 pub const YRAL_METADATA_CONTAINER_TAG: &str = "a4879e2e711c17beeb12ed6987ba315c110be9e5";
@@ -22,6 +22,8 @@ pub static CF_BASE_URL: Lazy<Url> =
     Lazy::new(|| Url::parse("https://api.cloudflare.com/client/v4/").unwrap());
 pub const NSFW_TOGGLE_STORE: &str = "nsfw-enabled";
 pub const REFERRER_STORE: &str = "referrer";
+/// JWK of the base identity, only used by the insecure local storage login
+pub const IDENTITY_JWK_STORE: &str = "id-jwk-insecure";
 
 pub static OFF_CHAIN_AGENT_GRPC_URL: Lazy<Url> =
     Lazy::new(|| Url::parse("https://icp-off-chain-agent.fly.dev:443").unwrap());
@@ -37,6 +39,14 @@ pub mod social {
     pub const IC_WEBSITE: &str = "https://vyatz-hqaaa-aaaam-qauea-cai.ic0.app";
 }
 
+/// Endpoints of the networks the server can be pointed at with `IC_NETWORK`
+pub mod network {
+    pub const MAINNET_AGENT_URL: &str = "https://ic0.app";
+    pub const MAINNET_METADATA_API_BASE: &str = "https://yral-metadata.fly.dev";
+    pub const LOCAL_AGENT_URL: &str = "http://localhost:4943";
+    pub const LOCAL_METADATA_API_BASE: &str = "http://localhost:8001";
+}
+
 pub mod auth {
     use web_time::Duration;
 
@@ -45,11 +55,32 @@ pub mod auth {
     /// Refresh expiry, 30 days
     pub const REFRESH_MAX_AGE: Duration = Duration::from_secs(60 * 60 * 24 * 30);
     pub const REFRESH_TOKEN_COOKIE: &str = "user-identity";
+    /// Expiry of the cookies kept during a login flow, e.g PKCE verifiers, 10 minutes
+    pub const FLOW_COOKIE_MAX_AGE: Duration = Duration::from_secs(60 * 10);
+    /// Delegations are renewed this long before they expire
+    pub const DELEGATION_RENEWAL_MARGIN: Duration = Duration::from_secs(60 * 60);
+    /// Minimum delay between delegation renewal attempts
+    pub const DELEGATION_RENEWAL_RETRY: Duration = Duration::from_secs(60);
 }
 
 #[cfg(feature = "oauth-ssr")]
-pub mod google {
+pub mod oidc {
+    pub const DEFAULT_SCOPES: &[&str] = &["openid"];
+
     pub const GOOGLE_AUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
     pub const GOOGLE_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
     pub const GOOGLE_ISSUER_URL: &str = "https://accounts.google.com";
+    pub const GOOGLE_JWKS_URL: &str = "https://www.googleapis.com/oauth2/v3/certs";
+
+    pub const APPLE_AUTH_URL: &str = "https://appleid.apple.com/auth/authorize";
+    pub const APPLE_TOKEN_URL: &str = "https://appleid.apple.com/auth/token";
+    pub const APPLE_ISSUER_URL: &str = "https://appleid.apple.com";
+    pub const APPLE_JWKS_URL: &str = "https://appleid.apple.com/auth/keys";
+
+    #[cfg(feature = "mock-oidc-ssr")]
+    pub const MOCK_OIDC_PATH: &str = "/mock-oidc";
+    #[cfg(feature = "mock-oidc-ssr")]
+    pub const MOCK_OIDC_CLIENT_ID: &str = "yral-local";
+    #[cfg(feature = "mock-oidc-ssr")]
+    pub const MOCK_OIDC_CLIENT_SECRET: &str = "yral-local-secret";
 }
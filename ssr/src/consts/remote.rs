@@ -1,7 +1,9 @@
 use once_cell::sync::Lazy;
 use reqwest::Url;
 
+use super::network;
+
 pub static METADATA_API_BASE: Lazy<Url> =
-    Lazy::new(|| Url::parse("https://yral-metadata.fly.dev").unwrap());
+    Lazy::new(|| Url::parse(network::MAINNET_METADATA_API_BASE).unwrap());
 
-pub const AGENT_URL: &str = "https://ic0.app";
+pub const AGENT_URL: &str = network::MAINNET_AGENT_URL;
@@ -0,0 +1,225 @@
+//! Health checks for the load balancer and deploys
+//!
+//! `/healthz` only tells that the process is serving requests,
+//! `/readyz` checks every dependency and fails with 503 if any of them is unavailable.
+//! `/readyz` is public, its result is cached so requests can't amplify calls to dependencies
+
+use std::{collections::BTreeMap, future::Future, pin::Pin, time::Instant};
+
+use axum::{extract::State, response::IntoResponse, Json};
+use futures::future::join_all;
+use http::StatusCode;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tokio::sync::Mutex;
+use web_time::Duration;
+
+use crate::{
+    auth::server_impl::store::{KVStore, KvKey, RecordKind},
+    state::{network::network, server::AppState},
+    utils::ic::AgentWrapper,
+};
+
+/// Each check fails if it takes longer than this
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Checks are only run again once the last result is older than this
+const READINESS_CACHE_TTL: Duration = Duration::from_secs(10);
+
+/// Key read to check that the KV store is reachable, never written
+const KV_PROBE_KEY: &str = "readyz-probe";
+
+/// Last readiness result, locked while checks run so concurrent requests share a single run
+static LAST_READINESS: Lazy<Mutex<Option<(Instant, ReadinessReport)>>> =
+    Lazy::new(|| Mutex::new(None));
+
+type Check = Pin<Box<dyn Future<Output = Result<(), String>> + Send>>;
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Ok,
+    Error,
+}
+
+#[derive(Serialize, Clone)]
+struct CheckReport {
+    status: Status,
+    latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+struct ReadinessReport {
+    status: Status,
+    checks: BTreeMap<&'static str, CheckReport>,
+}
+
+async fn run_check(check: Check) -> CheckReport {
+    let start = Instant::now();
+    let res = tokio::time::timeout(CHECK_TIMEOUT, check)
+        .await
+        .unwrap_or_else(|_| Err("timed out".into()));
+    let latency_ms = start.elapsed().as_millis();
+    match res {
+        Ok(()) => CheckReport {
+            status: Status::Ok,
+            latency_ms,
+            error: None,
+        },
+        Err(e) => CheckReport {
+            status: Status::Error,
+            latency_ms,
+            error: Some(e),
+        },
+    }
+}
+
+fn check_kv(state: &AppState) -> Check {
+    let kv = state.kv.clone();
+    Box::pin(async move {
+        kv.read(KvKey::new(RecordKind::Other, KV_PROBE_KEY))
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    })
+}
+
+fn check_ic_agent() -> Check {
+    Box::pin(async move {
+        let agent = AgentWrapper::build(|b| b);
+        agent
+            .get_agent()
+            .await
+            .map_err(|e| e.to_string())?
+            .status()
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    })
+}
+
+/// Any response short of a server error means the service is up
+fn check_metadata() -> Check {
+    Box::pin(async move {
+        let res = reqwest::get(network().metadata_api_base())
+            .await
+            .map_err(|e| e.to_string())?;
+        if res.status().is_server_error() {
+            return Err(format!("responded with {}", res.status()));
+        }
+        Ok(())
+    })
+}
+
+/// Calls the standard gRPC health check on the shared channel
+/// any gRPC response means the agent is reachable, even if it doesn't implement the check
+#[cfg(feature = "ga4")]
+fn check_offchain_grpc(state: &AppState) -> Check {
+    use tonic::{client::Grpc, codec::ProstCodec, codegen::http::uri::PathAndQuery, Code, Request};
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct HealthCheckRequest {
+        #[prost(string, tag = "1")]
+        service: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct HealthCheckResponse {
+        #[prost(int32, tag = "1")]
+        status: i32,
+    }
+
+    let mut grpc = Grpc::new(state.grpc_offchain_channel.clone());
+    Box::pin(async move {
+        grpc.ready().await.map_err(|e| e.to_string())?;
+        let res = grpc
+            .unary::<_, HealthCheckResponse, _>(
+                Request::new(HealthCheckRequest::default()),
+                PathAndQuery::from_static("/grpc.health.v1.Health/Check"),
+                ProstCodec::default(),
+            )
+            .await;
+        match res {
+            Err(status) if status.code() == Code::Unavailable => Err(status.to_string()),
+            _ => Ok(()),
+        }
+    })
+}
+
+/// Verifies the API token
+#[cfg(feature = "cloudflare")]
+fn check_cloudflare(state: &AppState) -> Check {
+    use crate::consts::CF_BASE_URL;
+
+    let token = state.config.cloudflare.token.clone();
+    Box::pin(async move {
+        let url = CF_BASE_URL
+            .join("user/tokens/verify")
+            .map_err(|e| e.to_string())?;
+        let res = reqwest::Client::new()
+            .get(url)
+            .bearer_auth(token)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !res.status().is_success() {
+            return Err(format!("token verification failed with {}", res.status()));
+        }
+        Ok(())
+    })
+}
+
+/// The process is up
+pub async fn healthz() -> impl IntoResponse {
+    Json(serde_json::json!({ "status": Status::Ok }))
+}
+
+async fn check_readiness(state: &AppState) -> ReadinessReport {
+    #[cfg_attr(not(any(feature = "ga4", feature = "cloudflare")), allow(unused_mut))]
+    let mut checks: Vec<(&'static str, Check)> = vec![
+        ("kv", check_kv(state)),
+        ("ic_agent", check_ic_agent()),
+        ("metadata", check_metadata()),
+    ];
+    #[cfg(feature = "ga4")]
+    checks.push(("offchain_grpc", check_offchain_grpc(state)));
+    #[cfg(feature = "cloudflare")]
+    checks.push(("cloudflare", check_cloudflare(state)));
+
+    let (names, checks): (Vec<_>, Vec<_>) = checks.into_iter().unzip();
+    let reports = join_all(checks.into_iter().map(run_check)).await;
+    let checks: BTreeMap<_, _> = names.into_iter().zip(reports).collect();
+
+    let status = if checks.values().all(|check| check.status == Status::Ok) {
+        Status::Ok
+    } else {
+        Status::Error
+    };
+    ReadinessReport { status, checks }
+}
+
+/// Every dependency is reachable, with the status and latency of each one
+/// results are reused for [READINESS_CACHE_TTL]
+pub async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    let mut last = LAST_READINESS.lock().await;
+    let report = match &*last {
+        Some((checked_at, report)) if checked_at.elapsed() < READINESS_CACHE_TTL => report.clone(),
+        _ => {
+            let report = check_readiness(&state).await;
+            if report.status == Status::Error {
+                log::warn!("readiness check failed");
+            }
+            *last = Some((Instant::now(), report.clone()));
+            report
+        }
+    };
+    drop(last);
+
+    let code = match report.status {
+        Status::Ok => StatusCode::OK,
+        Status::Error => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (code, Json(report))
+}
@@ -0,0 +1,499 @@
+#[cfg(test)]
+mod tests;
+
+use std::{env, fmt, fs};
+
+use serde::Deserialize;
+use web_time::Duration;
+
+use crate::{
+    auth::server_impl::secrets::MIN_KEY_LEN, rate_limit::RateLimits, state::network::NetworkConfig,
+};
+
+/// Path to an optional TOML file with the application's configuration
+pub const APP_CONFIG_FILE_ENV: &str = "APP_CONFIG_FILE";
+
+/// Configuration as written in the TOML file
+///
+/// Keys are the lowercase names of the env vars, e.g `cookie_key`.
+/// Env vars take precedence over the file
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+struct RawConfig {
+    cookie_key: Option<String>,
+    cookie_retired_keys: Option<String>,
+    identity_encryption_key: Option<String>,
+    identity_encryption_retired_keys: Option<String>,
+    kv_backend: Option<String>,
+    redis_url: Option<String>,
+    redis_sentinels: Option<String>,
+    redis_sentinel_master: Option<String>,
+    redis_cluster_nodes: Option<String>,
+    redis_key_prefixes: Option<String>,
+    rate_limits: Option<String>,
+    client_ip_header: Option<String>,
+    metrics_token: Option<String>,
+    guest_identity_max_age_days: Option<String>,
+    cf_token: Option<String>,
+    cf_account_id: Option<String>,
+    backend_admin_identity: Option<String>,
+    passkey_rp_id: Option<String>,
+    passkey_rp_name: Option<String>,
+    passkey_origin: Option<String>,
+    email_login_base_url: Option<String>,
+    smtp_url: Option<String>,
+    mail_from: Option<String>,
+    mail_output_file: Option<String>,
+    grpc_auth_token: Option<String>,
+    off_chain_agent_grpc_url: Option<String>,
+    ic_network: Option<String>,
+    ic_agent_url: Option<String>,
+    metadata_api_base: Option<String>,
+    ic_fetch_root_key: Option<String>,
+    oidc_config_file: Option<String>,
+}
+
+impl RawConfig {
+    fn override_from_env(&mut self) {
+        fn set(field: &mut Option<String>, name: &str) {
+            if let Some(value) = env::var(name).ok().filter(|v| !v.is_empty()) {
+                *field = Some(value);
+            }
+        }
+
+        set(&mut self.cookie_key, "COOKIE_KEY");
+        set(&mut self.cookie_retired_keys, "COOKIE_RETIRED_KEYS");
+        set(&mut self.identity_encryption_key, "IDENTITY_ENCRYPTION_KEY");
+        set(
+            &mut self.identity_encryption_retired_keys,
+            "IDENTITY_ENCRYPTION_RETIRED_KEYS",
+        );
+        set(&mut self.kv_backend, "KV_BACKEND");
+        set(&mut self.redis_url, "REDIS_URL");
+        set(&mut self.redis_sentinels, "REDIS_SENTINELS");
+        set(&mut self.redis_sentinel_master, "REDIS_SENTINEL_MASTER");
+        set(&mut self.redis_cluster_nodes, "REDIS_CLUSTER_NODES");
+        set(&mut self.redis_key_prefixes, "REDIS_KEY_PREFIXES");
+        set(&mut self.rate_limits, "RATE_LIMITS");
+        set(&mut self.client_ip_header, "CLIENT_IP_HEADER");
+        set(&mut self.metrics_token, "METRICS_TOKEN");
+        set(
+            &mut self.guest_identity_max_age_days,
+            "GUEST_IDENTITY_MAX_AGE_DAYS",
+        );
+        set(&mut self.cf_token, "CF_TOKEN");
+        set(&mut self.cf_account_id, "CF_ACCOUNT_ID");
+        set(&mut self.backend_admin_identity, "BACKEND_ADMIN_IDENTITY");
+        set(&mut self.passkey_rp_id, "PASSKEY_RP_ID");
+        set(&mut self.passkey_rp_name, "PASSKEY_RP_NAME");
+        set(&mut self.passkey_origin, "PASSKEY_ORIGIN");
+        set(&mut self.email_login_base_url, "EMAIL_LOGIN_BASE_URL");
+        set(&mut self.smtp_url, "SMTP_URL");
+        set(&mut self.mail_from, "MAIL_FROM");
+        set(&mut self.mail_output_file, "MAIL_OUTPUT_FILE");
+        set(&mut self.grpc_auth_token, "GRPC_AUTH_TOKEN");
+        set(
+            &mut self.off_chain_agent_grpc_url,
+            "OFF_CHAIN_AGENT_GRPC_URL",
+        );
+        set(&mut self.ic_network, "IC_NETWORK");
+        set(&mut self.ic_agent_url, "IC_AGENT_URL");
+        set(&mut self.metadata_api_base, "METADATA_API_BASE");
+        set(&mut self.ic_fetch_root_key, "IC_FETCH_ROOT_KEY");
+        set(&mut self.oidc_config_file, "OIDC_CONFIG_FILE");
+    }
+}
+
+/// Every problem found in the configuration
+#[derive(Debug)]
+pub struct ConfigError(Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "invalid configuration:")?;
+        for problem in &self.0 {
+            writeln!(f, "  - {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Collects problems so they're all reported at once
+#[derive(Default)]
+struct Validator(Vec<String>);
+
+impl Validator {
+    fn required(&mut self, name: &str, value: Option<String>) -> Option<String> {
+        if value.is_none() {
+            self.0.push(format!("`{name}` is required"));
+        }
+        value
+    }
+
+    fn parse<T>(
+        &mut self,
+        name: &str,
+        value: Option<String>,
+        parse: impl FnOnce(&str) -> Result<T, String>,
+    ) -> Option<T> {
+        match parse(value.as_deref()?) {
+            Ok(parsed) => Some(parsed),
+            Err(e) => {
+                self.0.push(format!("invalid `{name}`: {e}"));
+                None
+            }
+        }
+    }
+
+    fn finish(self) -> Result<(), ConfigError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError(self.0))
+        }
+    }
+}
+
+/// Comma separated values, ignoring empty ones
+fn list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn hex_key(value: &str, min_len: usize) -> Result<Vec<u8>, String> {
+    let key = hex::decode(value).map_err(|_| "must be hex".to_string())?;
+    if key.len() < min_len {
+        return Err(format!("must be at least {min_len} bytes"));
+    }
+    Ok(key)
+}
+
+fn hex_keys(value: &str, min_len: usize) -> Result<Vec<Vec<u8>>, String> {
+    list(value).iter().map(|k| hex_key(k, min_len)).collect()
+}
+
+fn url(value: &str) -> Result<String, String> {
+    reqwest::Url::parse(value).map_err(|e| e.to_string())?;
+    Ok(value.to_string())
+}
+
+/// Backend of the KV store
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KvBackend {
+    /// Redis with "redis-kv", ReDB otherwise
+    Persistent,
+    /// In memory, data is lost on restart
+    Memory,
+}
+
+#[cfg(feature = "cloudflare")]
+#[derive(Clone)]
+pub struct CloudflareConfig {
+    pub token: String,
+    pub account_id: String,
+}
+
+#[cfg(feature = "email-ssr")]
+#[derive(Clone)]
+pub enum MailerConfig {
+    Smtp {
+        url: String,
+        from: String,
+    },
+    /// Written to the file, or stdout if unset
+    File(Option<std::path::PathBuf>),
+}
+
+#[cfg(feature = "email-ssr")]
+#[derive(Clone)]
+pub struct EmailConfig {
+    pub mailer: MailerConfig,
+    pub link_base_url: String,
+}
+
+/// Configuration of the server, loaded once at startup
+#[derive(Clone)]
+pub struct AppConfig {
+    pub cookie_key: Vec<u8>,
+    pub cookie_retired_keys: Vec<Vec<u8>>,
+    pub identity_encryption_key: Vec<u8>,
+    pub identity_encryption_retired_keys: Vec<Vec<u8>>,
+    /// Passed to the client with the rendered page
+    pub network: NetworkConfig,
+    pub kv_backend: KvBackend,
+    #[cfg(all(feature = "redis-kv", not(feature = "local-bin")))]
+    pub redis_topology: crate::auth::server_impl::store::redis_kv::RedisTopology,
+    #[cfg(feature = "redis-kv")]
+    pub redis_key_prefixes: crate::auth::server_impl::store::redis_kv::KeyPrefixes,
+    pub rate_limits: RateLimits,
+    /// Header the proxy in front of the server puts the client IP in
+    /// the peer address is used if unset, headers can't be trusted without a proxy
+    pub client_ip_header: Option<http::HeaderName>,
+    /// Bearer token required by `/metrics`, disabled if unset
+    pub metrics_token: Option<String>,
+    /// Guest identities are removed after this long, never if unset
+    pub guest_identity_max_age: Option<Duration>,
+    #[cfg(feature = "cloudflare")]
+    pub cloudflare: CloudflareConfig,
+    /// ED25519 PEM
+    #[cfg(all(feature = "backend-admin", not(feature = "local-bin")))]
+    pub backend_admin_identity: String,
+    /// OpenID Connect providers, providers without a client id are disabled
+    #[cfg(feature = "oauth-ssr")]
+    pub oidc: crate::auth::server_impl::oidc::config::OidcConfig,
+    #[cfg(feature = "passkey-ssr")]
+    pub passkey: crate::auth::server_impl::passkey::PasskeyConfig,
+    #[cfg(feature = "email-ssr")]
+    pub email: EmailConfig,
+    /// Bearer token for the off-chain agent
+    #[cfg(feature = "ga4")]
+    pub grpc_auth_token: String,
+    #[cfg(feature = "ga4")]
+    pub off_chain_agent_grpc_url: reqwest::Url,
+}
+
+impl AppConfig {
+    /// Load the TOML file at `APP_CONFIG_FILE` (if set), apply env overrides and validate
+    pub fn load() -> Result<Self, ConfigError> {
+        let config_file = env::var(APP_CONFIG_FILE_ENV).ok().filter(|p| !p.is_empty());
+        let mut raw = if let Some(path) = config_file {
+            let content = fs::read_to_string(&path)
+                .map_err(|e| ConfigError(vec![format!("failed to read `{path}`: {e}")]))?;
+            toml::from_str(&content)
+                .map_err(|e| ConfigError(vec![format!("invalid config file `{path}`: {e}")]))?
+        } else {
+            RawConfig::default()
+        };
+        raw.override_from_env();
+
+        Self::validate(raw)
+    }
+
+    fn validate(raw: RawConfig) -> Result<Self, ConfigError> {
+        let mut v = Validator::default();
+
+        // `Key` requires at least 64 bytes
+        let cookie_key = v.required("COOKIE_KEY", raw.cookie_key);
+        let cookie_key = v.parse("COOKIE_KEY", cookie_key, |k| hex_key(k, 64));
+        let cookie_retired_keys = v
+            .parse("COOKIE_RETIRED_KEYS", raw.cookie_retired_keys, |k| {
+                hex_keys(k, 64)
+            })
+            .unwrap_or_default();
+
+        let identity_encryption_key =
+            v.required("IDENTITY_ENCRYPTION_KEY", raw.identity_encryption_key);
+        let identity_encryption_key =
+            v.parse("IDENTITY_ENCRYPTION_KEY", identity_encryption_key, |k| {
+                hex_key(k, MIN_KEY_LEN)
+            });
+        let identity_encryption_retired_keys = v
+            .parse(
+                "IDENTITY_ENCRYPTION_RETIRED_KEYS",
+                raw.identity_encryption_retired_keys,
+                |k| hex_keys(k, MIN_KEY_LEN),
+            )
+            .unwrap_or_default();
+
+        // `staging` is a replica other than mainnet, its url must be set
+        let network = v.parse("IC_NETWORK", raw.ic_network, |network| match network {
+            "mainnet" => Ok(Some(NetworkConfig::mainnet())),
+            "local" => Ok(Some(NetworkConfig::local())),
+            "staging" => Ok(None),
+            _ => Err(format!(
+                "unknown network `{network}`, expected `mainnet`, `staging` or `local`"
+            )),
+        });
+        let staging = matches!(network, Some(None));
+        let mut network = network.flatten().unwrap_or_default();
+        if staging {
+            network.fetch_root_key = true;
+            v.required("IC_AGENT_URL", raw.ic_agent_url.clone());
+        }
+        if let Some(agent_url) = v.parse("IC_AGENT_URL", raw.ic_agent_url, url) {
+            network.agent_url = agent_url;
+        }
+        if let Some(base) = v.parse("METADATA_API_BASE", raw.metadata_api_base, url) {
+            network.metadata_api_base = base;
+        }
+        if let Some(fetch) = v.parse("IC_FETCH_ROOT_KEY", raw.ic_fetch_root_key, |fetch| {
+            fetch
+                .parse()
+                .map_err(|_| "must be `true` or `false`".to_string())
+        }) {
+            network.fetch_root_key = fetch;
+        }
+
+        let kv_backend = v
+            .parse("KV_BACKEND", raw.kv_backend, |backend| match backend {
+                "memory" => Ok(KvBackend::Memory),
+                _ => Err(format!("unknown backend `{backend}`, expected `memory`")),
+            })
+            .unwrap_or(KvBackend::Persistent);
+
+        #[cfg(all(feature = "redis-kv", not(feature = "local-bin")))]
+        let redis_topology = {
+            use crate::auth::server_impl::store::redis_kv::RedisTopology;
+
+            let cluster_nodes = raw.redis_cluster_nodes.as_deref().map(list);
+            let sentinels = raw.redis_sentinels.as_deref().map(list);
+            if let Some(nodes) = cluster_nodes.filter(|nodes| !nodes.is_empty()) {
+                Some(RedisTopology::Cluster(nodes))
+            } else if let Some(sentinels) = sentinels.filter(|s| !s.is_empty()) {
+                v.required("REDIS_SENTINEL_MASTER", raw.redis_sentinel_master)
+                    .map(|master_name| RedisTopology::Sentinel {
+                        sentinels,
+                        master_name,
+                    })
+            } else if kv_backend == KvBackend::Persistent {
+                v.required("REDIS_URL", raw.redis_url)
+                    .map(RedisTopology::Standalone)
+            } else {
+                // unused with the in-memory store
+                Some(RedisTopology::Standalone(raw.redis_url.unwrap_or_default()))
+            }
+        };
+        #[cfg(feature = "redis-kv")]
+        let redis_key_prefixes = {
+            use crate::auth::server_impl::store::redis_kv::KeyPrefixes;
+            v.parse(
+                "REDIS_KEY_PREFIXES",
+                raw.redis_key_prefixes,
+                KeyPrefixes::parse,
+            )
+            .unwrap_or_default()
+        };
+
+        let mut rate_limits = RateLimits::default();
+        v.parse("RATE_LIMITS", raw.rate_limits, |spec| {
+            rate_limits.extend(spec)
+        });
+        let client_ip_header = v.parse("CLIENT_IP_HEADER", raw.client_ip_header, |name| {
+            http::HeaderName::try_from(name).map_err(|e| e.to_string())
+        });
+
+        let guest_identity_max_age = v.parse(
+            "GUEST_IDENTITY_MAX_AGE_DAYS",
+            raw.guest_identity_max_age_days,
+            |days| {
+                let days: u64 = days.parse().map_err(|_| "must be a number of days")?;
+                Ok(Duration::from_secs(days * 24 * 60 * 60))
+            },
+        );
+
+        #[cfg(feature = "cloudflare")]
+        let cloudflare = {
+            let token = v.required("CF_TOKEN", raw.cf_token);
+            let account_id = v.required("CF_ACCOUNT_ID", raw.cf_account_id);
+            token
+                .zip(account_id)
+                .map(|(token, account_id)| CloudflareConfig { token, account_id })
+        };
+
+        #[cfg(all(feature = "backend-admin", not(feature = "local-bin")))]
+        let backend_admin_identity = {
+            let pem = v.required("BACKEND_ADMIN_IDENTITY", raw.backend_admin_identity);
+            v.parse("BACKEND_ADMIN_IDENTITY", pem, |pem| {
+                ic_agent::identity::BasicIdentity::from_pem(pem.as_bytes())
+                    .map_err(|e| e.to_string())?;
+                Ok(pem.to_string())
+            })
+        };
+
+        // providers are configured with their own env vars, see `OidcProviderConfig`
+        #[cfg(feature = "oauth-ssr")]
+        let oidc = {
+            use crate::auth::server_impl::oidc::config::OidcConfig;
+            OidcConfig::load(raw.oidc_config_file.as_deref()).unwrap_or_else(|problems| {
+                v.0.extend(problems);
+                OidcConfig::default()
+            })
+        };
+
+        #[cfg(feature = "passkey-ssr")]
+        let passkey = {
+            let rp_id = v.required("PASSKEY_RP_ID", raw.passkey_rp_id);
+            let origin = v.required("PASSKEY_ORIGIN", raw.passkey_origin);
+            rp_id.zip(origin).map(|(rp_id, origin)| {
+                crate::auth::server_impl::passkey::PasskeyConfig {
+                    rp_id,
+                    rp_name: raw.passkey_rp_name.unwrap_or_else(|| "Yral".into()),
+                    origin,
+                }
+            })
+        };
+
+        #[cfg(feature = "email-ssr")]
+        let email = {
+            let mailer = if let Some(url) = raw.smtp_url {
+                v.required("MAIL_FROM", raw.mail_from)
+                    .map(|from| MailerConfig::Smtp { url, from })
+            } else {
+                Some(MailerConfig::File(raw.mail_output_file.map(Into::into)))
+            };
+            let link_base_url = v.required("EMAIL_LOGIN_BASE_URL", raw.email_login_base_url);
+            mailer
+                .zip(link_base_url)
+                .map(|(mailer, link_base_url)| EmailConfig {
+                    mailer,
+                    link_base_url,
+                })
+        };
+
+        #[cfg(feature = "ga4")]
+        let grpc_auth_token =
+            v.required("GRPC_AUTH_TOKEN", raw.grpc_auth_token)
+                .map(|mut token| {
+                    // removing whitespaces and new lines for proper parsing
+                    token.retain(|c| !c.is_whitespace());
+                    token
+                });
+        #[cfg(feature = "ga4")]
+        let off_chain_agent_grpc_url = v
+            .parse(
+                "OFF_CHAIN_AGENT_GRPC_URL",
+                raw.off_chain_agent_grpc_url,
+                |url| reqwest::Url::parse(url).map_err(|e| e.to_string()),
+            )
+            .unwrap_or_else(|| crate::consts::OFF_CHAIN_AGENT_GRPC_URL.clone());
+
+        v.finish()?;
+        // every required value is set once validation passed
+        Ok(Self {
+            cookie_key: cookie_key.unwrap(),
+            cookie_retired_keys,
+            identity_encryption_key: identity_encryption_key.unwrap(),
+            identity_encryption_retired_keys,
+            network,
+            kv_backend,
+            #[cfg(all(feature = "redis-kv", not(feature = "local-bin")))]
+            redis_topology: redis_topology.unwrap(),
+            #[cfg(feature = "redis-kv")]
+            redis_key_prefixes,
+            rate_limits,
+            client_ip_header,
+            metrics_token: raw.metrics_token,
+            guest_identity_max_age,
+            #[cfg(feature = "cloudflare")]
+            cloudflare: cloudflare.unwrap(),
+            #[cfg(all(feature = "backend-admin", not(feature = "local-bin")))]
+            backend_admin_identity: backend_admin_identity.unwrap(),
+            #[cfg(feature = "oauth-ssr")]
+            oidc,
+            #[cfg(feature = "passkey-ssr")]
+            passkey: passkey.unwrap(),
+            #[cfg(feature = "email-ssr")]
+            email: email.unwrap(),
+            #[cfg(feature = "ga4")]
+            grpc_auth_token: grpc_auth_token.unwrap(),
+            #[cfg(feature = "ga4")]
+            off_chain_agent_grpc_url,
+        })
+    }
+}
@@ -16,8 +16,8 @@ use yral_testcontainers::{
 
 use crate::{
     canister::USER_INDEX_ID,
-    consts::{METADATA_API_BASE, YRAL_BACKEND_CONTAINER_TAG, YRAL_METADATA_CONTAINER_TAG},
-    state::admin_canisters::AdminCanisters,
+    consts::{YRAL_BACKEND_CONTAINER_TAG, YRAL_METADATA_CONTAINER_TAG},
+    state::{admin_canisters::AdminCanisters, network::network},
 };
 
 type MaybeContainer<I> = Option<ContainerAsync<I>>;
@@ -56,7 +56,7 @@ impl TestContainers {
         // Setup User Principal -> User Canister ID
         // for the admin canister
         let metadata_client: MetadataClient<false> =
-            MetadataClient::with_base_url(METADATA_API_BASE.clone());
+            MetadataClient::with_base_url(network().metadata_api_base());
         let sk = SecretKey::from_bytes(&ADMIN_SECP_BYTES.into()).unwrap();
         let id = Secp256k1Identity::from_private_key(sk);
         let cans = AdminCanisters::new(id.clone());
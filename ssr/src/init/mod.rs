@@ -1,73 +1,101 @@
+pub mod config;
 #[cfg(feature = "local-bin")]
 pub mod containers;
 
-use std::env;
+use std::sync::Arc;
 
 use axum_extra::extract::cookie::Key;
 use leptos::LeptosOptions;
 use leptos_router::RouteListing;
 
 use crate::{
-    auth::server_impl::store::KVStoreImpl,
+    auth::server_impl::{cookies::CookieKeys, secrets::IdentityKeyring, store::KVStoreImpl},
     state::{canisters::Canisters, server::AppState},
 };
 
+use self::config::{AppConfig, KvBackend};
+
 #[cfg(feature = "cloudflare")]
-fn init_cf() -> gob_cloudflare::CloudflareAuth {
+fn init_cf(config: &AppConfig) -> gob_cloudflare::CloudflareAuth {
     use gob_cloudflare::{CloudflareAuth, Credentials};
     let creds = Credentials {
-        token: env::var("CF_TOKEN").expect("`CF_TOKEN` is required!"),
-        account_id: env::var("CF_ACCOUNT_ID").expect("`CF_ACCOUNT_ID` is required!"),
+        token: config.cloudflare.token.clone(),
+        account_id: config.cloudflare.account_id.clone(),
     };
     CloudflareAuth::new(creds)
 }
 
-fn init_cookie_key() -> Key {
-    let cookie_key_str = env::var("COOKIE_KEY").expect("`COOKIE_KEY` is required!");
-    let cookie_key_raw =
-        hex::decode(cookie_key_str).expect("Invalid `COOKIE_KEY` (must be length 128 hex)");
-    Key::from(&cookie_key_raw)
+fn init_cookie_keys(config: &AppConfig) -> CookieKeys {
+    let retired = config
+        .cookie_retired_keys
+        .iter()
+        .map(|raw| Key::from(raw.as_slice()))
+        .collect();
+
+    CookieKeys::new(Key::from(&config.cookie_key), retired)
 }
 
-#[cfg(feature = "oauth-ssr")]
-fn init_google_oauth() -> openidconnect::core::CoreClient {
-    use crate::consts::google::{GOOGLE_AUTH_URL, GOOGLE_ISSUER_URL, GOOGLE_TOKEN_URL};
-    use openidconnect::{
-        core::CoreClient, AuthUrl, ClientId, ClientSecret, IssuerUrl, RedirectUrl, TokenUrl,
+fn init_identity_keyring(config: &AppConfig) -> IdentityKeyring {
+    IdentityKeyring::new(
+        &config.identity_encryption_key,
+        &config.identity_encryption_retired_keys,
+    )
+    .expect("identity encryption keys are validated with the config")
+}
+
+#[cfg(feature = "email-ssr")]
+fn init_email_login(config: &AppConfig) -> crate::auth::server_impl::email::EmailLogin {
+    use self::config::MailerConfig;
+    use crate::auth::server_impl::email::{
+        mailer::{FileMailer, MailerImpl, SmtpMailer},
+        EmailLogin,
     };
 
-    let client_id = env::var("GOOGLE_CLIENT_ID").expect("`GOOGLE_CLIENT_ID` is required!");
-    let client_secret =
-        env::var("GOOGLE_CLIENT_SECRET").expect("`GOOGLE_CLIENT_SECRET` is required!");
-    let redirect_uri = env::var("GOOGLE_REDIRECT_URL").expect("`GOOGLE_REDIRECT_URL` is required!");
-
-    CoreClient::new(
-        ClientId::new(client_id),
-        Some(ClientSecret::new(client_secret)),
-        IssuerUrl::new(GOOGLE_ISSUER_URL.to_string()).unwrap(),
-        AuthUrl::new(GOOGLE_AUTH_URL.to_string()).unwrap(),
-        Some(TokenUrl::new(GOOGLE_TOKEN_URL.to_string()).unwrap()),
-        None,
-        // We don't validate id_tokens against Google's public keys
-        Default::default(),
-    )
-    .set_redirect_uri(RedirectUrl::new(redirect_uri).unwrap())
+    let mailer = match &config.email.mailer {
+        MailerConfig::Smtp { url, from } => {
+            MailerImpl::Smtp(SmtpMailer::new(url, from).expect("Invalid SMTP config"))
+        }
+        MailerConfig::File(path) => MailerImpl::File(FileMailer::new(path.clone())),
+    };
+
+    EmailLogin {
+        mailer,
+        link_base_url: config.email.link_base_url.clone(),
+    }
+}
+
+#[cfg(feature = "oauth-ssr")]
+async fn init_oidc_providers(
+    config: &AppConfig,
+    #[cfg(feature = "mock-oidc-ssr")] mock: &crate::auth::server_impl::oidc::mock::MockOidcIssuer,
+) -> crate::auth::server_impl::oidc::OidcProviders {
+    #[cfg_attr(not(feature = "mock-oidc-ssr"), allow(unused_mut))]
+    let mut oidc = config.oidc.clone();
+
+    #[cfg(feature = "mock-oidc-ssr")]
+    {
+        use crate::component::auth_providers::ProviderKind;
+        oidc.insert(ProviderKind::MockOidc, mock.provider_config());
+    }
+
+    oidc.build().await
 }
 
 #[cfg(feature = "ga4")]
-async fn init_grpc_offchain_channel() -> tonic::transport::Channel {
-    use crate::consts::OFF_CHAIN_AGENT_GRPC_URL;
+async fn init_grpc_offchain_channel(config: &AppConfig) -> tonic::transport::Channel {
     use tonic::transport::Channel;
 
-    let off_chain_agent_url = OFF_CHAIN_AGENT_GRPC_URL.as_ref();
-    Channel::from_static(off_chain_agent_url)
+    Channel::from_shared(config.off_chain_agent_grpc_url.to_string())
+        .expect("Invalid `OFF_CHAIN_AGENT_GRPC_URL`")
         .connect()
         .await
         .expect("Couldn't connect to off-chain agent")
 }
 
 #[cfg(feature = "backend-admin")]
-fn init_admin_canisters() -> crate::state::admin_canisters::AdminCanisters {
+fn init_admin_canisters(
+    #[cfg_attr(feature = "local-bin", allow(unused_variables))] config: &AppConfig,
+) -> crate::state::admin_canisters::AdminCanisters {
     use crate::state::admin_canisters::AdminCanisters;
 
     #[cfg(feature = "local-bin")]
@@ -85,9 +113,7 @@ fn init_admin_canisters() -> crate::state::admin_canisters::AdminCanisters {
     {
         use ic_agent::identity::BasicIdentity;
 
-        let admin_id_pem =
-            env::var("BACKEND_ADMIN_IDENTITY").expect("`BACKEND_ADMIN_IDENTITY` is required!");
-        let admin_id_pem_by = admin_id_pem.as_bytes();
+        let admin_id_pem_by = config.backend_admin_identity.as_bytes();
         let admin_id =
             BasicIdentity::from_pem(admin_id_pem_by).expect("Invalid `BACKEND_ADMIN_IDENTITY`");
         AdminCanisters::new(admin_id)
@@ -103,66 +129,107 @@ pub struct AppStateRes {
 pub struct AppStateBuilder {
     leptos_options: LeptosOptions,
     routes: Vec<RouteListing>,
+    config: AppConfig,
     #[cfg(feature = "local-bin")]
     containers: containers::TestContainers,
 }
 
 impl AppStateBuilder {
-    pub fn new(leptos_options: LeptosOptions, routes: Vec<RouteListing>) -> Self {
+    pub fn new(
+        leptos_options: LeptosOptions,
+        routes: Vec<RouteListing>,
+        config: AppConfig,
+    ) -> Self {
         Self {
             leptos_options,
             routes,
+            config,
             #[cfg(feature = "local-bin")]
             containers: containers::TestContainers::default(),
         }
     }
 
     async fn init_kv(&mut self) -> KVStoreImpl {
+        if self.config.kv_backend == KvBackend::Memory {
+            use crate::auth::server_impl::store::memory_kv::MemoryKV;
+            log::warn!("using the in-memory KV store, all data is lost on restart");
+            let kv = MemoryKV::new();
+            kv.spawn_sweeper(web_time::Duration::from_secs(60));
+            return KVStoreImpl::Memory(kv);
+        }
+
         #[cfg(feature = "redis-kv")]
         {
-            use crate::auth::server_impl::store::redis_kv::RedisKV;
-            let redis_url: String;
+            use crate::auth::server_impl::store::redis_kv::{RedisKV, RedisTopology};
+            let topology: RedisTopology;
             #[cfg(feature = "local-bin")]
             {
                 self.containers.start_redis().await;
-                redis_url = "redis://127.0.0.1:6379".to_string();
+                topology = RedisTopology::Standalone("redis://127.0.0.1:6379".to_string());
             }
             #[cfg(not(feature = "local-bin"))]
             {
-                redis_url = env::var("REDIS_URL").expect("`REDIS_URL` is required!");
+                topology = self.config.redis_topology.clone();
             }
-            KVStoreImpl::Redis(RedisKV::new(&redis_url).await.unwrap())
+            let prefixes = self.config.redis_key_prefixes.clone();
+            KVStoreImpl::Redis(RedisKV::connect(topology, prefixes).await.unwrap())
         }
 
         #[cfg(not(feature = "redis-kv"))]
         {
             use crate::auth::server_impl::store::redb_kv::ReDBKV;
-            KVStoreImpl::ReDB(ReDBKV::new().expect("Failed to initialize ReDB"))
+            let kv = ReDBKV::new().expect("Failed to initialize ReDB");
+            kv.spawn_sweeper(web_time::Duration::from_secs(60));
+            KVStoreImpl::ReDB(kv)
         }
     }
 
     pub async fn build(mut self) -> AppStateRes {
+        log::info!("using IC replica at {}", self.config.network.agent_url);
         let kv = self.init_kv().await;
+        // the issuer registered with the providers must be the one serving its routes
+        #[cfg(feature = "mock-oidc-ssr")]
+        let mock_oidc = crate::auth::server_impl::oidc::mock::MockOidcIssuer::new(
+            self.leptos_options.site_addr,
+        );
+        #[cfg(all(feature = "oauth-ssr", feature = "mock-oidc-ssr"))]
+        let oidc_providers = init_oidc_providers(&self.config, &mock_oidc).await;
+        #[cfg(all(feature = "oauth-ssr", not(feature = "mock-oidc-ssr")))]
+        let oidc_providers = init_oidc_providers(&self.config).await;
         #[cfg(feature = "local-bin")]
         {
             self.containers.start_backend().await;
             self.containers.start_metadata().await;
         }
 
+        let canisters = Canisters::default();
+        if let Some(max_age) = self.config.guest_identity_max_age {
+            use crate::auth::server_impl::guest_gc::spawn_guest_identity_gc;
+            spawn_guest_identity_gc(kv.clone(), canisters.clone(), max_age);
+        }
+
         let app_state = AppState {
             leptos_options: self.leptos_options,
-            canisters: Canisters::default(),
+            canisters,
             routes: self.routes,
             #[cfg(feature = "backend-admin")]
-            admin_canisters: init_admin_canisters(),
+            admin_canisters: init_admin_canisters(&self.config),
             #[cfg(feature = "cloudflare")]
-            cloudflare: init_cf(),
+            cloudflare: init_cf(&self.config),
             kv,
-            cookie_key: init_cookie_key(),
+            cookie_keys: init_cookie_keys(&self.config),
+            identity_keyring: init_identity_keyring(&self.config),
             #[cfg(feature = "oauth-ssr")]
-            google_oauth: init_google_oauth(),
+            oidc_providers,
+            #[cfg(feature = "mock-oidc-ssr")]
+            mock_oidc,
+            #[cfg(feature = "passkey-ssr")]
+            passkey_config: self.config.passkey.clone(),
+            #[cfg(feature = "email-ssr")]
+            email_login: init_email_login(&self.config),
             #[cfg(feature = "ga4")]
-            grpc_offchain_channel: init_grpc_offchain_channel().await,
+            grpc_offchain_channel: init_grpc_offchain_channel(&self.config).await,
+            config: Arc::new(self.config),
         };
 
         AppStateRes {
@@ -89,3 +89,36 @@ pub mod wasp {
         }
     }
 }
+
+#[cfg(feature = "hydrate")]
+pub mod webauthn {
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen(module = "/src/js/webauthn.js")]
+    extern "C" {
+        /// Returns a promise resolving to the JSON encoded registration
+        fn createPasskey(options_json: &str) -> JsValue;
+        /// Returns a promise resolving to the JSON encoded assertion
+        fn getPasskey(options_json: &str) -> JsValue;
+    }
+
+    async fn resolve(promise: JsValue) -> Result<String, String> {
+        use wasm_bindgen_futures::{js_sys::Promise, JsFuture};
+
+        JsFuture::from(Promise::from(promise))
+            .await
+            .map_err(|e| format!("{e:?}"))?
+            .as_string()
+            .ok_or_else(|| "invalid passkey response".into())
+    }
+
+    /// Create a passkey with `navigator.credentials.create`
+    pub async fn create_passkey(options_json: &str) -> Result<String, String> {
+        resolve(createPasskey(options_json)).await
+    }
+
+    /// Sign a challenge with a passkey through `navigator.credentials.get`
+    pub async fn get_passkey(options_json: &str) -> Result<String, String> {
+        resolve(getPasskey(options_json)).await
+    }
+}
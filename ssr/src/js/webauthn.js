@@ -0,0 +1,65 @@
+function fromBase64Url(value) {
+    const base64 = value.replace(/-/g, "+").replace(/_/g, "/");
+    const padded = base64 + "=".repeat((4 - (base64.length % 4)) % 4);
+    return Uint8Array.from(atob(padded), (c) => c.charCodeAt(0));
+}
+
+function toBase64Url(buffer) {
+    const bytes = new Uint8Array(buffer);
+    let binary = "";
+    for (const byte of bytes) {
+        binary += String.fromCharCode(byte);
+    }
+    return btoa(binary).replace(/\+/g, "-").replace(/\//g, "_").replace(/=+$/, "");
+}
+
+export async function createPasskey(optionsJson) {
+    const options = JSON.parse(optionsJson);
+    const credential = await navigator.credentials.create({
+        publicKey: {
+            challenge: fromBase64Url(options.challenge),
+            rp: { id: options.rpId, name: options.rpName },
+            user: {
+                id: fromBase64Url(options.user.id),
+                name: options.user.name,
+                displayName: options.user.name,
+            },
+            pubKeyCredParams: [{ type: "public-key", alg: -7 }],
+            excludeCredentials: options.excludeCredentials.map((id) => ({
+                type: "public-key",
+                id: fromBase64Url(id),
+            })),
+            authenticatorSelection: {
+                residentKey: "required",
+                userVerification: "preferred",
+            },
+            attestation: "none",
+        },
+    });
+
+    return JSON.stringify({
+        id: credential.id,
+        clientDataJson: toBase64Url(credential.response.clientDataJSON),
+        authenticatorData: toBase64Url(credential.response.getAuthenticatorData()),
+        publicKey: toBase64Url(credential.response.getPublicKey()),
+        publicKeyAlgorithm: credential.response.getPublicKeyAlgorithm(),
+    });
+}
+
+export async function getPasskey(optionsJson) {
+    const options = JSON.parse(optionsJson);
+    const credential = await navigator.credentials.get({
+        publicKey: {
+            challenge: fromBase64Url(options.challenge),
+            rpId: options.rpId,
+            userVerification: "preferred",
+        },
+    });
+
+    return JSON.stringify({
+        id: credential.id,
+        clientDataJson: toBase64Url(credential.response.clientDataJSON),
+        authenticatorData: toBase64Url(credential.response.authenticatorData),
+        signature: toBase64Url(credential.response.signature),
+    });
+}
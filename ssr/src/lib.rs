@@ -8,10 +8,18 @@ pub mod error_template;
 #[cfg(feature = "ssr")]
 pub mod fallback;
 #[cfg(feature = "ssr")]
+pub mod health;
+#[cfg(feature = "ssr")]
 pub mod init;
 pub mod js;
+#[cfg(feature = "ssr")]
+pub mod metrics;
 pub mod page;
+#[cfg(feature = "ssr")]
+pub mod rate_limit;
 pub mod state;
+#[cfg(feature = "ssr")]
+pub mod telemetry;
 pub mod utils;
 
 #[cfg(feature = "hydrate")]
@@ -21,6 +29,7 @@ pub fn hydrate() {
     // initializes logging using the `log` crate
     _ = console_log::init_with_level(log::Level::Debug);
     console_error_panic_hook::set_once();
+    crate::state::network::init_network_from_document();
 
     leptos::mount_to_body(App);
 }
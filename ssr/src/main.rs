@@ -1,71 +1,134 @@
+use std::{net::SocketAddr, time::Instant};
+
 use axum::{
     body::Body as AxumBody,
-    extract::{Path, State},
+    extract::{MatchedPath, Path, State},
     http::Request,
     response::{IntoResponse, Response},
 };
-use axum::{routing::get, Router};
+use axum::{middleware, routing::get, Router};
 use hot_or_not_web_leptos_ssr::fallback::file_and_error_handler;
-use hot_or_not_web_leptos_ssr::{app::App, init::AppStateBuilder, state::server::AppState};
-use leptos::{get_configuration, logging::log, provide_context};
+use hot_or_not_web_leptos_ssr::health::{healthz, readyz};
+use hot_or_not_web_leptos_ssr::metrics::{metrics, metrics_handler, server_fn_name};
+use hot_or_not_web_leptos_ssr::rate_limit::rate_limit;
+use hot_or_not_web_leptos_ssr::telemetry::{init_telemetry, request_span, RequestTrace};
+use hot_or_not_web_leptos_ssr::{
+    app::App,
+    init::{config::AppConfig, AppStateBuilder},
+    state::{network::set_network, server::AppState},
+};
+use leptos::{get_configuration, provide_context};
 use leptos_axum::handle_server_fns_with_context;
 use leptos_axum::{generate_route_list, LeptosRoutes};
+use tower::ServiceBuilder;
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::TraceLayer,
+};
 
 pub async fn server_fn_handler(
     State(app_state): State<AppState>,
     path: Path<String>,
     request: Request<AxumBody>,
 ) -> impl IntoResponse {
-    log!("{:?}", path);
+    tracing::debug!(path = path.as_str(), "server function");
+    // server functions may run outside the request's task
+    let trace = RequestTrace::current(request.headers());
+    let start = Instant::now();
+    // unknown functions share a label, the path is chosen by the client
+    let known = leptos::server_fn::axum::server_fn_paths()
+        .any(|(fn_path, _)| fn_path == request.uri().path());
+    let fn_name = if known {
+        server_fn_name(&path).to_string()
+    } else {
+        "unknown".to_string()
+    };
 
-    handle_server_fns_with_context(
+    let res = handle_server_fns_with_context(
         move || {
             provide_context(app_state.canisters.clone());
+            provide_context(trace.clone());
+            provide_context(app_state.config.clone());
+            provide_context(app_state.config.network.clone());
             #[cfg(feature = "backend-admin")]
             provide_context(app_state.admin_canisters.clone());
             #[cfg(feature = "cloudflare")]
             provide_context(app_state.cloudflare.clone());
             provide_context(app_state.kv.clone());
-            provide_context(app_state.cookie_key.clone());
+            provide_context(app_state.cookie_keys.clone());
+            provide_context(app_state.identity_keyring.clone());
             #[cfg(feature = "oauth-ssr")]
-            provide_context(app_state.google_oauth.clone());
+            provide_context(app_state.oidc_providers.clone());
+            #[cfg(feature = "passkey-ssr")]
+            provide_context(app_state.passkey_config.clone());
+            #[cfg(feature = "email-ssr")]
+            provide_context(app_state.email_login.clone());
             #[cfg(feature = "ga4")]
             provide_context(app_state.grpc_offchain_channel.clone());
         },
         request,
     )
     .await
+    .into_response();
+
+    metrics()
+        .server_fn_requests
+        .with_label_values(&[&fn_name, res.status().as_str()])
+        .inc();
+    metrics()
+        .server_fn_duration
+        .with_label_values(&[&fn_name])
+        .observe(start.elapsed().as_secs_f64());
+    res
 }
 
 pub async fn leptos_routes_handler(
     State(app_state): State<AppState>,
+    matched: Option<MatchedPath>,
     req: Request<AxumBody>,
 ) -> Response {
+    let start = Instant::now();
+    let trace = RequestTrace::current(req.headers());
     let handler = leptos_axum::render_route_with_context(
         app_state.leptos_options.clone(),
         app_state.routes.clone(),
         move || {
             provide_context(app_state.canisters.clone());
+            provide_context(trace.clone());
+            provide_context(app_state.config.clone());
+            provide_context(app_state.config.network.clone());
             #[cfg(feature = "backend-admin")]
             provide_context(app_state.admin_canisters.clone());
             #[cfg(feature = "cloudflare")]
             provide_context(app_state.cloudflare.clone());
             provide_context(app_state.kv.clone());
-            provide_context(app_state.cookie_key.clone());
+            provide_context(app_state.cookie_keys.clone());
+            provide_context(app_state.identity_keyring.clone());
             #[cfg(feature = "oauth-ssr")]
-            provide_context(app_state.google_oauth.clone());
+            provide_context(app_state.oidc_providers.clone());
+            #[cfg(feature = "passkey-ssr")]
+            provide_context(app_state.passkey_config.clone());
+            #[cfg(feature = "email-ssr")]
+            provide_context(app_state.email_login.clone());
             #[cfg(feature = "ga4")]
             provide_context(app_state.grpc_offchain_channel.clone());
         },
         App,
     );
-    handler(req).await.into_response()
+    let res = handler(req).await.into_response();
+
+    let route = matched.as_ref().map_or("unknown", MatchedPath::as_str);
+    metrics()
+        .ssr_render_duration
+        .with_label_values(&[route])
+        .observe(start.elapsed().as_secs_f64());
+    res
 }
 
 #[tokio::main]
 async fn main() {
-    simple_logger::init_with_level(log::Level::Debug).expect("couldn't initialize logging");
     dotenv::dotenv().ok();
+    let _telemetry = init_telemetry();
 
     // Setting get_configuration(None) means we'll be using cargo-leptos's env values
     // For deployment these variables are:
@@ -75,9 +138,15 @@ async fn main() {
     let conf = get_configuration(None).await.unwrap();
     let leptos_options = conf.leptos_options;
     let addr = leptos_options.site_addr;
-    let routes = generate_route_list(App);
 
-    let res = AppStateBuilder::new(leptos_options, routes.clone())
+    let config = AppConfig::load().unwrap_or_else(|e| {
+        log::error!("{e}");
+        std::process::exit(1);
+    });
+    // rendering the routes builds agents, the network must be selected first
+    set_network(config.network.clone());
+    let routes = generate_route_list(App);
+    let res = AppStateBuilder::new(leptos_options, routes.clone(), config)
         .build()
         .await;
     let terminate = {
@@ -119,16 +188,41 @@ async fn main() {
             "/api/*fn_name",
             get(server_fn_handler).post(server_fn_handler),
         )
-        .leptos_routes_with_handler(routes, get(leptos_routes_handler))
+        .route_layer(middleware::from_fn_with_state(
+            res.app_state.clone(),
+            rate_limit,
+        ))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics_handler))
+        .leptos_routes_with_handler(routes, get(leptos_routes_handler));
+
+    #[cfg(feature = "mock-oidc-ssr")]
+    let app = {
+        use hot_or_not_web_leptos_ssr::consts::oidc::MOCK_OIDC_PATH;
+        app.nest(MOCK_OIDC_PATH, res.app_state.mock_oidc.clone().router())
+    };
+
+    let app = app
         .fallback(file_and_error_handler)
-        .with_state(res.app_state);
+        .with_state(res.app_state)
+        .layer(
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+                .layer(TraceLayer::new_for_http().make_span_with(request_span::<AxumBody>))
+                .layer(PropagateRequestIdLayer::x_request_id()),
+        );
 
     // run our app with hyper
     // `axum::Server` is a re-export of `hyper::Server`
     log::info!("listening on http://{}", &addr);
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app.into_make_service())
-        .with_graceful_shutdown(terminate)
-        .await
-        .unwrap();
+    // the peer address is used for rate limiting
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(terminate)
+    .await
+    .unwrap();
 }
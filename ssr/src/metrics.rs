@@ -0,0 +1,183 @@
+//! Prometheus metrics, served at `/metrics`
+//!
+//! `/metrics` requires `METRICS_TOKEN` as a bearer token and is disabled if it's unset
+
+use std::{future::Future, time::Instant};
+
+use axum::{extract::State, response::IntoResponse};
+use http::{header, HeaderMap, StatusCode};
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram_vec_with_registry, register_int_counter_vec_with_registry, Encoder,
+    HistogramVec, IntCounterVec, Registry, TextEncoder,
+};
+use sha2::{Digest, Sha256};
+
+use crate::state::server::AppState;
+
+pub struct Metrics {
+    registry: Registry,
+    /// labels: `function`, `status`
+    pub server_fn_requests: IntCounterVec,
+    /// labels: `function`
+    pub server_fn_duration: HistogramVec,
+    /// Time until the response starts streaming, labels: `route`
+    pub ssr_render_duration: HistogramVec,
+    /// labels: `op`, `result`
+    pub kv_op_duration: HistogramVec,
+    /// Requests sent to the replica, labels: `kind`, `result` (`ok`, `reject` or `error`)
+    pub ic_agent_calls: IntCounterVec,
+    /// Requests let through because the KV store failed, labels: `function`
+    pub rate_limit_failures: IntCounterVec,
+}
+
+static METRICS: Lazy<Metrics> = Lazy::new(|| {
+    let registry = Registry::new_custom(Some("yral".into()), None).unwrap();
+    Metrics {
+        server_fn_requests: register_int_counter_vec_with_registry!(
+            "server_fn_requests_total",
+            "Server function requests",
+            &["function", "status"],
+            registry
+        )
+        .unwrap(),
+        server_fn_duration: register_histogram_vec_with_registry!(
+            "server_fn_duration_seconds",
+            "Server function latency",
+            &["function"],
+            registry
+        )
+        .unwrap(),
+        ssr_render_duration: register_histogram_vec_with_registry!(
+            "ssr_render_duration_seconds",
+            "Time until a server rendered page starts streaming",
+            &["route"],
+            registry
+        )
+        .unwrap(),
+        kv_op_duration: register_histogram_vec_with_registry!(
+            "kv_operation_duration_seconds",
+            "KV store operation latency",
+            &["op", "result"],
+            prometheus::exponential_buckets(0.0005, 2.0, 14).unwrap(),
+            registry
+        )
+        .unwrap(),
+        ic_agent_calls: register_int_counter_vec_with_registry!(
+            "ic_agent_calls_total",
+            "Requests sent to the IC replica",
+            &["kind", "result"],
+            registry
+        )
+        .unwrap(),
+        rate_limit_failures: register_int_counter_vec_with_registry!(
+            "rate_limit_failures_total",
+            "Requests let through without rate limiting because the KV store failed",
+            &["function"],
+            registry
+        )
+        .unwrap(),
+        registry,
+    }
+});
+
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+fn result_label<T, E>(res: &Result<T, E>) -> &'static str {
+    if res.is_ok() {
+        "ok"
+    } else {
+        "error"
+    }
+}
+
+/// Whether `suffix` can be the hash leptos appends to server function names, a `u64` in decimal
+pub fn is_server_fn_hash(suffix: &str) -> bool {
+    suffix.bytes().all(|b| b.is_ascii_digit())
+        && (suffix == "0" || !suffix.starts_with('0'))
+        && suffix.parse::<u64>().is_ok()
+}
+
+/// Server function name from `/api/{fn_path}`, without the hash leptos appends
+/// a name ending in digits can't always be told apart from its hash, the longest possible hash is stripped
+pub fn server_fn_name(fn_path: &str) -> &str {
+    let digits = fn_path.len() - fn_path.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    (1..=digits.min(20))
+        .rev()
+        .map(|len| fn_path.split_at(fn_path.len() - len))
+        .find(|(_, hash)| is_server_fn_hash(hash))
+        .map_or(fn_path, |(name, _)| name)
+}
+
+/// Record the latency of a KV store operation
+pub async fn time_kv_op<T, E>(op: &str, fut: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+    let start = Instant::now();
+    let res = fut.await;
+    metrics()
+        .kv_op_duration
+        .with_label_values(&[op, result_label(&res)])
+        .observe(start.elapsed().as_secs_f64());
+    res
+}
+
+/// Count a request sent to the replica, `is_reject` tells replies the replica rejected
+pub async fn count_ic_call<T, E>(
+    kind: &str,
+    fut: impl Future<Output = Result<T, E>>,
+    is_reject: impl FnOnce(&T) -> bool,
+) -> Result<T, E> {
+    let res = fut.await;
+    let result = match &res {
+        Ok(reply) if is_reject(reply) => "reject",
+        res => result_label(res),
+    };
+    metrics()
+        .ic_agent_calls
+        .with_label_values(&[kind, result])
+        .inc();
+    res
+}
+
+/// The request carries `token` as a bearer token
+/// digests are compared so the comparison doesn't leak the token's prefix
+fn is_authorized(headers: &HeaderMap, token: &str) -> bool {
+    let Some(bearer) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    Sha256::digest(bearer.as_bytes()) == Sha256::digest(token.as_bytes())
+}
+
+/// `/metrics` in the Prometheus text format
+pub async fn metrics_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let Some(token) = &state.config.metrics_token else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !is_authorized(&headers, token) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let encoder = TextEncoder::new();
+    let mut body = vec![];
+    if let Err(e) = encoder.encode(&METRICS.registry.gather(), &mut body) {
+        log::warn!("failed to encode metrics {e}");
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "failed to encode metrics",
+        )
+            .into_response();
+    }
+    (
+        [(header::CONTENT_TYPE, encoder.format_type().to_string())],
+        body,
+    )
+        .into_response()
+}
@@ -0,0 +1,141 @@
+use k256::elliptic_curve::JwkEcKey;
+use leptos::*;
+use leptos_router::use_navigate;
+use leptos_use::{
+    storage::use_local_storage,
+    utils::{FromToStringCodec, JsonCodec},
+};
+
+use crate::{
+    auth::{recover_identity, recovery_phrase},
+    component::{back_btn::BackButton, title::Title},
+    consts::{ACCOUNT_CONNECTED_STORE, IDENTITY_JWK_STORE},
+    state::auth::auth_state,
+};
+
+#[component]
+fn RecoveryPhraseBackup() -> impl IntoView {
+    let reveal_action = create_action(|()| async move { recovery_phrase().await });
+    let phrase = reveal_action.value();
+
+    view! {
+        <div class="flex flex-col gap-4">
+            <span class="text-xl font-bold">Back up your account</span>
+            <span class="text-sm text-white/50">
+                Anyone with these words can access your account. Write them down and keep them somewhere safe.
+            </span>
+            {move || match phrase() {
+                Some(Ok(phrase)) => {
+                    view! {
+                        <div class="grid grid-cols-3 gap-2 rounded-lg bg-neutral-900 p-4 font-mono text-sm">
+                            {phrase
+                                .split(' ')
+                                .enumerate()
+                                .map(|(idx, word)| {
+                                    view! {
+                                        <span>
+                                            <span class="text-white/50">{format!("{}. ", idx + 1)}</span>
+                                            {word.to_string()}
+                                        </span>
+                                    }
+                                })
+                                .collect_view()}
+                        </div>
+                    }
+                        .into_view()
+                }
+                res => {
+                    view! {
+                        <button
+                            class="font-bold rounded-full bg-primary-600 py-2 md:py-3 w-full text-center text-lg text-white disabled:opacity-50"
+                            disabled=reveal_action.pending()
+                            on:click=move |_| reveal_action.dispatch(())
+                        >
+                            Show recovery phrase
+                        </button>
+                        {res
+                            .and_then(|res| res.err())
+                            .map(|e| view! { <span class="text-sm text-red-500">{e.to_string()}</span> })}
+                    }
+                        .into_view()
+                }
+            }}
+
+        </div>
+    }
+}
+
+#[component]
+fn RecoveryPhraseRestore() -> impl IntoView {
+    let auth = auth_state();
+    let phrase = create_rw_signal(String::new());
+    let (_, write_account_connected, _) =
+        use_local_storage::<bool, FromToStringCodec>(ACCOUNT_CONNECTED_STORE);
+    let (_, _, clear_jwk_identity) =
+        use_local_storage::<Option<JwkEcKey>, JsonCodec>(IDENTITY_JWK_STORE);
+
+    let restore_action = create_action(move |phrase: &String| {
+        let phrase = phrase.clone();
+        let clear_jwk_identity = clear_jwk_identity.clone();
+        async move {
+            let id = recover_identity(phrase).await?;
+            // the stored JWK belongs to the identity being replaced
+            clear_jwk_identity();
+            write_account_connected(true);
+            auth.set(Some(id));
+            let navigate = use_navigate();
+            navigate("/menu", Default::default());
+            Ok::<_, ServerFnError>(())
+        }
+    });
+    let error = move || {
+        restore_action
+            .value()
+            .get()
+            .and_then(|res| res.err())
+            .map(|e| e.to_string())
+    };
+
+    view! {
+        <div class="flex flex-col gap-4">
+            <span class="text-xl font-bold">Restore an account</span>
+            <span class="text-sm text-white/50">
+                Enter the recovery phrase of the account. You will be logged out of the current account.
+            </span>
+            <textarea
+                class="w-full rounded-lg bg-neutral-800 p-4 font-mono text-sm text-white"
+                rows="4"
+                placeholder="word1 word2 word3 ..."
+                prop:value=phrase
+                on:input=move |ev| phrase.set(event_target_value(&ev))
+            ></textarea>
+            {move || error().map(|e| view! { <span class="text-sm text-red-500">{e}</span> })}
+            <button
+                class="font-bold rounded-full bg-neutral-600 py-2 md:py-3 w-full text-center text-lg text-white disabled:opacity-50"
+                disabled=move || restore_action.pending().get() || phrase.with(|p| p.trim().is_empty())
+                on:click=move |_| restore_action.dispatch(phrase.get_untracked())
+            >
+                Restore account
+            </button>
+        </div>
+    }
+}
+
+#[component]
+pub fn AccountRecovery() -> impl IntoView {
+    view! {
+        <div class="flex flex-col items-center min-w-dvw min-h-dvh bg-black pt-2 pb-12 gap-6 text-white">
+            <Title justify_center=false>
+                <div class="flex flex-row justify-between">
+                    <BackButton fallback="/menu".to_string()/>
+                    <span class="text-lg font-bold">Account Recovery</span>
+                    <div></div>
+                </div>
+            </Title>
+            <div class="flex flex-col px-8 gap-12 w-full sm:w-7/12">
+                <RecoveryPhraseBackup/>
+                <RecoveryPhraseRestore/>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,123 @@
+use leptos::*;
+use leptos_icons::*;
+use leptos_router::use_navigate;
+
+use crate::{
+    auth::{active_sessions, revoke_other_sessions, revoke_session, SessionInfo},
+    component::{back_btn::BackButton, title::Title},
+    utils::{current_epoch, timestamp::get_day_month},
+};
+
+/// Approximate time since `epoch_ms`, e.g `3 hours ago`
+fn time_ago(epoch_ms: u64) -> String {
+    let elapsed_secs = (current_epoch().as_millis() as u64).saturating_sub(epoch_ms) / 1000;
+    let (value, unit) = match elapsed_secs {
+        0..=59 => return "just now".into(),
+        60..=3599 => (elapsed_secs / 60, "minute"),
+        3600..=86399 => (elapsed_secs / 3600, "hour"),
+        _ => (elapsed_secs / 86400, "day"),
+    };
+    let plural = if value == 1 { "" } else { "s" };
+    format!("{value} {unit}{plural} ago")
+}
+
+#[component]
+fn DeviceItem(session: SessionInfo, changed: Trigger) -> impl IntoView {
+    let session_id = session.id.clone();
+    let revoke_action = create_action(move |()| {
+        let session_id = session_id.clone();
+        async move {
+            if let Err(e) = revoke_session(session_id).await {
+                log::warn!("failed to revoke session {e}");
+            }
+            changed.notify();
+        }
+    });
+    let pending = revoke_action.pending();
+
+    view! {
+        <div class="grid grid-cols-3 items-center w-full">
+            <div class="flex flex-row gap-4 items-center col-span-2">
+                <Icon class="text-2xl shrink-0" icon=icondata::AiMobileOutlined/>
+                <div class="flex flex-col">
+                    <span class="text-wrap">{session.device}</span>
+                    <span class="text-sm text-white/50">
+                        {format!(
+                            "Signed in {} · Last seen {}",
+                            get_day_month(session.created_at_ms / 1000),
+                            time_ago(session.last_used_ms),
+                        )}
+                    </span>
+                </div>
+            </div>
+            <Show
+                when=move || !session.current
+                fallback=|| {
+                    view! {
+                        <span class="justify-self-end text-sm text-primary-600">This device</span>
+                    }
+                }
+            >
+                <button
+                    class="justify-self-end rounded-full bg-neutral-600 px-4 py-1 text-sm disabled:opacity-50"
+                    disabled=pending
+                    on:click=move |_| revoke_action.dispatch(())
+                >
+                    Sign out
+                </button>
+            </Show>
+        </div>
+    }
+}
+
+#[component]
+pub fn Devices() -> impl IntoView {
+    let changed = create_trigger();
+    let sessions = create_resource(
+        move || changed.track(),
+        |_| async move { active_sessions().await },
+    );
+    let sign_out_everywhere = create_action(move |()| async move {
+        if let Err(e) = revoke_other_sessions().await {
+            log::warn!("failed to revoke sessions {e}");
+            return;
+        }
+        // the current session is revoked by logging out
+        let navigate = use_navigate();
+        navigate("/logout", Default::default());
+    });
+
+    view! {
+        <div class="flex flex-col items-center min-w-dvw min-h-dvh bg-black pt-2 pb-12 gap-6 text-white">
+            <Title justify_center=false>
+                <div class="flex flex-row justify-between">
+                    <BackButton fallback="/menu".to_string()/>
+                    <span class="text-lg font-bold">Active Devices</span>
+                    <div></div>
+                </div>
+            </Title>
+            <div class="flex flex-col px-8 gap-8 w-full sm:w-7/12 text-lg">
+                <Suspense>
+                    {move || {
+                        sessions()
+                            .and_then(|res| res.ok())
+                            .map(|sessions| {
+                                sessions
+                                    .into_iter()
+                                    .map(|session| view! { <DeviceItem session changed/> })
+                                    .collect_view()
+                            })
+                    }}
+
+                </Suspense>
+                <button
+                    class="font-bold rounded-full bg-primary-600 py-2 md:py-3 w-full text-center text-lg text-white disabled:opacity-50"
+                    disabled=sign_out_everywhere.pending()
+                    on:click=move |_| sign_out_everywhere.dispatch(())
+                >
+                    Sign out everywhere
+                </button>
+            </div>
+        </div>
+    }
+}
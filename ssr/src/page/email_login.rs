@@ -0,0 +1,59 @@
+use leptos::*;
+use leptos_router::*;
+use leptos_use::{storage::use_local_storage, utils::FromToStringCodec};
+
+use crate::{
+    auth::DelegatedIdentityWire, component::loading::Loading, consts::ACCOUNT_CONNECTED_STORE,
+    state::auth::auth_state, try_or_redirect,
+};
+
+#[server]
+async fn perform_email_auth(token: String) -> Result<DelegatedIdentityWire, ServerFnError> {
+    use crate::auth::server_impl::email::perform_email_auth_impl;
+    perform_email_auth_impl(token).await
+}
+
+#[derive(Params, Debug, PartialEq, Clone)]
+struct EmailLoginQuery {
+    token: String,
+}
+
+/// Landing page of the emailed login link
+/// the link is only consumed once the user confirms, so link previews and mail scanners
+/// opening it don't log anyone in
+#[component]
+pub fn EmailLoginVerify() -> impl IntoView {
+    let query = use_query::<EmailLoginQuery>();
+    let auth = auth_state();
+
+    let login_action = create_action(move |()| async move {
+        let query = try_or_redirect!(query.get_untracked());
+        let id = try_or_redirect!(perform_email_auth(query.token).await);
+        auth.set(Some(id));
+
+        let (_, write_account_connected, _) =
+            use_local_storage::<bool, FromToStringCodec>(ACCOUNT_CONNECTED_STORE);
+        write_account_connected(true);
+
+        let navigate = use_navigate();
+        navigate("/menu", Default::default());
+    });
+    let pending = login_action.pending();
+
+    view! {
+        <Show
+            when=move || !pending()
+            fallback=|| view! { <Loading text="Logging in...".to_string()>""</Loading> }
+        >
+            <div class="flex flex-col items-center justify-center min-w-dvw min-h-dvh bg-black px-8 gap-6 text-white">
+                <span class="text-lg text-center">Log in to Yral with the emailed link?</span>
+                <button
+                    class="font-bold rounded-full bg-primary-600 py-2 md:py-3 w-full sm:w-7/12 text-center text-lg text-white"
+                    on:click=move |_| login_action.dispatch(())
+                >
+                    Log in
+                </button>
+            </div>
+        </Show>
+    }
+}
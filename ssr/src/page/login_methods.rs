@@ -0,0 +1,159 @@
+use leptos::*;
+use leptos_icons::*;
+use leptos_use::{use_event_listener, use_window};
+
+use crate::component::{
+    auth_providers::{
+        oidc::{enabled_oidc_providers, provider_icon, provider_label},
+        ProviderKind,
+    },
+    back_btn::BackButton,
+    title::Title,
+};
+
+use super::oidc_redirect::OidcAuthMessage;
+
+#[server]
+async fn linked_login_methods() -> Result<Vec<ProviderKind>, ServerFnError> {
+    use crate::auth::server_impl::oidc::linked_login_methods_impl;
+    linked_login_methods_impl().await
+}
+
+#[server]
+async fn unlink_login_method(provider: ProviderKind) -> Result<(), ServerFnError> {
+    use crate::auth::server_impl::oidc::unlink_login_method_impl;
+    unlink_login_method_impl(provider).await
+}
+
+#[derive(Clone, Copy)]
+struct LoginMethodsCtx {
+    /// Set after linking or unlinking, refetches linked methods
+    changed: Trigger,
+    error: RwSignal<Option<String>>,
+}
+
+#[component]
+fn LoginMethod(prov: ProviderKind, linked: bool, can_unlink: bool) -> impl IntoView {
+    let ctx: LoginMethodsCtx = expect_context();
+    let processing = create_rw_signal(false);
+
+    let unlink_action = create_action(move |()| async move {
+        if let Err(e) = unlink_login_method(prov).await {
+            ctx.error.set(Some(e.to_string()));
+        }
+        processing.set(false);
+        ctx.changed.notify();
+    });
+
+    let link = move || {
+        let window = window();
+        let origin = window.origin();
+        let redirect_uri = format!("{origin}/auth/{}/perform_redirect?link=true", prov.as_str());
+        let Some(target) = window
+            .open_with_url(&redirect_uri)
+            .transpose()
+            .and_then(|w| w.ok())
+        else {
+            processing.set(false);
+            return;
+        };
+
+        _ = use_event_listener(use_window(), ev::message, move |msg| {
+            if msg.origin() != origin {
+                return;
+            }
+            let Some(data) = msg.data().as_string() else {
+                return;
+            };
+            match serde_json::from_str::<OidcAuthMessage>(&data)
+                .map_err(|e| e.to_string())
+                .and_then(|r| r)
+            {
+                Ok(_) => ctx.error.set(None),
+                Err(e) => ctx.error.set(Some(e)),
+            }
+            _ = target.close();
+            processing.set(false);
+            ctx.changed.notify();
+        });
+    };
+
+    let on_click = move |_| {
+        processing.set(true);
+        if linked {
+            unlink_action.dispatch(());
+        } else {
+            link();
+        }
+    };
+
+    view! {
+        <div class="grid grid-cols-3 items-center w-full">
+            <div class="flex flex-row gap-4 items-center col-span-2">
+                <Icon class="text-2xl" icon=provider_icon(prov)/>
+                <span class="text-wrap">{provider_label(prov)}</span>
+            </div>
+            <button
+                class="justify-self-end rounded-full px-4 py-1 text-sm disabled:opacity-50"
+                class=("bg-primary-600", !linked)
+                class=("bg-neutral-600", linked)
+                disabled=move || processing() || (linked && !can_unlink)
+                on:click=on_click
+            >
+                {if linked { "Unlink" } else { "Link" }}
+            </button>
+        </div>
+    }
+}
+
+#[component]
+pub fn LoginMethods() -> impl IntoView {
+    let ctx = LoginMethodsCtx {
+        changed: create_trigger(),
+        error: create_rw_signal(None),
+    };
+    provide_context(ctx);
+
+    let enabled = create_resource(|| (), |_| enabled_oidc_providers());
+    let linked = create_resource(
+        move || ctx.changed.track(),
+        |_| async move { linked_login_methods().await },
+    );
+
+    view! {
+        <div class="flex flex-col items-center min-w-dvw min-h-dvh bg-black pt-2 pb-12 gap-6 text-white">
+            <Title justify_center=false>
+                <div class="flex flex-row justify-between">
+                    <BackButton fallback="/menu".to_string()/>
+                    <span class="text-lg font-bold">Login Methods</span>
+                    <div></div>
+                </div>
+            </Title>
+            <div class="flex flex-col px-8 gap-8 w-full sm:w-7/12 text-lg">
+                <Suspense>
+                    {move || {
+                        let enabled = enabled()?.ok()?;
+                        let linked = linked()?.ok()?;
+                        let can_unlink = linked.len() > 1;
+                        Some(
+                            enabled
+                                .into_iter()
+                                .map(|prov| {
+                                    let is_linked = linked.contains(&prov);
+                                    view! { <LoginMethod prov linked=is_linked can_unlink/> }
+                                })
+                                .collect_view(),
+                        )
+                    }}
+
+                </Suspense>
+                <Show when=move || ctx.error.with(|e| e.is_some())>
+                    <span class="text-sm text-red-500">{move || ctx.error.get()}</span>
+                </Show>
+                <span class="text-sm text-white/50">
+                    {r#"You can log in with any linked method. The last one can't be unlinked."#}
+                </span>
+            </div>
+        </div>
+    }
+}
@@ -268,7 +268,27 @@ pub fn Menu() -> impl IntoView {
                 />
                 <MenuItem href="/terms-of-service" text="Terms of Service" icon=icondata::TbBook2/>
                 <MenuItem href="/privacy-policy" text="Privacy Policy" icon=icondata::TbLock/>
+                <MenuItem
+                    href="/account-recovery"
+                    text="Account Recovery"
+                    icon=icondata::AiSafetyOutlined
+                />
                 <Show when=is_connected>
+                    {
+                        #[cfg(any(feature = "oauth-ssr", feature = "oauth-hydrate"))]
+                        view! {
+                            <MenuItem
+                                href="/login-methods"
+                                text="Login Methods"
+                                icon=icondata::AiLinkOutlined
+                            />
+                        }
+                    }
+                    <MenuItem
+                        href="/devices"
+                        text="Active Devices"
+                        icon=icondata::AiMobileOutlined
+                    />
                     <MenuItem href="/logout" text="Logout" icon=icondata::FiLogOut/>
                 </Show>
             // <MenuItem href="/install-app" text="Install App" icon=icondata::TbDownload/>
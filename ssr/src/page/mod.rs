@@ -1,12 +1,18 @@
 pub mod about_us;
+pub mod account_recovery;
 pub mod account_transfer;
 pub mod airdrop;
+pub mod devices;
+#[cfg(any(feature = "email-ssr", feature = "email-hydrate"))]
+pub mod email_login;
 pub mod err;
 pub mod faq;
-#[cfg(any(feature = "oauth-ssr", feature = "oauth-hydrate"))]
-pub mod google_redirect;
 pub mod leaderboard;
+#[cfg(any(feature = "oauth-ssr", feature = "oauth-hydrate"))]
+pub mod login_methods;
 pub mod menu;
+#[cfg(any(feature = "oauth-ssr", feature = "oauth-hydrate"))]
+pub mod oidc_redirect;
 pub mod post_view;
 pub mod privacy;
 pub mod profile;
@@ -0,0 +1,147 @@
+use leptos::*;
+use leptos_router::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::LoginOutcome,
+    component::{auth_providers::ProviderKind, loading::Loading},
+    utils::route::go_to_root,
+};
+
+pub type OidcAuthMessage = Result<LoginOutcome, String>;
+
+#[server]
+async fn oidc_auth_redirector(provider: ProviderKind, link: bool) -> Result<(), ServerFnError> {
+    use crate::auth::server_impl::oidc::oidc_auth_url_impl;
+    let url = oidc_auth_url_impl(provider, link).await?;
+    leptos_axum::redirect(&url);
+    Ok(())
+}
+
+#[server]
+async fn perform_oidc_auth(
+    provider: ProviderKind,
+    oauth: OAuthQuery,
+) -> Result<LoginOutcome, ServerFnError> {
+    use crate::auth::server_impl::oidc::perform_oidc_auth_impl;
+    perform_oidc_auth_impl(provider, oauth.state, oauth.code).await
+}
+
+#[derive(Params, Debug, PartialEq, Clone, Serialize, Deserialize)]
+struct OAuthQuery {
+    pub code: String,
+    pub state: String,
+}
+
+#[derive(Params, Debug, PartialEq, Clone)]
+struct ProviderParams {
+    provider: String,
+}
+
+/// Provider from the `:provider` route param
+/// routes without the param are the legacy Google routes
+fn use_provider() -> Memo<Option<ProviderKind>> {
+    let params = use_params::<ProviderParams>();
+    create_memo(move |_| match params() {
+        Ok(ProviderParams { provider }) => ProviderKind::from_oidc_str(&provider),
+        Err(_) => Some(ProviderKind::Google),
+    })
+}
+
+#[component]
+pub fn IdentitySender(identity_res: OidcAuthMessage) -> impl IntoView {
+    create_effect(move |_| {
+        let _id = &identity_res;
+        #[cfg(feature = "hydrate")]
+        {
+            use web_sys::Window;
+
+            let win = window();
+            let origin = win.origin();
+            let opener = win.opener().unwrap();
+            if opener.is_null() {
+                go_to_root();
+            }
+            let opener = Window::from(opener);
+            let msg = serde_json::to_string(&_id).unwrap();
+            _ = opener.post_message(&msg.into(), &origin);
+        }
+    });
+
+    view! {
+        <div class="h-dvh w-dvw bg-black flex flex-col justify-center items-center gap-10">
+            <img class="h-56 w-56 object-contain animate-pulse" src="/img/logo.webp"/>
+            <span class="text-2xl text-white/60">Good things come to those who wait...</span>
+        </div>
+    }
+}
+
+async fn handle_oauth_query(
+    provider: Option<ProviderKind>,
+    query: Result<OAuthQuery, ParamsError>,
+) -> OidcAuthMessage {
+    let (Some(provider), Ok(oauth_query)) = (provider, query) else {
+        go_to_root();
+        return Err("Invalid query".to_string());
+    };
+    let outcome = perform_oidc_auth(provider, oauth_query)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(outcome)
+}
+
+#[component]
+pub fn OidcRedirectHandler() -> impl IntoView {
+    let provider = use_provider();
+    let query = use_query::<OAuthQuery>();
+    let identity_resource = create_blocking_resource(
+        move || (provider(), query()),
+        |(provider, query_res)| async move { handle_oauth_query(provider, query_res).await },
+    );
+
+    view! {
+        <Loading text="Logging out...".to_string()>
+            <Suspense>
+                {move || {
+                    identity_resource().map(|identity_res| view! { <IdentitySender identity_res/> })
+                }}
+
+            </Suspense>
+        </Loading>
+    }
+}
+
+#[component]
+pub fn OidcRedirector() -> impl IntoView {
+    let provider = use_provider();
+    // `?link=true` links the provider to the current account instead of logging in
+    let query = use_query_map();
+    let link = move || query.with(|q| q.get("link").is_some_and(|l| l == "true"));
+    let oidc_redirect = create_blocking_resource(
+        move || (provider(), link()),
+        |(provider, link)| async move {
+            let provider = provider.ok_or_else(|| ServerFnError::new("Unknown login provider"))?;
+            oidc_auth_redirector(provider, link).await
+        },
+    );
+    let do_close = create_rw_signal(false);
+    create_effect(move |_| {
+        if !do_close() {
+            return;
+        }
+        let window = window();
+        _ = window.close();
+    });
+
+    view! {
+        <Suspense>
+            {move || {
+                if let Some(Err(_)) = oidc_redirect() {
+                    do_close.set(true);
+                }
+                None::<()>
+            }}
+
+        </Suspense>
+    }
+}
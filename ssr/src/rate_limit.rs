@@ -0,0 +1,257 @@
+//! Rate limiting of server functions
+//!
+//! Every limited server function has a token bucket per client IP and per principal,
+//! stored in the KV store so limits are shared by every instance.
+//! `rate-limit-{function}-ip-{ip}` and `rate-limit-{function}-principal-{principal}` hold the buckets
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode};
+use serde::{Deserialize, Serialize};
+use web_time::Duration;
+
+use crate::{
+    auth::server_impl::store::{KVError, KVStore, KVStoreImpl, KvKey, RecordKind},
+    metrics::{is_server_fn_hash, metrics},
+    state::server::AppState,
+    utils::current_epoch,
+};
+
+/// Attempts to update a bucket before letting the request through
+const CAS_RETRIES: usize = 5;
+
+/// Limits applied unless overridden by `RATE_LIMITS`
+/// these server functions create identities or start login flows
+const DEFAULT_LIMITS: &str = "extract_or_generate_identity=60/60,\
+    renew_identity=60/60,\
+    logout_identity=10/60,\
+    recover_identity=5/60,\
+    oidc_auth_redirector=20/60,\
+    perform_oidc_auth=20/60,\
+    perform_local_storage_auth=20/60,\
+    passkey_login_options=20/60,\
+    perform_passkey_auth=20/60,\
+    send_email_login_link=5/600,\
+    perform_email_auth=20/60";
+
+/// `capacity` requests per `period`, refilled continuously
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    pub capacity: u32,
+    pub period: Duration,
+}
+
+impl RateLimit {
+    fn refill_per_ms(&self) -> f64 {
+        self.capacity as f64 / self.period.as_millis().max(1) as f64
+    }
+}
+
+/// Limits per server function, functions without a limit aren't limited
+#[derive(Clone, Debug)]
+pub struct RateLimits(HashMap<String, RateLimit>);
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self::parse(DEFAULT_LIMITS).unwrap()
+    }
+}
+
+impl RateLimits {
+    /// Parse comma separated `function=capacity/seconds` pairs, e.g `logout_identity=10/60`
+    fn parse(spec: &str) -> Result<Self, String> {
+        let mut limits = Self(HashMap::new());
+        limits.extend(spec)?;
+        Ok(limits)
+    }
+
+    /// Add or override limits, `function=off` removes the limit of a function
+    pub fn extend(&mut self, spec: &str) -> Result<(), String> {
+        for pair in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+        {
+            let (name, limit) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected `function=capacity/seconds`, got `{pair}`"))?;
+            if limit == "off" {
+                self.0.remove(name);
+                continue;
+            }
+            let (capacity, secs) = limit
+                .split_once('/')
+                .ok_or_else(|| format!("expected `capacity/seconds`, got `{limit}`"))?;
+            let limit = RateLimit {
+                capacity: capacity
+                    .parse()
+                    .map_err(|_| format!("invalid capacity `{capacity}`"))?,
+                period: Duration::from_secs(
+                    secs.parse()
+                        .map_err(|_| format!("invalid period `{secs}`"))?,
+                ),
+            };
+            self.0.insert(name.to_string(), limit);
+        }
+        Ok(())
+    }
+
+    /// Limit of the server function at `/api/{fn_path}`
+    /// the path is a function name followed by exactly the hash leptos appends
+    fn get(&self, fn_path: &str) -> Option<(&str, RateLimit)> {
+        self.0
+            .iter()
+            .filter(|(name, _)| {
+                fn_path
+                    .strip_prefix(name.as_str())
+                    .is_some_and(is_server_fn_hash)
+            })
+            .max_by_key(|(name, _)| name.len())
+            .map(|(name, limit)| (name.as_str(), *limit))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Bucket {
+    tokens: f64,
+    updated_at_ms: u64,
+}
+
+/// Take a token from the bucket at `key`
+/// returns the time until a token is available if the bucket is empty
+async fn take_token(
+    kv: &KVStoreImpl,
+    key: KvKey,
+    limit: RateLimit,
+) -> Result<Result<(), Duration>, KVError> {
+    for _ in 0..CAS_RETRIES {
+        let now = current_epoch().as_millis() as u64;
+        let current = kv.read(key.clone()).await?;
+        let mut bucket = match &current {
+            Some(raw) => serde_json::from_str(raw)?,
+            None => Bucket {
+                tokens: limit.capacity as f64,
+                updated_at_ms: now,
+            },
+        };
+        let elapsed_ms = now.saturating_sub(bucket.updated_at_ms) as f64;
+        bucket.tokens =
+            (bucket.tokens + elapsed_ms * limit.refill_per_ms()).min(limit.capacity as f64);
+        bucket.updated_at_ms = now;
+
+        if bucket.tokens < 1.0 {
+            let wait_ms = (1.0 - bucket.tokens) / limit.refill_per_ms();
+            return Ok(Err(Duration::from_millis(wait_ms.ceil() as u64)));
+        }
+        bucket.tokens -= 1.0;
+
+        // an untouched bucket is full again after `period`
+        let updated = serde_json::to_string(&bucket)?;
+        if kv
+            .compare_and_set(key.clone(), current, updated, Some(limit.period))
+            .await?
+        {
+            return Ok(Ok(()));
+        }
+    }
+    // heavily contended, let the request through rather than failing it
+    Ok(Ok(()))
+}
+
+/// IP of the client, from the header set by the proxy in front of the server if configured
+/// only the last entry of a list is used, earlier ones are set by the client or other proxies.
+/// Falls back to the peer address if the header is missing or isn't an IP
+fn client_ip(
+    headers: &HeaderMap,
+    trusted_header: Option<&HeaderName>,
+    peer: Option<SocketAddr>,
+) -> Option<IpAddr> {
+    trusted_header
+        .and_then(|name| headers.get_all(name).iter().last())
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.rsplit(',').next())
+        .and_then(|ip| ip.trim().parse().ok())
+        .or_else(|| peer.map(|addr| addr.ip()))
+}
+
+fn too_many_requests(retry_after: Duration) -> Response {
+    let secs = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, HeaderValue::from(secs))],
+        "Too many requests",
+    )
+        .into_response()
+}
+
+/// Middleware for `/api/*fn_name`
+pub async fn rate_limit(
+    State(state): State<AppState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let fn_path = req
+        .uri()
+        .path()
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let Some((name, limit)) = state.config.rate_limits.get(&fn_path) else {
+        return next.run(req).await;
+    };
+
+    let headers = req.headers();
+    let mut keys = vec![];
+    if let Some(ip) = client_ip(
+        headers,
+        state.config.client_ip_header.as_ref(),
+        connect_info.map(|ConnectInfo(addr)| addr),
+    ) {
+        keys.push(KvKey::new(
+            RecordKind::Other,
+            format!("rate-limit-{name}-ip-{ip}"),
+        ));
+    }
+    match state
+        .cookie_keys
+        .refresh_token_principal(headers, &state.kv)
+        .await
+    {
+        Ok(Some(principal)) => keys.push(KvKey::new(
+            RecordKind::Other,
+            format!("rate-limit-{name}-principal-{}", principal.to_text()),
+        )),
+        Ok(None) => (),
+        Err(e) => log::warn!("failed to verify refresh token for rate limiting {e}"),
+    }
+
+    for key in keys {
+        match take_token(&state.kv, key, limit).await {
+            Ok(Ok(())) => (),
+            Ok(Err(retry_after)) => return too_many_requests(retry_after),
+            // the KV store being down shouldn't take every server function with it
+            Err(e) => {
+                log::warn!("rate limiting failed {e}");
+                metrics()
+                    .rate_limit_failures
+                    .with_label_values(&[name])
+                    .inc();
+            }
+        }
+    }
+
+    next.run(req).await
+}
@@ -0,0 +1,107 @@
+use std::net::{IpAddr, SocketAddr};
+
+use http::{HeaderMap, HeaderName, HeaderValue};
+
+use super::{client_ip, RateLimits};
+use crate::metrics::server_fn_name;
+
+const PEER: &str = "10.0.0.1:443";
+
+fn ip(ip: &str) -> Option<IpAddr> {
+    Some(ip.parse().unwrap())
+}
+
+fn client_ip_from(name: &'static str, values: &[&'static str]) -> Option<IpAddr> {
+    let name = HeaderName::from_static(name);
+    let mut headers = HeaderMap::new();
+    for value in values {
+        headers.append(name.clone(), HeaderValue::from_static(value));
+    }
+    client_ip(
+        &headers,
+        Some(&name),
+        Some(PEER.parse::<SocketAddr>().unwrap()),
+    )
+}
+
+#[test]
+fn uses_the_trusted_header() {
+    assert_eq!(
+        client_ip_from("fly-client-ip", &["203.0.113.7"]),
+        ip("203.0.113.7")
+    );
+    assert_eq!(
+        client_ip_from("fly-client-ip", &[" 2001:db8::1 "]),
+        ip("2001:db8::1")
+    );
+}
+
+#[test]
+fn uses_the_last_hop_of_a_list() {
+    assert_eq!(
+        client_ip_from("x-forwarded-for", &["198.51.100.1, 203.0.113.7"]),
+        ip("203.0.113.7")
+    );
+    assert_eq!(
+        client_ip_from("x-forwarded-for", &["198.51.100.1", "203.0.113.7"]),
+        ip("203.0.113.7")
+    );
+}
+
+#[test]
+fn falls_back_to_the_peer() {
+    let peer = ip("10.0.0.1");
+    assert_eq!(client_ip_from("fly-client-ip", &[]), peer);
+    assert_eq!(client_ip_from("fly-client-ip", &["not an ip"]), peer);
+    assert_eq!(client_ip_from("x-forwarded-for", &["203.0.113.7, "]), peer);
+
+    let mut headers = HeaderMap::new();
+    headers.insert("fly-client-ip", HeaderValue::from_static("203.0.113.7"));
+    assert_eq!(
+        client_ip(&headers, None, Some(PEER.parse().unwrap())),
+        peer,
+        "the header is ignored unless trusted"
+    );
+    assert_eq!(client_ip(&headers, None, None), None);
+}
+
+#[test]
+fn matches_function_names_exactly() {
+    let limits = RateLimits::parse("renew_identity=1/60,renew_identity2=2/60").unwrap();
+    let capacity = |fn_path: &str| {
+        limits
+            .get(fn_path)
+            .map(|(name, limit)| (name, limit.capacity))
+    };
+
+    assert_eq!(
+        capacity("renew_identity14692843152436371052"),
+        Some(("renew_identity", 1))
+    );
+    assert_eq!(
+        capacity("renew_identity214692843152436371052"),
+        Some(("renew_identity2", 2))
+    );
+    assert_eq!(capacity("renew_identity"), None, "the hash is required");
+    assert_eq!(capacity("renew_identity_2"), None);
+    assert_eq!(
+        capacity("renew_identity099"),
+        None,
+        "hashes have no leading zeros"
+    );
+    assert_eq!(
+        capacity("renew_identity99999999999999999999"),
+        None,
+        "hashes fit a u64"
+    );
+}
+
+#[test]
+fn strips_the_server_fn_hash() {
+    assert_eq!(
+        server_fn_name("renew_identity14692843152436371052"),
+        "renew_identity"
+    );
+    assert_eq!(server_fn_name("get_post2985732124516493381"), "get_post2");
+    assert_eq!(server_fn_name("renew_identity"), "renew_identity");
+}
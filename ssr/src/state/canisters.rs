@@ -15,7 +15,7 @@ use crate::{
         user_index::UserIndex,
         PLATFORM_ORCHESTRATOR_ID, POST_CACHE_ID,
     },
-    consts::METADATA_API_BASE,
+    state::network::network,
     utils::{ic::AgentWrapper, profile::ProfileDetails, MockPartialEq},
 };
 
@@ -34,7 +34,7 @@ impl Default for Canisters<false> {
         Self {
             agent: AgentWrapper::build(|b| b),
             id: None,
-            metadata_client: MetadataClient::with_base_url(METADATA_API_BASE.clone()),
+            metadata_client: MetadataClient::with_base_url(network().metadata_api_base()),
             user_canister: Principal::anonymous(),
             expiry: 0,
             profile_details: None,
@@ -54,7 +54,7 @@ impl Canisters<true> {
 
         Canisters {
             agent: AgentWrapper::build(|b| b.with_arc_identity(id.clone())),
-            metadata_client: MetadataClient::with_base_url(METADATA_API_BASE.clone()),
+            metadata_client: MetadataClient::with_base_url(network().metadata_api_base()),
             id: Some(id),
             user_canister: Principal::anonymous(),
             expiry,
@@ -66,6 +66,15 @@ impl Canisters<true> {
         self.expiry
     }
 
+    /// Same canisters, acting with a renewed delegation of the same principal
+    pub fn renewed(&self, id: DelegatedIdentity) -> Canisters<true> {
+        Canisters {
+            user_canister: self.user_canister,
+            profile_details: self.profile_details.clone(),
+            ..Canisters::authenticated(id)
+        }
+    }
+
     pub fn identity(&self) -> &DelegatedIdentity {
         self.id
             .as_ref()
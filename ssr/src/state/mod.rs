@@ -5,14 +5,14 @@ pub mod canisters;
 pub mod content_seed_client;
 pub mod history;
 pub mod local_storage;
+pub mod network;
 
 #[cfg(feature = "ssr")]
 pub mod server {
-    use crate::auth::server_impl::store::KVStoreImpl;
+    use crate::auth::server_impl::{cookies::CookieKeys, store::KVStoreImpl};
 
     use super::canisters::Canisters;
     use axum::extract::FromRef;
-    use axum_extra::extract::cookie::Key;
     use leptos::LeptosOptions;
     use leptos_router::RouteListing;
     use tonic::transport::Channel;
@@ -27,9 +27,17 @@ pub mod server {
         pub cloudflare: gob_cloudflare::CloudflareAuth,
         pub kv: KVStoreImpl,
         pub routes: Vec<RouteListing>,
-        pub cookie_key: Key,
+        pub cookie_keys: CookieKeys,
+        pub identity_keyring: crate::auth::server_impl::secrets::IdentityKeyring,
+        pub config: std::sync::Arc<crate::init::config::AppConfig>,
         #[cfg(feature = "oauth-ssr")]
-        pub google_oauth: openidconnect::core::CoreClient,
+        pub oidc_providers: crate::auth::server_impl::oidc::OidcProviders,
+        #[cfg(feature = "mock-oidc-ssr")]
+        pub mock_oidc: crate::auth::server_impl::oidc::mock::MockOidcIssuer,
+        #[cfg(feature = "passkey-ssr")]
+        pub passkey_config: crate::auth::server_impl::passkey::PasskeyConfig,
+        #[cfg(feature = "email-ssr")]
+        pub email_login: crate::auth::server_impl::email::EmailLogin,
         #[cfg(feature = "ga4")]
         pub grpc_offchain_channel: Channel,
     }
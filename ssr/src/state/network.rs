@@ -0,0 +1,93 @@
+#[cfg(test)]
+mod tests;
+
+use once_cell::sync::OnceCell;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::consts::{network, AGENT_URL, METADATA_API_BASE};
+
+/// Name of the meta tag the server passes the network to the client with
+pub const NETWORK_META: &str = "ic-network";
+
+static NETWORK: OnceCell<NetworkConfig> = OnceCell::new();
+
+/// Internet Computer network and service endpoints the app talks to
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    /// Replica the agent sends calls to
+    pub agent_url: String,
+    pub metadata_api_base: String,
+    /// Fetch the root key from the replica instead of using mainnet's
+    /// never enable this against mainnet
+    pub fetch_root_key: bool,
+}
+
+impl Default for NetworkConfig {
+    /// Network of the build, local with "local-bin"/"local-lib" and mainnet otherwise
+    fn default() -> Self {
+        Self {
+            agent_url: AGENT_URL.to_string(),
+            metadata_api_base: METADATA_API_BASE.to_string(),
+            fetch_root_key: cfg!(any(feature = "local-bin", feature = "local-lib")),
+        }
+    }
+}
+
+impl NetworkConfig {
+    pub fn mainnet() -> Self {
+        Self {
+            agent_url: network::MAINNET_AGENT_URL.to_string(),
+            metadata_api_base: network::MAINNET_METADATA_API_BASE.to_string(),
+            fetch_root_key: false,
+        }
+    }
+
+    /// A replica started with `dfx start`
+    pub fn local() -> Self {
+        Self {
+            agent_url: network::LOCAL_AGENT_URL.to_string(),
+            metadata_api_base: network::LOCAL_METADATA_API_BASE.to_string(),
+            fetch_root_key: true,
+        }
+    }
+
+    pub fn metadata_api_base(&self) -> Url {
+        Url::parse(&self.metadata_api_base).expect("invalid metadata API base")
+    }
+}
+
+/// Network used by every agent and metadata client
+/// set once at startup, defaults to the network of the build
+pub fn network() -> &'static NetworkConfig {
+    NETWORK.get_or_init(NetworkConfig::default)
+}
+
+/// Select the network, must be called before the first call to [network]
+/// panics otherwise, agents may already be talking to the default network
+pub fn set_network(config: NetworkConfig) {
+    if let Err(config) = NETWORK.set(config) {
+        panic!(
+            "network was already selected as {}, can't switch to {}",
+            network().agent_url,
+            config.agent_url
+        );
+    }
+}
+
+/// Select the network the server rendered the page with
+#[cfg(feature = "hydrate")]
+pub fn init_network_from_document() {
+    let Some(content) = leptos::document()
+        .query_selector(&format!("meta[name={NETWORK_META}]"))
+        .ok()
+        .flatten()
+        .and_then(|meta| meta.get_attribute("content"))
+    else {
+        return;
+    };
+    match serde_json::from_str(&content) {
+        Ok(config) => set_network(config),
+        Err(e) => log::warn!("invalid network from server: {e}"),
+    }
+}
@@ -0,0 +1,20 @@
+use super::{network, set_network, NetworkConfig};
+
+// the network is global, a single test covers selecting it
+#[test]
+fn selected_network_is_used() {
+    let staging = NetworkConfig {
+        agent_url: "https://replica.staging.example".into(),
+        metadata_api_base: "https://metadata.staging.example".into(),
+        fetch_root_key: true,
+    };
+    assert_ne!(staging, NetworkConfig::default());
+
+    set_network(staging.clone());
+    assert_eq!(network(), &staging);
+
+    // switching later would leave agents on the previous network
+    let late = std::panic::catch_unwind(|| set_network(NetworkConfig::mainnet()));
+    assert!(late.is_err());
+    assert_eq!(network(), &staging);
+}
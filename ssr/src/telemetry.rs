@@ -0,0 +1,150 @@
+//! Structured logging, request ids and OpenTelemetry export
+//!
+//! Every request gets a span carrying its `x-request-id`.
+//! KV operations, canister calls and gRPC calls are recorded as child spans of the request,
+//! `log` records are forwarded to `tracing`.
+//!
+//! Configured from env before [AppConfig](crate::init::config::AppConfig) is loaded,
+//! so configuration errors are logged in the right format:
+//! - `LOG_FORMAT`: `json` for one JSON object per line, human readable otherwise
+//! - `RUST_LOG`: filter directives, defaults to `info`
+//! - `OTEL_EXPORTER_OTLP_ENDPOINT`: spans are exported to this OTLP (gRPC) collector if set
+//! - `OTEL_SERVICE_NAME`: defaults to `hot-or-not-web-leptos-ssr`
+
+use std::env;
+
+use http::{HeaderMap, Request};
+use leptos::use_context;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace as sdktrace, Resource};
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tracing::Span;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+const DEFAULT_SERVICE_NAME: &str = "hot-or-not-web-leptos-ssr";
+
+/// Span and id of the request being handled, provided through context
+///
+/// server rendering and server functions run outside the request's task,
+/// spans created there are parented to this one
+#[derive(Clone)]
+pub struct RequestTrace {
+    pub id: String,
+    pub span: Span,
+}
+
+impl RequestTrace {
+    /// The request's span must be the current one, e.g from within its handler
+    pub fn current(headers: &HeaderMap) -> Self {
+        Self {
+            id: request_id(headers).to_string(),
+            span: Span::current(),
+        }
+    }
+}
+
+fn request_id(headers: &HeaderMap) -> &str {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|id| id.to_str().ok())
+        .unwrap_or_default()
+}
+
+/// Span of an incoming request
+pub fn request_span<B>(req: &Request<B>) -> Span {
+    tracing::info_span!(
+        "request",
+        request_id = request_id(req.headers()),
+        method = %req.method(),
+        uri = %req.uri(),
+    )
+}
+
+/// Parent for spans of outgoing calls
+/// the current span if any, the span of the request in context otherwise
+pub fn parent_span() -> Span {
+    let current = Span::current();
+    if !current.is_none() {
+        return current;
+    }
+    use_context::<RequestTrace>()
+        .map(|trace| trace.span)
+        .unwrap_or(current)
+}
+
+/// Forward the id of the request in context to a gRPC service
+pub fn forward_request_id(metadata: &mut MetadataMap) {
+    let id = use_context::<RequestTrace>()
+        .map(|trace| trace.id)
+        .filter(|id| !id.is_empty())
+        .and_then(|id| MetadataValue::try_from(id).ok());
+    if let Some(id) = id {
+        metadata.insert(REQUEST_ID_HEADER, id);
+    }
+}
+
+/// Exports spans when dropped
+pub struct TelemetryGuard {
+    otel: bool,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if self.otel {
+            opentelemetry::global::shutdown_tracer_provider();
+        }
+    }
+}
+
+fn otel_tracer(endpoint: String) -> sdktrace::Tracer {
+    let service_name =
+        env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| DEFAULT_SERVICE_NAME.to_string());
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            sdktrace::config()
+                .with_resource(Resource::new([KeyValue::new("service.name", service_name)])),
+        )
+        .install_batch(runtime::Tokio)
+        .expect("Failed to initialize OTLP exporter")
+}
+
+/// Install the global subscriber, must be called from within the tokio runtime
+pub fn init_telemetry() -> TelemetryGuard {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let json = env::var("LOG_FORMAT").is_ok_and(|format| format == "json");
+    let fmt = if json {
+        tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed()
+    } else {
+        tracing_subscriber::fmt::layer().boxed()
+    };
+
+    let endpoint = env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .ok()
+        .filter(|endpoint| !endpoint.is_empty());
+    let otel =
+        endpoint.map(|endpoint| tracing_opentelemetry::layer().with_tracer(otel_tracer(endpoint)));
+    let guard = TelemetryGuard {
+        otel: otel.is_some(),
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt)
+        .with(otel)
+        .init();
+
+    guard
+}
@@ -558,12 +558,7 @@ impl LoginMethodSelected {
             send_event(
                 "login_method_selected",
                 &json!({
-                    "login_method": match prov {
-                        #[cfg(feature = "local-auth")]
-                        ProviderKind::LocalStorage => "local_storage",
-                        #[cfg(any(feature = "oauth-ssr", feature = "oauth-hydrate"))]
-                        ProviderKind::Google => "google",
-                    },
+                    "login_method": prov.as_str(),
                     "attempt_count": 1,
                 }),
             );
@@ -1,5 +1,3 @@
-use std::env;
-
 use gloo_utils::format::JsValueSerdeExt;
 use leptos::*;
 use serde_json::json;
@@ -67,17 +65,19 @@ pub fn send_event_warehouse(event_name: &str, params: &serde_json::Value) {
 #[cfg(feature = "ga4")]
 #[server]
 pub async fn stream_to_offchain_agent(event: String, params: String) -> Result<(), ServerFnError> {
+    use crate::init::config::AppConfig;
+    use crate::telemetry::{forward_request_id, parent_span};
+    use std::sync::Arc;
     use tonic::metadata::MetadataValue;
     use tonic::transport::Channel;
     use tonic::Request;
+    use tracing::Instrument;
 
     let channel: Channel = expect_context();
 
-    let mut off_chain_agent_grpc_auth_token = env::var("GRPC_AUTH_TOKEN").expect("GRPC_AUTH_TOKEN");
-    // removing whitespaces and new lines for proper parsing
-    off_chain_agent_grpc_auth_token.retain(|c| !c.is_whitespace());
+    let config: Arc<AppConfig> = expect_context();
 
-    let token: MetadataValue<_> = format!("Bearer {}", off_chain_agent_grpc_auth_token).parse()?;
+    let token: MetadataValue<_> = format!("Bearer {}", config.grpc_auth_token).parse()?;
 
     let mut client =
         warehouse_events::warehouse_events_client::WarehouseEventsClient::with_interceptor(
@@ -88,9 +88,13 @@ pub async fn stream_to_offchain_agent(event: String, params: String) -> Result<(
             },
         );
 
-    let request = tonic::Request::new(warehouse_events::WarehouseEvent { event, params });
+    let mut request = tonic::Request::new(warehouse_events::WarehouseEvent { event, params });
+    forward_request_id(request.metadata_mut());
 
-    client.send_event(request).await?;
+    client
+        .send_event(request)
+        .instrument(tracing::info_span!(parent: &parent_span(), "grpc", method = "send_event"))
+        .await?;
 
     Ok(())
 }
@@ -1,23 +1,169 @@
 use ic_agent::{agent::AgentBuilder, Agent, AgentError};
 
-use crate::consts::AGENT_URL;
+use crate::state::network::network;
 
 #[derive(Clone)]
 pub struct AgentWrapper(Agent);
 
 impl AgentWrapper {
     pub fn build(builder_func: impl FnOnce(AgentBuilder) -> AgentBuilder) -> Self {
-        let mut builder = Agent::builder().with_url(AGENT_URL);
+        #[cfg(feature = "ssr")]
+        let mut builder =
+            Agent::builder().with_transport(metered::MeteredTransport::new(&network().agent_url));
+        #[cfg(not(feature = "ssr"))]
+        let mut builder = Agent::builder().with_url(&network().agent_url);
         builder = builder_func(builder);
         Self(builder.build().unwrap())
     }
 
     pub async fn get_agent(&self) -> Result<&Agent, AgentError> {
         let agent = &self.0;
-        #[cfg(any(feature = "local-bin", feature = "local-lib"))]
-        {
+        if network().fetch_root_key {
             agent.fetch_root_key().await?;
         }
         Ok(agent)
     }
 }
+
+#[cfg(feature = "ssr")]
+mod metered {
+    use std::{future::Future, pin::Pin};
+
+    use candid::Principal;
+    use ic_agent::{
+        agent::{http_transport::ReqwestTransport, Transport},
+        AgentError,
+    };
+    use serde_cbor::Value;
+    use tracing::Instrument;
+
+    use crate::{metrics::count_ic_call, telemetry::parent_span};
+
+    type IcFuture<'a, V> = Pin<Box<dyn Future<Output = Result<V, AgentError>> + Send + 'a>>;
+
+    /// Counts the requests sent to the replica, split by replies, rejects and errors
+    /// each request is traced as a child of the caller's span
+    pub struct MeteredTransport(ReqwestTransport);
+
+    fn traced<'a, V>(
+        kind: &'static str,
+        canister: Option<Principal>,
+        fut: IcFuture<'a, V>,
+        is_reject: fn(&V) -> bool,
+    ) -> IcFuture<'a, V> {
+        let span = tracing::info_span!(
+            parent: &parent_span(),
+            "ic_agent",
+            kind,
+            canister = %canister.map(|id| id.to_text()).unwrap_or_default(),
+        );
+        Box::pin(count_ic_call(kind, fut, is_reject).instrument(span))
+    }
+
+    fn field<'a>(map: &'a Value, name: &str) -> Option<&'a Value> {
+        let Value::Map(map) = map else {
+            return None;
+        };
+        map.get(&Value::Text(name.into()))
+    }
+
+    /// The query response has a `rejected` status
+    fn is_query_reject(body: &[u8]) -> bool {
+        serde_cbor::from_slice::<Value>(body)
+            .is_ok_and(|res| field(&res, "status") == Some(&Value::Text("rejected".into())))
+    }
+
+    /// The certificate of a read_state response holds a `rejected` request status
+    fn is_read_state_reject(body: &[u8]) -> bool {
+        let Ok(res) = serde_cbor::from_slice::<Value>(body) else {
+            return false;
+        };
+        let Some(Value::Bytes(certificate)) = field(&res, "certificate") else {
+            return false;
+        };
+        let Ok(certificate) = serde_cbor::from_slice::<Value>(certificate) else {
+            return false;
+        };
+        field(&certificate, "tree").is_some_and(|tree| has_rejected_status(tree, 0))
+    }
+
+    /// Walk a hash tree for a `request_status/<id>/status` leaf holding `rejected`
+    /// `depth` is the number of labels of that path above `tree`
+    fn has_rejected_status(tree: &Value, depth: usize) -> bool {
+        let Value::Array(node) = tree else {
+            return false;
+        };
+        match node.as_slice() {
+            [Value::Integer(1), left, right] => {
+                has_rejected_status(left, depth) || has_rejected_status(right, depth)
+            }
+            [Value::Integer(2), Value::Bytes(label), sub] => {
+                let matches = match depth {
+                    0 => label == b"request_status",
+                    1 => true,
+                    2 => label == b"status",
+                    _ => false,
+                };
+                matches && has_rejected_status(sub, depth + 1)
+            }
+            [Value::Integer(3), Value::Bytes(leaf)] => depth == 3 && leaf == b"rejected",
+            _ => false,
+        }
+    }
+
+    fn never_reject<V>(_: &V) -> bool {
+        false
+    }
+
+    impl MeteredTransport {
+        pub fn new(url: &str) -> Self {
+            Self(ReqwestTransport::create(url).expect("invalid agent url"))
+        }
+    }
+
+    impl Transport for MeteredTransport {
+        fn call(&self, effective_canister_id: Principal, envelope: Vec<u8>) -> IcFuture<()> {
+            traced(
+                "call",
+                Some(effective_canister_id),
+                self.0.call(effective_canister_id, envelope),
+                never_reject,
+            )
+        }
+
+        fn read_state(
+            &self,
+            effective_canister_id: Principal,
+            envelope: Vec<u8>,
+        ) -> IcFuture<Vec<u8>> {
+            traced(
+                "read_state",
+                Some(effective_canister_id),
+                self.0.read_state(effective_canister_id, envelope),
+                |body| is_read_state_reject(body),
+            )
+        }
+
+        fn read_subnet_state(&self, subnet_id: Principal, envelope: Vec<u8>) -> IcFuture<Vec<u8>> {
+            traced(
+                "read_subnet_state",
+                None,
+                self.0.read_subnet_state(subnet_id, envelope),
+                never_reject,
+            )
+        }
+
+        fn query(&self, effective_canister_id: Principal, envelope: Vec<u8>) -> IcFuture<Vec<u8>> {
+            traced(
+                "query",
+                Some(effective_canister_id),
+                self.0.query(effective_canister_id, envelope),
+                |body| is_query_reject(body),
+            )
+        }
+
+        fn status(&self) -> IcFuture<Vec<u8>> {
+            traced("status", None, self.0.status(), never_reject)
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::{env, fmt::Display};
+use std::fmt::Display;
 
 use leptos::{expect_context, server, ServerFnError};
 
@@ -33,18 +33,20 @@ pub async fn send_report_offchain(
     reason: String,
     video_url: String,
 ) -> Result<(), ServerFnError> {
+    use crate::init::config::AppConfig;
+    use crate::telemetry::{forward_request_id, parent_span};
     use crate::utils::off_chain;
+    use std::sync::Arc;
     use tonic::metadata::MetadataValue;
     use tonic::transport::Channel;
     use tonic::Request;
+    use tracing::Instrument;
 
     let channel: Channel = expect_context();
 
-    let mut off_chain_agent_grpc_auth_token = env::var("GRPC_AUTH_TOKEN").expect("GRPC_AUTH_TOKEN");
-    // removing whitespaces and new lines for proper parsing
-    off_chain_agent_grpc_auth_token.retain(|c| !c.is_whitespace());
+    let config: Arc<AppConfig> = expect_context();
 
-    let token: MetadataValue<_> = format!("Bearer {}", off_chain_agent_grpc_auth_token).parse()?;
+    let token: MetadataValue<_> = format!("Bearer {}", config.grpc_auth_token).parse()?;
 
     let mut client = off_chain::off_chain_client::OffChainClient::with_interceptor(
         channel,
@@ -54,7 +56,7 @@ pub async fn send_report_offchain(
         },
     );
 
-    let request = tonic::Request::new(off_chain::ReportPostRequest {
+    let mut request = tonic::Request::new(off_chain::ReportPostRequest {
         reporter_id,
         publisher_id,
         publisher_canister_id,
@@ -64,7 +66,12 @@ pub async fn send_report_offchain(
         video_url,
     });
 
-    client.report_post(request).await?;
+    forward_request_id(request.metadata_mut());
+
+    client
+        .report_post(request)
+        .instrument(tracing::info_span!(parent: &parent_span(), "grpc", method = "report_post"))
+        .await?;
 
     Ok(())
 }